@@ -1,14 +1,15 @@
-use std::path::PathBuf;
+use std::path::{Path, PathBuf};
 use std::error::Error;
 use std::fs::File;
-use std::os::unix::fs::FileExt;
+use iobase::source::Source;
 use std::sync::Arc;
 use db::{DB, RegisterSuccess};
 use zisraw::ZisrawInterface;
 use zisraw::utils::XmlUtil;
+use zisraw::transcode::RecompressOptions;
 use prettytable::{row, Table};
 
-pub fn register(database: &DB, fname: &PathBuf) -> Result<(), Box<dyn Error>> {
+pub fn register(database: &DB, fname: &Path) -> Result<(), Box<dyn Error>> {
 	let fname = fname.canonicalize()?;
 	match database.register_file(fname.as_path())? {
 		RegisterSuccess::Inserted => println!("{} is now registered", fname.to_string_lossy()),
@@ -43,11 +44,11 @@ pub fn query(database: DB, where_clause: Option<String>, json:bool) -> Result<()
 }
 
 pub fn dump(name:PathBuf, xmlfile:Option<PathBuf>) -> Result<(), Box<dyn Error>> {
-	let file:Arc<dyn FileExt> = Arc::new(File::open(name)?);
+	let file:Arc<dyn Source> = Arc::new(File::open(name)?);
 	let hd = zisraw::get_file_header(&file)?;
 	println!("{hd:#?}");
 
-	let mut metadata = hd.get_metadata(&file)?;
+	let metadata = hd.get_metadata(&file)?;
 
 	if let Some(xmlfile) = &xmlfile{
 		println!("writing {} bytes xml data to {}",metadata.cache.source.len(),xmlfile.to_string_lossy());
@@ -78,3 +79,37 @@ pub fn dump(name:PathBuf, xmlfile:Option<PathBuf>) -> Result<(), Box<dyn Error>>
 
 	Ok(())
 }
+
+pub fn recompress(source:&Path, target:&Path, options:&RecompressOptions) -> Result<(), Box<dyn Error>> {
+	let report = zisraw::transcode::recompress(source, target, options)?;
+	println!(
+		"recompressed {} subblocks from {} to {} bytes, copied {} subblocks",
+		report.recompressed, report.bytes_before, report.bytes_after, report.copied
+	);
+	if options.verify {
+		println!("pixel data of {} matches {}", target.to_string_lossy(), source.to_string_lossy());
+	}
+	Ok(())
+}
+
+pub fn attach(file:&Path, data:&Path, name:&str, content_file_type:&str) -> Result<(), Box<dyn Error>> {
+	let data = std::fs::read(data)?;
+	let entry = zisraw::writer::put_attachment(file, name, content_file_type, &data)?;
+	println!("attached {} bytes as \"{}\" at position {}", data.len(), entry.Name, entry.FilePosition);
+	Ok(())
+}
+
+pub fn split(source:&Path, target:&Path, parts:usize) -> Result<(), Box<dyn Error>> {
+	let targets = zisraw::parts::part_file_names(target, parts);
+	zisraw::parts::split(source, &targets)?;
+	for t in targets {
+		println!("written {}", t.to_string_lossy());
+	}
+	Ok(())
+}
+
+pub fn merge(parts:&[PathBuf], target:&Path) -> Result<(), Box<dyn Error>> {
+	zisraw::parts::merge(parts, target)?;
+	println!("merged {} parts into {}", parts.len(), target.to_string_lossy());
+	Ok(())
+}
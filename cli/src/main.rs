@@ -1,7 +1,8 @@
 use std::path::PathBuf;
 use argh::FromArgs;
 use db::DB;
-use db::Error::Own;
+use db::Error::NotFound;
+use zisraw::transcode::RecompressOptions;
 
 #[derive(FromArgs, PartialEq, Debug)]
 #[argh(description = "sqlite backed registry for czi files")]
@@ -19,7 +20,11 @@ struct Cli {
 enum Commands {
 	Register(Register),
 	Query(Query),
-	Dump(Dump)
+	Dump(Dump),
+	Recompress(Recompress),
+	Attach(Attach),
+	Split(Split),
+	Merge(Merge)
 }
 
 #[derive(FromArgs, PartialEq, Debug)]
@@ -55,6 +60,77 @@ struct Dump {
 	xmlfile:Option<PathBuf>
 }
 
+#[derive(FromArgs, PartialEq, Debug)]
+/// losslessly recompress a czi file with zstd
+#[argh(subcommand, name = "recompress")]
+struct Recompress {
+	#[argh(positional)]
+	/// czi file to read
+	source:PathBuf,
+	#[argh(positional)]
+	/// czi file to write
+	target:PathBuf,
+	/// zstd compression level
+	#[argh(option, short='l', default = "zstd_default_level()")]
+	level:i32,
+	/// use hi/lo byte packing for 16bit pixel types
+	#[argh(switch)]
+	hilo:bool,
+	/// also recompress lzw compressed subblocks
+	#[argh(switch)]
+	lzw:bool,
+	/// skip comparing the pixel data of the written file against the source
+	#[argh(switch)]
+	no_verify:bool
+}
+
+#[derive(FromArgs, PartialEq, Debug)]
+/// add or replace a named attachment (e.g. a thumbnail) in a czi file
+#[argh(subcommand, name = "attach")]
+struct Attach {
+	#[argh(positional)]
+	/// czi file to modify
+	file:PathBuf,
+	#[argh(positional)]
+	/// file containing the data to attach
+	data:PathBuf,
+	/// name of the attachment
+	#[argh(option, short='n', default = "String::from(\"Thumbnail\")")]
+	name:String,
+	/// content file type of the attachment (at most 8 characters)
+	#[argh(option, short='t', default = "String::from(\"JPG\")")]
+	content_type:String
+}
+
+#[derive(FromArgs, PartialEq, Debug)]
+/// split a czi file into multiple parts
+#[argh(subcommand, name = "split")]
+struct Split {
+	#[argh(positional)]
+	/// czi file to split
+	source:PathBuf,
+	#[argh(positional)]
+	/// name of the first part, the other parts will be named "name(1).czi", "name(2).czi", ...
+	target:PathBuf,
+	/// number of parts
+	#[argh(option, short='n')]
+	parts:usize
+}
+
+#[derive(FromArgs, PartialEq, Debug)]
+/// merge the parts of a multi part czi file into one file
+#[argh(subcommand, name = "merge")]
+struct Merge {
+	#[argh(positional)]
+	/// czi file to write
+	target:PathBuf,
+	#[argh(positional)]
+	/// all parts of the image in any order
+	parts:Vec<PathBuf>
+}
+
+fn zstd_default_level() -> i32 {RecompressOptions::default().level}
+
 fn main() -> Result<(), Box<dyn std::error::Error>> {
 
 	let cli: Cli = argh::from_env();
@@ -71,15 +147,22 @@ fn main() -> Result<(), Box<dyn std::error::Error>> {
 			let fname = match uuid::Uuid::parse_str(d.file.as_str()).ok(){
 				None => d.file.into(),
 				Some(uuid) => database
-					.get_image(uuid)?.ok_or(Own(format!("Image with guid \"{uuid}\" not found in \"{}\"",cli.dbfile.to_string_lossy())))?
+					.get_image(uuid)?.ok_or(NotFound(format!("Image with guid \"{uuid}\" in \"{}\"",cli.dbfile.to_string_lossy())))?
 					.filenames.iter()
-					.find_map(|f|f.exists().then_some(f))
-					.ok_or(Own(format!("None of the files registered with guid \"{uuid}\" could be found or accessed")))?
+					.find(|f|f.exists())
+					.ok_or(NotFound(format!("Accessible file for image \"{uuid}\"")))?
 					.clone()
 			};
 
 			cli::dump(fname, d.xmlfile )?
 		}
+		Commands::Recompress(r) => {
+			let options = RecompressOptions{level:r.level, hilo_packing:r.hilo, lzw:r.lzw, verify:!r.no_verify};
+			cli::recompress(&r.source, &r.target, &options)?
+		}
+		Commands::Attach(a) => cli::attach(&a.file, &a.data, &a.name, &a.content_type)?,
+		Commands::Split(s) => cli::split(&s.source, &s.target, s.parts)?,
+		Commands::Merge(m) => cli::merge(&m.parts, &m.target)?
 	}
 	Ok(())
 }
@@ -0,0 +1,59 @@
+use fixture::{open, temp_path, Fixture};
+use db::DB;
+use zisraw::transcode::RecompressOptions;
+use zisraw::ZisrawInterface;
+
+#[test]
+fn register_and_query() {
+	let dbfile = temp_path!("cli.db");
+	std::fs::remove_file(&dbfile).ok();
+	let database = DB::new(&dbfile).unwrap();
+	let path = temp_path!("cli_register.czi");
+	Fixture::new(16, 16).write(&path).unwrap();
+
+	cli::register(&database, &path).unwrap();
+	cli::register(&database, &path).unwrap();
+	assert_eq!(database.query_images(None).unwrap().len(), 1);
+	cli::query(database, None, true).unwrap();
+}
+
+#[test]
+fn dump() {
+	let path = temp_path!("cli_dump.czi");
+	let xml = temp_path!("cli_dump.xml");
+	let fixture = Fixture::new(16, 16);
+	fixture.write(&path).unwrap();
+
+	cli::dump(path, Some(xml.clone())).unwrap();
+	assert_eq!(std::fs::read_to_string(xml).unwrap(), fixture.metadata_xml());
+}
+
+#[test]
+fn dump_without_image_name_fails() {
+	let path = temp_path!("cli_dump_broken.czi");
+	Fixture::new(16, 16)
+		.metadata("<ImageDocument><Metadata><Information><Image><AcquisitionDateAndTime>2022-09-01T12:00:00</AcquisitionDateAndTime></Image></Information><Experiment/></Metadata></ImageDocument>")
+		.write(&path).unwrap();
+
+	assert!(cli::dump(path, None).is_err());
+}
+
+#[test]
+fn recompress_attach_split_merge() {
+	let source = temp_path!("cli_source.czi");
+	let recompressed = temp_path!("cli_recompressed.czi");
+	let thumbnail = temp_path!("cli_thumbnail.jpg");
+	let merged = temp_path!("cli_merged.czi");
+	Fixture::new(64, 64).tiles(32, 32, 0).write(&source).unwrap();
+	std::fs::write(&thumbnail, b"thumb").unwrap();
+
+	cli::recompress(&source, &recompressed, &RecompressOptions::default()).unwrap();
+	cli::attach(&recompressed, &thumbnail, "Thumbnail", "JPG").unwrap();
+	cli::split(&recompressed, &temp_path!("cli_split.czi"), 2).unwrap();
+	cli::merge(&zisraw::parts::part_file_names(&temp_path!("cli_split.czi"), 2), &merged).unwrap();
+	let recompressed = open(&recompressed);
+	let merged = open(&merged);
+	zisraw::transcode::verify_pixels(&[recompressed], std::slice::from_ref(&merged)).unwrap();
+	let thumbnail = zisraw::get_file_header(&merged).unwrap().get_thumbnail(&merged).unwrap().unwrap();
+	assert_eq!(thumbnail.Data.get().unwrap(), b"thumb");
+}
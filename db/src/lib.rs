@@ -3,20 +3,20 @@ mod error;
 use std::borrow::Borrow;
 use chrono::{DateTime, Local, TimeZone};
 use std::fs::File;
-use std::os::unix::fs::FileExt;
+use iobase::source::Source;
 use std::path::{Path,PathBuf};
 use std::sync::Arc;
 use rusqlite::Connection;
 use rusqlite::types::FromSql;
 use uuid::Uuid;
-use uuid;
 use zisraw::utils::XmlUtil;
 use zisraw::ZisrawInterface;
 use serde::{Deserialize, Serialize};
 pub use error::Error;
+use error::SqlResult;
 pub use iobase::Result;
 
-const IMAGE_TABLE_CREATE: &'static str =
+const IMAGE_TABLE_CREATE: &str =
 	r#"create table if not exists images (
 		guid CHAR(36) primary key,
 		parent_guid CHAR(36),
@@ -28,7 +28,7 @@ const IMAGE_TABLE_CREATE: &'static str =
 		thumbnail blob
 	)"#;
 
-const FILE_TABLE_CREATE: &'static str =
+const FILE_TABLE_CREATE: &str =
 	r#"create table if not exists files (
 		filename TEXT NOT NULL PRIMARY KEY,
 		image_id CHAR(36) NOT NULL,
@@ -58,19 +58,11 @@ pub struct ImageInfo{
 
 fn guid_from_string(s:String)->Result<Uuid>{
 	Uuid::parse_str(s.as_str())
-		.or_else(|e|Err(Error::Because((e.into(),format!("Failed to parse {s} as uuid)"))).into()))
+		.map_err(|e|Error::InvalidData(format!("Failed to parse {s} as uuid ({e})")))
 }
 
 fn guid_from_maybe_string(s:Option<String>)->Result<Option<Uuid>>{
-	s.clone()
-		.map(|x:String|Uuid::parse_str(x.as_str()))
-		.transpose()
-		.or_else(
-			|e|Err(Error::Because((
-				e.into(),
-				format!("Failed to parse {} as uuid)",s.unwrap())
-			)).into())
-		)
+	s.map(guid_from_string).transpose()
 }
 
 impl DB {
@@ -78,25 +70,26 @@ impl DB {
 		self.conn.prepare("SELECT guid FROM images WHERE guid=?")?
 			.exists([guid.to_string()])
 	}
-	fn has_file(&self, filename:&Path) -> rusqlite::Result<bool>{
+	fn has_file(&self, filename:&str) -> rusqlite::Result<bool>{
 		self.conn.prepare("SELECT filename FROM files WHERE filename=?")?
-			.exists([filename.to_str().unwrap()])
+			.exists([filename])
 	}
-	fn register_image(&self, hd:&zisraw::structs::FileHeader, file:&Arc<dyn FileExt>) -> Result<RegisterSuccess>{
-		if !self.has_image(&hd.FileGuid)?
+	fn register_image(&self, hd:&zisraw::structs::FileHeader, file:&Arc<dyn Source>) -> Result<RegisterSuccess>{
+		if !self.has_image(&hd.FileGuid).sql()?
 		{ // image is not yet known, register it
-			let mut metadata = hd.get_metadata(file)?;
+			let metadata = hd.get_metadata(file)?;
 			let metadata_tree = metadata.as_tree()?;
 
 			let org_filename = metadata_tree
 				.drill_down(["Experiment", "ImageName"].borrow())?
-				.get_text().unwrap();
+				.get_text()
+				.ok_or(Error::Xml("Experiment/ImageName has no text".to_string()))?;
 			let primary_file_guid = if hd.PrimaryFileGuid == hd.FileGuid { None } else { Some(hd.PrimaryFileGuid.to_string()) };
 
-			let mut thumbnail = hd.get_thumbnail(file)?;
+			let thumbnail = hd.get_thumbnail(file)?;
 			let thumbnail_type = thumbnail.as_ref().map(|t|t.Entry.ContentFileType.clone());
-			let thumbnail_data= match thumbnail.as_mut() {
-				Some(a) => Some(a.Data.get()?),
+			let thumbnail_data= match thumbnail.as_ref() {
+				Some(a) => Some(a.Data.get()?.to_vec()),
 				_ => None
 			};
 
@@ -111,10 +104,10 @@ impl DB {
 					hd.FilePart,
 					hd.get_timestamp(file)?.timestamp(), // decode with SELECT datetime(acquisition_timestamp, 'unixepoch')
 					org_filename,
-					metadata.cache.source,
+					&metadata.cache.source,
 					thumbnail_type, thumbnail_data
 				)
-			)?;
+			).sql()?;
 			Ok(RegisterSuccess::Inserted)
 		} else {//image is already registered but filename is new
 			let existing = self.lookup_filenames(&hd.FileGuid)?;
@@ -127,8 +120,8 @@ impl DB {
 			None => format!("SELECT {column} FROM images"),
 			Some(c) => format!("SELECT {column} FROM images WHERE {}",c)
 		};
-		let mut stmt= self.conn.prepare(query.as_str())?;
-		let rows = stmt.query_map([],|r| {r.get::<usize,T>(0)})?;
+		let mut stmt= self.conn.prepare(query.as_str()).sql()?;
+		let rows = stmt.query_map([],|r| {r.get::<usize,T>(0)}).sql()?;
 		Ok(rows.filter_map(|r|r.ok()).collect())
 	}
 	pub fn query_images<O:Into<Option<String>>>(&self,where_clause:O) -> Result<Vec<ImageInfo>>{
@@ -137,19 +130,19 @@ impl DB {
 			None => "SELECT guid, parent_guid, file_part, timestamp, original_path FROM images".to_string(),
 			Some(c) => format!("SELECT guid, parent_guid, file_part, timestamp, original_path FROM images WHERE {}",c)
 		};
-		let mut stmt= self.conn.prepare(query.as_str())?;
+		let mut stmt= self.conn.prepare(query.as_str()).sql()?;
 		// todo implement proper error handling (right now we simply ignore rows that raised errors
 		let rows = stmt.query_map([],|r| {
 			let guid = guid_from_string(r.get(0)?).unwrap_or_default();
 			Ok(ImageInfo {
-				timestamp: Local.timestamp(r.get(3)?,0),
+				timestamp: Local.timestamp_opt(r.get(3)?,0).earliest().unwrap_or_default(),
 				guid,
 				parent_guid: guid_from_maybe_string(r.get(1)?).unwrap_or_default(),
-				orig_path: r.get(4).and_then(|v: String| Ok(PathBuf::from(v)))?,
+				orig_path: r.get(4).map(|v: String| PathBuf::from(v))?,
 				file_part: r.get(2)?,
-				filenames: self.lookup_filenames(&guid)?
+				filenames: self.filenames(&guid)?
 			})
-		})?;
+		}).sql()?;
 		Ok(rows.filter_map(|r|r.ok()).collect())
 	}
 	pub fn get_image(&self,id:Uuid)-> Result<Option<ImageInfo>>{
@@ -164,25 +157,30 @@ impl DB {
 		let found = self.query_from_images("meta_data", format!("guid = \"{id}\""));
 		found.map(|mut v|v.pop())
 	}
-	pub fn new(filename:&PathBuf) -> rusqlite::Result<Self> {
+	pub fn new(filename:&PathBuf) -> Result<Self> {
 		let slf=Self{
-			conn: Connection::open(filename)?
+			conn: Connection::open(filename).sql()?
 		};
-		slf.conn.execute(IMAGE_TABLE_CREATE, [])?;
-		slf.conn.execute(FILE_TABLE_CREATE, [])?;
+		slf.conn.execute(IMAGE_TABLE_CREATE, []).sql()?;
+		slf.conn.execute(FILE_TABLE_CREATE, []).sql()?;
 		Ok(slf)
 	}
-	pub fn lookup_filenames(&self,guid:&Uuid) -> rusqlite::Result<Vec<PathBuf>>{
+	pub fn lookup_filenames(&self,guid:&Uuid) -> Result<Vec<PathBuf>>{
+		self.filenames(guid).sql()
+	}
+	fn filenames(&self,guid:&Uuid) -> rusqlite::Result<Vec<PathBuf>>{
 		self.conn.prepare("SELECT filename FROM files WHERE image_id = ?")?
 			.query_map([guid.to_string()],|row|
-				row.get(0).and_then(|v: String| Ok(PathBuf::from(v)))
+				row.get(0).map(|v: String| PathBuf::from(v))
 			)?.collect()
 	}
 	pub fn register_file(&self, filename:&Path) -> Result<RegisterSuccess>{
-		if self.has_file(&filename)?{
+		let name = filename.to_str()
+			.ok_or(Error::InvalidData(format!("{} is not a valid unicode filename",filename.to_string_lossy())))?;
+		if self.has_file(name).sql()?{
 			return Ok(RegisterSuccess::FileExists);//file is already registered
 		}
-		let file:Arc<dyn FileExt> = Arc::new(File::open(filename.clone())?);
+		let file:Arc<dyn Source> = Arc::new(File::open(filename)?);
 		let hd = zisraw::get_file_header(&file)?;
 
 		let result = self.register_image(&hd,&file)?;
@@ -190,8 +188,8 @@ impl DB {
 		// register filename regardless if image was new and return either result of registration or error
 		self.conn.execute(
 			"INSERT INTO files (filename, image_id) values (?1, ?2)",
-			(filename.to_str().unwrap(), hd.FileGuid.to_string())
-		)?;
+			(name, hd.FileGuid.to_string())
+		).sql()?;
 		Ok(result)
 	}
 }
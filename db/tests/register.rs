@@ -0,0 +1,64 @@
+use std::path::PathBuf;
+use fixture::{temp_path, Fixture};
+use uuid::Uuid;
+use db::{DB, RegisterSuccess};
+
+fn fresh_db(name:&str) -> DB {
+	let path = temp_path!(name);
+	std::fs::remove_file(&path).ok();
+	DB::new(&path).unwrap()
+}
+
+#[test]
+fn register_and_query() {
+	let db = fresh_db("register_and_query.db");
+	let guid = Uuid::from_u128(1);
+	let path = temp_path!("register_and_query.czi");
+	Fixture::new(16, 16).guid(guid).thumbnail(b"thumb").write(&path).unwrap();
+
+	assert!(matches!(db.register_file(&path).unwrap(), RegisterSuccess::Inserted));
+	assert!(matches!(db.register_file(&path).unwrap(), RegisterSuccess::FileExists));
+
+	let images = db.query_images(None).unwrap();
+	assert_eq!(images.len(), 1);
+	let image = db.get_image(guid).unwrap().unwrap();
+	assert_eq!(image.guid, guid);
+	assert_eq!(image.parent_guid, None);
+	assert_eq!(image.orig_path, PathBuf::from("fixture.czi"));
+	assert_eq!(image.filenames, [path]);
+
+	assert_eq!(db.get_image_thumbnail(guid).unwrap(), Some(b"thumb".to_vec()));
+	assert!(db.get_image_xml(guid).unwrap().unwrap().contains("<ImageName>fixture.czi</ImageName>"));
+	assert!(db.get_image(Uuid::from_u128(2)).unwrap().is_none());
+}
+
+#[test]
+fn copies_of_an_image() {
+	let db = fresh_db("copies_of_an_image.db");
+	let fixture = Fixture::new(16, 16).guid(Uuid::from_u128(3));
+	let (first, second) = (temp_path!("copy_1.czi"), temp_path!("copy_2.czi"));
+	fixture.write(&first).unwrap();
+	fixture.write(&second).unwrap();
+
+	db.register_file(&first).unwrap();
+	match db.register_file(&second).unwrap() {
+		RegisterSuccess::ImageExists(known) => assert_eq!(known, [first]),
+		_ => panic!("second copy should be recognized")
+	}
+	assert_eq!(db.lookup_filenames(&fixture.guid).unwrap().len(), 2);
+	// no thumbnail attachment
+	assert_eq!(db.get_image_thumbnail(fixture.guid).unwrap(), None);
+}
+
+#[test]
+fn multi_part_images_know_their_parent() {
+	let db = fresh_db("multi_part.db");
+	let source = temp_path!("multi_part_source.czi");
+	Fixture::new(32, 32).tiles(16, 16, 0).guid(Uuid::from_u128(4)).write(&source).unwrap();
+	let parts = zisraw::parts::part_file_names(&temp_path!("multi_part.czi"), 2);
+	zisraw::parts::split(&source, &parts).unwrap();
+
+	db.register_file(&parts[0]).unwrap();
+	let image = db.get_image(Uuid::from_u128(4)).unwrap().unwrap();
+	assert_eq!((image.parent_guid, image.file_part), (None, 0));
+}
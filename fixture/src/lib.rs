@@ -0,0 +1,286 @@
+//! Synthetic zisraw files for tests.
+//!
+//! Pixel values are a deterministic function of their position (see [Fixture::value]),
+//! so tests can check any pixel they read without keeping the written data around.
+#![allow(non_snake_case)]
+
+use std::collections::HashMap;
+use std::fs::File;
+use std::path::Path;
+use std::sync::Arc;
+use iobase::source::Source;
+use uuid::Uuid;
+use iobase::Result;
+use zisraw::compression::{encode_zstd, Compression};
+use zisraw::structs::*;
+use zisraw::writer::FileWriter;
+
+/// Path of a file in the temporary directory of the calling test target (`CARGO_TARGET_TMPDIR`).
+#[macro_export]
+macro_rules! temp_path {
+	($name:expr) => {std::path::Path::new(env!("CARGO_TARGET_TMPDIR")).join($name)}
+}
+
+/// Open a file as source, panicking if that fails.
+pub fn open(path:&Path) -> Arc<dyn Source>{
+	Arc::new(File::open(path).unwrap())
+}
+
+/// Open the parts of an image as sources, panicking if that fails.
+pub fn open_parts(paths:&[&Path]) -> Arc<[Arc<dyn Source>]>{
+	paths.iter().map(|p|open(p)).collect()
+}
+
+/// Description of a synthetic image, written by [Fixture::write].
+#[derive(Debug,Clone)]
+pub struct Fixture{
+	/// size of each scene in full resolution pixels
+	pub size:(u32,u32),
+	/// size of the mosaic tiles, None results in one subblock per plane and scene
+	pub tile:Option<(u32,u32)>,
+	/// overlap of neighbouring mosaic tiles in pixels
+	pub overlap:u32,
+	pub channels:u32,
+	pub z:u32,
+	pub t:u32,
+	pub scenes:u32,
+	pub pixel_type:PixelType,
+	/// one of Uncompressed, Zstd0 or Zstd1 (which uses hi/lo packing)
+	pub compression:Compression,
+	/// number of pyramid levels above full resolution and the minification factor between them
+	pub pyramid:Option<(u32,u32)>,
+	/// replaces the generated metadata xml if set
+	pub metadata:Option<String>,
+	/// attachments as (name, content file type, data)
+	pub attachments:Vec<(String,String,Vec<u8>)>,
+	pub guid:Uuid,
+	/// pixel size in meter
+	pub scaling:f64,
+	/// stage position (in micrometer) of pixel 0/0, written as StageXPosition/StageYPosition of each subblock
+	pub stage:Option<(f64,f64)>,
+	/// replaces the generated metadata xml of every subblock if set
+	pub subblock_metadata:Option<String>,
+	/// further dimensions (e.g. H or R) as (name, start) written on every subblock
+	pub dimensions:Vec<(String,i32)>
+}
+
+impl Fixture {
+	/// A single plane Gray8 image of the given size, everything else can be set by the builder functions.
+	pub fn new(width:u32, height:u32) -> Self{
+		Fixture{
+			size:(width,height), tile:None, overlap:0,
+			channels:1, z:1, t:1, scenes:1,
+			pixel_type:PixelType::Gray8,
+			compression:Compression::Uncompressed,
+			pyramid:None, metadata:None, attachments:vec![],
+			guid:Uuid::from_u128(0x2153_7e8a_4b1d_4c3e_9f00_0000_0000_0001),
+			scaling:1e-6, stage:None, subblock_metadata:None, dimensions:vec![]
+		}
+	}
+	pub fn tiles(self, width:u32, height:u32, overlap:u32) -> Self{Fixture{tile:Some((width,height)), overlap, ..self}}
+	pub fn channels(self, channels:u32) -> Self{Fixture{channels, ..self}}
+	pub fn z(self, z:u32) -> Self{Fixture{z, ..self}}
+	pub fn t(self, t:u32) -> Self{Fixture{t, ..self}}
+	pub fn scenes(self, scenes:u32) -> Self{Fixture{scenes, ..self}}
+	pub fn pixel_type(self, pixel_type:PixelType) -> Self{Fixture{pixel_type, ..self}}
+	pub fn compression(self, compression:Compression) -> Self{Fixture{compression, ..self}}
+	pub fn pyramid(self, levels:u32, factor:u32) -> Self{Fixture{pyramid:Some((levels,factor)), ..self}}
+	pub fn metadata(self, xml:&str) -> Self{Fixture{metadata:Some(xml.to_string()), ..self}}
+	pub fn guid(self, guid:Uuid) -> Self{Fixture{guid, ..self}}
+	pub fn scaling(self, scaling:f64) -> Self{Fixture{scaling, ..self}}
+	pub fn stage(self, x:f64, y:f64) -> Self{Fixture{stage:Some((x,y)), ..self}}
+	pub fn subblock_metadata(self, xml:&str) -> Self{Fixture{subblock_metadata:Some(xml.to_string()), ..self}}
+	pub fn extra_dimension(mut self, name:&str, start:i32) -> Self{
+		self.dimensions.push((name.to_string(),start));
+		self
+	}
+	pub fn attachment(mut self, name:&str, content_file_type:&str, data:&[u8]) -> Self{
+		self.attachments.push((name.to_string(),content_file_type.to_string(),data.to_vec()));
+		self
+	}
+	/// shortcut for a "Thumbnail" attachment of type "JPG"
+	pub fn thumbnail(self, data:&[u8]) -> Self{self.attachment("Thumbnail","JPG",data)}
+
+	/// position of the upper left corner of a scene in the common pixel space (scenes are placed side by side)
+	pub fn scene_origin(&self, scene:u32) -> (i32,i32){
+		((scene*self.size.0*2) as i32,0)
+	}
+	/// value of the first sample of the full resolution pixel at x/y in the common pixel space
+	///
+	/// following samples of multi sample pixel types (e.g. Bgr24) are value+1, value+2 ...
+	pub fn value(x:i32, y:i32, c:u32, z:u32, t:u32) -> u64{
+		(x as i64*3 + y as i64*7 + c as i64*1000 + z as i64*100 + t as i64*10000) as u64
+	}
+	/// the bytes of a full resolution pixel as they are stored in the file
+	pub fn pixel_bytes(&self, x:i32, y:i32, c:u32, z:u32, t:u32) -> Vec<u8>{
+		let v = Self::value(x,y,c,z,t);
+		let (samples,sample_size) = match self.pixel_type {
+			PixelType::Gray8 => (1,1),
+			PixelType::Gray16 => (1,2),
+			PixelType::Gray32 => (1,4),
+			PixelType::Gray64 => (1,8),
+			PixelType::Bgr24 => (3,1),
+			PixelType::Bgr48 => (3,2),
+			PixelType::Bgra32 => (4,1),
+			PixelType::Gray32Float => (1,0),
+			PixelType::Bgr96Float => (3,0),
+			PixelType::Gray64ComplexFloat => (2,0),
+			PixelType::Bgr192ComplexFloat => (6,0)
+		};
+		(0..samples).flat_map(|k|{
+			let v = v+k;
+			match sample_size {
+				1 => vec![v as u8],
+				2 => (v as u16).to_le_bytes().to_vec(),
+				4 => (v as u32).to_le_bytes().to_vec(),
+				8 => v.to_le_bytes().to_vec(),
+				_ => (v as f32).to_le_bytes().to_vec()
+			}
+		}).collect()
+	}
+	/// all pyramid levels as (level, minification) starting with full resolution
+	fn levels(&self) -> Vec<(u32,u32)>{
+		match self.pyramid {
+			None => vec![(0,1)],
+			Some((levels,factor)) => (0..=levels).map(|l|(l,factor.pow(l))).collect()
+		}
+	}
+	/// tile rectangles of a scene at full resolution, relative to the scene origin
+	fn tile_frames(&self) -> Vec<(u32,u32,u32,u32)>{
+		let (w,h) = self.size;
+		match self.tile {
+			None => vec![(0,0,w,h)],
+			Some((tw,th)) => {
+				let (sx,sy) = (tw.saturating_sub(self.overlap).max(1),th.saturating_sub(self.overlap).max(1));
+				let mut ret = vec![];
+				for y in (0..h).step_by(sy as usize){
+					for x in (0..w).step_by(sx as usize){
+						ret.push((x,y,tw.min(w-x),th.min(h-y)));
+						if x+tw >= w {break}
+					}
+					if y+th >= h {break}
+				}
+				ret
+			}
+		}
+	}
+	fn dimension(name:&str, Start:i32, Size:u32, StoredSize:u32) -> (String,DimensionEntryDV1){
+		(name.to_string(),DimensionEntryDV1{Dimension:name.to_string(), Start, Size, StartCoordinate:0.0, StoredSize})
+	}
+	/// metadata xml as it will be written, either the generated one or the one set by [Fixture::metadata]
+	pub fn metadata_xml(&self) -> String{
+		if let Some(xml) = &self.metadata {
+			return xml.clone();
+		}
+		let scenes:String = (0..self.scenes).map(|s|format!(
+			"<Scene Index=\"{s}\"><RegionId>{}</RegionId><PyramidInfo><PyramidLayersCount>{}</PyramidLayersCount><MinificationFactor>{}</MinificationFactor></PyramidInfo></Scene>",
+			1000+s, self.pyramid.map_or(0,|p|p.0), self.pyramid.map_or(2,|p|p.1)
+		)).collect();
+		let size_m = self.tile_frames().len();
+		format!(concat!(
+			"<ImageDocument><Metadata>",
+			"<Experiment><ImageName>fixture.czi</ImageName></Experiment>",
+			"<Information><Image>",
+			"<SizeX>{}</SizeX><SizeY>{}</SizeY><SizeZ>{}</SizeZ><SizeC>{}</SizeC><SizeT>{}</SizeT><SizeS>{}</SizeS><SizeM>{}</SizeM>",
+			"<PixelType>{:?}</PixelType>",
+			"<AcquisitionDateAndTime>2022-09-01T12:00:00</AcquisitionDateAndTime>",
+			"<Dimensions><S><Scenes>{}</Scenes></S></Dimensions>",
+			"</Image></Information>",
+			"<Scaling><Items><Distance Id=\"X\"><Value>{:e}</Value></Distance><Distance Id=\"Y\"><Value>{:e}</Value></Distance></Items></Scaling>",
+			"</Metadata></ImageDocument>"),
+			self.size.0, self.size.1, self.z, self.channels, self.t, self.scenes, size_m,
+			self.pixel_type, scenes, self.scaling, self.scaling
+		)
+	}
+	fn encode(&self, data:Vec<u8>) -> Result<Vec<u8>>{
+		match self.compression {
+			Compression::Uncompressed => Ok(data),
+			Compression::Zstd0 => Ok(encode_zstd(&data,0,false)?.1),
+			Compression::Zstd1 => Ok(encode_zstd(&data,0,true)?.1),
+			c => Err(std::io::Error::new(std::io::ErrorKind::Unsupported,format!("Cannot create fixtures with {c:?}")).into())
+		}
+	}
+	/// Write the image into a new file.
+	///
+	/// Subblocks are written scene by scene, level by level, plane by plane, tile by tile.
+	pub fn write(&self, path:&Path) -> Result<()>{
+		let mut writer = FileWriter::create(path)?;
+		let mut entries = vec![];
+		for s in 0..self.scenes{
+			let (ox,oy) = self.scene_origin(s);
+			for (level,minification) in self.levels(){
+				for t in 0..self.t{ for z in 0..self.z{ for c in 0..self.channels{
+					for (m,(x,y,w,h)) in self.tile_frames().into_iter().enumerate(){
+						let (x,y) = (ox+x as i32,oy+y as i32);
+						let stored = (w.div_ceil(minification),h.div_ceil(minification));
+						let mut data = Vec::with_capacity((stored.0*stored.1) as usize*self.pixel_type.bytes_per_pixel());
+						for j in 0..stored.1{
+							for i in 0..stored.0{
+								let (px,py) = (x+(i*minification) as i32,y+(j*minification) as i32);
+								data.extend(self.pixel_bytes(px,py,c,z,t));
+							}
+						}
+						let mut dimension_map:HashMap<String,DimensionEntryDV1> = HashMap::from([
+							Self::dimension("X",x,w,stored.0),
+							Self::dimension("Y",y,h,stored.1),
+							Self::dimension("C",c as i32,1,1),
+							Self::dimension("Z",z as i32,1,1),
+							Self::dimension("T",t as i32,1,1),
+							Self::dimension("S",s as i32,1,1),
+						]);
+						if self.tile.is_some() {
+							dimension_map.extend([Self::dimension("M",m as i32,1,1)]);
+						}
+						dimension_map.extend(self.dimensions.iter().map(|(name,start)|Self::dimension(name,*start,1,1)));
+						let entry = DirectoryEntryDV{
+							SchemaType:"DV".to_string(),
+							PixelType:self.pixel_type.into(),
+							FilePosition:0,
+							FilePart:0,
+							Compression:self.compression.into(),
+							PyramidType:if level > 0 {2} else {0},
+							dimension_map
+						};
+						let stage = self.stage.map_or(String::new(),|(sx,sy)|{
+							// the stage position of the tile center
+							let um = self.scaling*1e6;
+							format!("<StageXPosition>{}</StageXPosition><StageYPosition>{}</StageYPosition>",
+								sx+(x as f64+w as f64/2.0)*um, sy+(y as f64+h as f64/2.0)*um)
+						});
+						let metadata = self.subblock_metadata.clone().unwrap_or_else(||
+							format!("<METADATA><Tags><PyramidLevel>{level}</PyramidLevel>{stage}</Tags></METADATA>")
+						);
+						entries.push(writer.write_subblock(&entry,&metadata,&self.encode(data)?,&[])?);
+					}
+				}}}
+			}
+		}
+		let MetadataPosition = writer.write_metadata(&self.metadata_xml())?;
+		let AttachmentDirectoryPosition = if self.attachments.is_empty() {0} else {
+			let mut directory = AttachmentDirectory{Entries:vec![]};
+			for (i,(name,content_file_type,data)) in self.attachments.iter().enumerate(){
+				let entry = AttachmentEntryA1{
+					SchemaType:"A1".to_string(),
+					FilePosition:0,
+					FilePart:0,
+					ContentGuid:Uuid::from_u128(self.guid.as_u128()+1+i as u128),
+					ContentFileType:content_file_type.clone(),
+					Name:name.clone()
+				};
+				directory.Entries.push(writer.write_attachment(&entry,data)?);
+			}
+			writer.write_attachment_directory(&directory)?
+		};
+		let DirectoryPosition = writer.write_directory(&Directory{Entries:entries})?;
+		writer.finish(&FileHeader{
+			version:[1,0],
+			PrimaryFileGuid:self.guid,
+			FileGuid:self.guid,
+			FilePart:0,
+			DirectoryPosition,
+			MetadataPosition,
+			UpdatePending:false,
+			AttachmentDirectoryPosition
+		})
+	}
+}
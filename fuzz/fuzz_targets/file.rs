@@ -0,0 +1,23 @@
+#![no_main]
+//! Read everything reachable from the file header of arbitrary bytes, the way registering a file does.
+
+use std::sync::Arc;
+use libfuzzer_sys::fuzz_target;
+use zisraw::segment::{Segment, SegmentBlock};
+use zisraw::ZisrawInterface;
+
+fuzz_target!(|data: &[u8]| {
+	let file:Arc<dyn iobase::source::Source> = Arc::new(data.to_vec());
+	let Ok(hd) = zisraw::get_file_header(&file) else {return};
+	hd.get_image_info(&file).ok();
+	if let Ok(Some(mut thumbnail)) = hd.get_thumbnail(&file) {
+		thumbnail.Data.get().ok();
+	}
+	for entry in hd.get_directory(&file).map(|d|d.Entries).unwrap_or_default() {
+		if let Ok(Segment{block:SegmentBlock::ImageSubBlock(mut s), ..}) = Segment::new(&file, entry.FilePosition) {
+			if let Ok(data) = s.Data.get() {
+				zisraw::compression::decode(entry.Compression.into(), data).ok();
+			}
+		}
+	}
+});
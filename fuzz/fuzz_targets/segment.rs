@@ -0,0 +1,21 @@
+#![no_main]
+//! Read a single segment (of any type) from arbitrary bytes.
+
+use std::sync::Arc;
+use libfuzzer_sys::fuzz_target;
+use zisraw::segment::{Segment, SegmentBlock};
+
+fuzz_target!(|data: &[u8]| {
+	let file:Arc<dyn iobase::source::Source> = Arc::new(data.to_vec());
+	if let Ok(segment) = Segment::new(&file, 0) {
+		match segment.block {
+			SegmentBlock::ImageSubBlock(mut s) => {
+				s.Metadata.get().ok();
+				s.Data.get().ok();
+			}
+			SegmentBlock::Metadata(mut m) => {m.as_tree().ok();}
+			SegmentBlock::Attachment(mut a) => {a.Data.get().ok();}
+			_ => {}
+		}
+	}
+});
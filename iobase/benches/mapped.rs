@@ -0,0 +1,69 @@
+use std::fs::File;
+use std::hint::black_box;
+use std::path::PathBuf;
+use std::sync::Arc;
+use criterion::{criterion_group, criterion_main, BenchmarkId, Criterion, Throughput};
+use iobase::blockbuf::BlockBuf;
+use iobase::source::{Mapped, Source};
+use iobase::{DataFromFile, Endian};
+
+const FILE_SIZE:usize = 64<<20;
+const PAYLOAD:usize = 1<<20;
+const HEADERS:usize = 1000;
+
+/// a file with a segment like header (16 byte id, two sizes) every 32 bytes
+fn test_file() -> PathBuf {
+	let path = PathBuf::from(env!("CARGO_TARGET_TMPDIR")).join("mapped_bench.bin");
+	if std::fs::metadata(&path).map(|m|m.len() as usize).ok() != Some(FILE_SIZE) {
+		let mut data = Vec::with_capacity(FILE_SIZE);
+		while data.len() < FILE_SIZE {
+			data.extend(b"ZISRAWSUBBLOCK\0\0");
+			data.extend((data.len() as u64).to_le_bytes());
+			data.extend(32u64.to_le_bytes());
+		}
+		std::fs::write(&path, data).unwrap();
+	}
+	path
+}
+
+fn sources() -> [(&'static str, Arc<dyn Source>);2] {
+	let path = test_file();
+	[
+		("read_exact_at", Arc::new(File::open(&path).unwrap())),
+		("mapped", Arc::new(Mapped::open(&path).unwrap())),
+	]
+}
+
+fn payloads(c:&mut Criterion) {
+	let mut group = c.benchmark_group("payloads");
+	group.throughput(Throughput::Bytes(FILE_SIZE as u64));
+	for (name, source) in sources() {
+		group.bench_function(BenchmarkId::from_parameter(name), |b| b.iter(|| {
+			for pos in (0..FILE_SIZE).step_by(PAYLOAD) {
+				let data = DataFromFile::new(&source, pos as u64, PAYLOAD);
+				black_box(data.get().unwrap().last());
+			}
+		}));
+	}
+	group.finish();
+}
+
+fn headers(c:&mut Criterion) {
+	let mut group = c.benchmark_group("headers");
+	group.throughput(Throughput::Elements(HEADERS as u64));
+	for (name, source) in sources() {
+		group.bench_function(BenchmarkId::from_parameter(name), |b| b.iter(|| {
+			// every header is read through its own buffer, like segments are
+			for pos in (0..FILE_SIZE).step_by(FILE_SIZE/HEADERS).take(HEADERS) {
+				let mut buffer = BlockBuf::new(source.clone(), (pos & !31) as u64, Endian::Little).unwrap();
+				black_box(buffer.get_ascii::<16>().unwrap());
+				black_box(buffer.get_scalar::<u64>().unwrap());
+				black_box(buffer.get_scalar::<u64>().unwrap());
+			}
+		}));
+	}
+	group.finish();
+}
+
+criterion_group!(benches, payloads, headers);
+criterion_main!(benches);
@@ -1,6 +1,7 @@
 use std::f64;
 use std::fmt::{Debug, Formatter};
-use std::time::Instant;
+use std::sync::{Arc, Mutex, MutexGuard, Weak};
+use crate::cache::{CacheManager, Evict};
 use crate::Result;
 
 pub trait ByteSwapper {
@@ -31,36 +32,114 @@ pub trait ByteSwapper {
 // 	}
 // }
 
+/// A value that is produced from its source when it is needed for the first time.
+///
+/// The value is kept until the [CacheManager] drops it to stay within its budget, it will be produced again then.
 pub struct Cached<S,T>{
-	store:Option<T>,
 	pub source:S,
 	producer:fn(&S)->Result<T>,
-	last_use:Instant
+	weigh:fn(&S,&T)->usize,
+	slot:Arc<Slot<T>>,
+	/// makes sure only one thread runs the producer at a time
+	producing:Mutex<()>,
+	id:u64,
+	manager:Arc<CacheManager>
+}
+
+/// the stored value together with its generation
+struct Slot<T>(Mutex<Option<(u64,Arc<T>)>>);
+
+impl<T> Slot<T> {
+	fn lock(&self) -> MutexGuard<'_, Option<(u64,Arc<T>)>>{
+		self.0.lock().unwrap_or_else(|e|e.into_inner())
+	}
+}
+
+impl<T:Send+Sync> Evict for Slot<T>{
+	fn evict(&self, generation:u64) {
+		let mut store = self.lock();
+		if store.as_ref().is_some_and(|(g,_)|*g == generation) {
+			let value = store.take();
+			drop(store);
+			drop(value); // drop the value outside the lock
+		}
+	}
+	fn holds(&self, generation:u64) -> bool {
+		self.lock().as_ref().is_some_and(|(g,_)|*g == generation)
+	}
 }
 
 impl<S,T> Debug for Cached<S,T> {
 	fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
-		let ago = Instant::now()-self.last_use;
-		write!(f,"Empty cache last used {ago:?} ago")
+		match self.slot.lock().is_some() {
+			true => write!(f,"Cached value"),
+			false => write!(f,"Empty cache"),
+		}
 	}
 }
 
-impl<S,T> Cached<S,T> {
+impl<S,T:Send+Sync+'static> Cached<S,T> {
+	/// Create a cache that counts the value with its stack size against the budget.
+	///
+	/// Use [Cached::with_weight] for values that own heap memory.
 	pub fn new(source:S,producer:fn(&S)->Result<T>) -> Cached<S,T>{
+		Self::with_weight(source,producer,|_,_|size_of::<T>())
+	}
+	/// Create a cache with a function to estimate the memory used by the value.
+	pub fn with_weight(source:S,producer:fn(&S)->Result<T>,weigh:fn(&S,&T)->usize) -> Cached<S,T>{
+		Self::managed_by(source,producer,weigh,CacheManager::global())
+	}
+	/// Create a cache that is managed by the given manager instead of the global one.
+	pub fn managed_by(source:S,producer:fn(&S)->Result<T>,weigh:fn(&S,&T)->usize,manager:&Arc<CacheManager>) -> Cached<S,T>{
 		Cached{
-			producer, source,
-			store:None,
-			last_use: Instant::now()
+			producer, weigh, source,
+			slot:Arc::new(Slot(Mutex::new(None))),
+			producing:Mutex::new(()),
+			id:manager.register(),
+			manager:manager.clone()
+		}
+	}
+	/// Get the value, producing it if it isn't stored (anymore).
+	///
+	/// - if multiple threads ask for a value that isn't stored, only one of them produces it and the others wait for it
+	/// - if producing fails the error is returned and the next call will try again
+	pub fn get(&self)->Result<Arc<T>>{
+		if let Some(value) = self.get_stored() {
+			return Ok(value);
+		}
+		let _producing = self.producing.lock().unwrap_or_else(|e|e.into_inner());
+		if let Some(value) = self.get_stored() { // someone else might have been faster
+			return Ok(value);
 		}
+		let generation = self.manager.generation();
+		let value = Arc::new((self.producer)(&self.source)?);
+		{
+			let mut store = self.slot.lock();
+			if store.as_ref().is_none_or(|(g,_)|*g < generation) {
+				*store = Some((generation,value.clone()));
+			}
+		}
+		let slot:Weak<dyn Evict> = Arc::downgrade(&self.slot) as Weak<Slot<T>>;
+		self.manager.stored(self.id,generation,(self.weigh)(&self.source,&value),slot);
+		Ok(value)
 	}
-	pub fn get(&mut self)->Result<&T>{
-		self.last_use= Instant::now();
-		if self.store.is_none() { // try to produce
-			let prod= (self.producer)(&self.source)?;
-			Ok(self.store.insert(prod))
-		} else {
-			Ok(self.store.as_ref().unwrap())
+	/// Get the value only if it is stored, without producing it.
+	pub fn get_stored(&self)->Option<Arc<T>>{
+		let stored = self.slot.lock().as_ref().map(|(_,value)|value.clone());
+		if stored.is_some() {
+			self.manager.used(self.id);
 		}
+		stored
+	}
+	/// true if the value is currently stored (so [Cached::get] won't have to produce it)
+	pub fn is_stored(&self) -> bool{
+		self.slot.lock().is_some()
+	}
+}
+
+impl<S,T> Drop for Cached<S,T> {
+	fn drop(&mut self) {
+		self.manager.forget(self.id);
 	}
 }
 
@@ -1,60 +1,96 @@
-use std::os::unix::fs::FileExt;
+use crate::source::Source;
 use std::sync::Arc;
 use crate::{DataFromFile,Endian};
 use std::mem::size_of;
 use std::fmt::{Debug, Formatter};
 use std::vec::Drain;
 use bytemuck::Pod;
-use std::io::Result;
+use crate::{Error, Result};
 use crate::basic::ByteSwapper;
+pub use iobase_derive::BlockRead;
+
+/// Limits protecting against corrupt or hostile files.
+///
+/// Sizes and counts read from a file are checked against these before anything is allocated for them.
+#[derive(Debug,Clone,PartialEq)]
+pub struct Limits{
+	/// maximum size of a block (see [BlockBuf::limit_to]) in bytes
+	pub max_block_size:u64,
+	/// maximum amount of elements read by [BlockBuf::read_vec] and [BlockBuf::get_vec]
+	pub max_elements:usize
+}
+
+impl Default for Limits{
+	fn default() -> Self {
+		Limits{max_block_size:1<<32, max_elements:1<<22}
+	}
+}
+
+/// amount of memory that is allocated at once while reading, so corrupt sizes can't allocate more than the file has
+const FETCH_CHUNK:usize = 1<<20;
+/// maximum amount of buffered bytes [BlockBuf::at] hands on, so peeking never copies the whole buffer
+const PEEK_CHUNK:usize = 1024;
 
 pub struct BlockBuf{
-	source:Arc<dyn FileExt>,
+	source:Arc<dyn Source>,
 	start_in_file:u64,
 	drained:usize,
 	buffer:Vec<u8>,
+	/// maximum amount of bytes (from start_in_file) this buffer may read
+	size:Option<u64>,
+	limits:Limits,
 	endianess:Endian
 }
 
 impl BlockBuf {
 	fn swap_bytes_if_needed<T:ByteSwapper>(&self,t:T)->T{
-		#[cfg(target_endian = "little")]
-		{
-			match self.endianess {
-				Endian::Big => t.swap_bytes(),
-				Endian::Little => t
-			}
-		}
-		#[cfg(not(target_endian = "little"))]
-		{
-			match endianess {
-				Endian::Big => t,
-				Endian::Little => t.swap_bytes()
-			}
-		}
-
+		self.endianess.swap_bytes_if_needed(t)
+	}
+	fn check_elements(&self, len:usize) -> Result<()>{
+		if len > self.limits.max_elements {
+			Err(Error::LimitExceeded{what:"element count".to_string(), value:len as u64, limit:self.limits.max_elements as u64})
+		} else {Ok(())}
+	}
+	/// position in the file pos bytes after the current position (fails on overflow)
+	fn file_position(&self, pos:usize) -> Result<u64>{
+		(self.drained as u64).checked_add(pos as u64)
+			.and_then(|p|p.checked_add(self.start_in_file))
+			.ok_or(Error::InvalidData(format!("Position {pos} after {} is out of range",self.start_in_file)))
 	}
 	/// read at least min bytes from the file and append them onto the buffer
+	///
+	/// - if the file (or the block) ends before min bytes could be read, an [Error::Truncated] is returned and the buffer is unchanged
 	fn fetch_at_least(&mut self, min:usize) -> Result<usize>{
-		let mut request:isize = min as isize; //request could actually go below zero as we're likely to read more than was requested
 		let oldsize = self.buffer.len();
-		self.buffer.resize(oldsize+min+1024,0); //always ask (and prepare) for more
-		let target = &mut self.buffer[oldsize..];
-
-		while request > 0 {
-			let already_red = min-request as usize;
-			let relative_pos= (self.drained+oldsize+already_red) as u64;
-			match self.source.read_at(target,self.start_in_file+relative_pos) {
+		let start = self.file_position(oldsize)?;
+		let mut want = min.saturating_add(1024); //always ask for more
+		if let Some(available) = self.remaining().map(|r|r.saturating_sub(oldsize as u64)) {
+			if (min as u64) > available {
+				return Err(Error::Truncated{offset:start, needed:min as u64, available});
+			}
+			want = want.min(available as usize);
+		}
+		let mut red = 0;
+		while red < min {
+			// grow step by step, so we never allocate much more than there actually is in the file
+			let end = oldsize + want.min(red+FETCH_CHUNK);
+			self.buffer.resize(end,0);
+			match self.source.read_at(&mut self.buffer[oldsize+red..end],start+red as u64) {
 				Ok(0) => break, // nothing to see here
-				Ok(n) => {request-=n as isize;}
+				Ok(n) => {red+=n;}
 				Err(ref e) if e.kind() == std::io::ErrorKind::Interrupted => {} // just try again
-				Err(e) => return Err(e.into()),
+				Err(e) => {
+					self.buffer.truncate(oldsize);
+					return Err(e.into())
+				}
 			}
 		}
-		assert!(request<=0); // we're done reading, request probably dropped below 0
-		let actually_red_data= min+(-request)as usize;//actually red plus overshoot
-		self.buffer.shrink_to(oldsize+actually_red_data); //resize to requested size plus the overshoot
-		Ok(actually_red_data)
+		self.buffer.truncate(oldsize+red); //cut to what was actually red (requested size plus the overshoot)
+		if red < min {
+			self.buffer.truncate(oldsize);
+			return Err(Error::Truncated{offset:start, needed:min as u64, available:red as u64});
+		}
+		Ok(red)
 	}
 	fn skip(&mut self, len:usize){
 		self.drained += len;
@@ -64,9 +100,40 @@ impl BlockBuf {
 			self.buffer = vec![];
 		}
 	}
-	pub fn drain(&mut self,size:usize) -> Result<Drain<u8>>{
-		if size > self.buffer.len(){ // make sure, we do have the data
-			self.fetch_at_least(size)?;
+	/// Call f with the next size bytes and drain them.
+	///
+	/// - if the source is in memory (see [Source::as_slice]) the bytes are borrowed from there and nothing is copied
+	fn with_bytes<R>(&mut self, size:usize, f:impl FnOnce(&[u8])->R) -> Result<R>{
+		if self.source.as_slice().is_none() {
+			return Ok(f(self.drain(size)?.as_slice()));
+		}
+		let start = self.file_position(0)?;
+		let end = self.file_position(size)?;
+		let data = self.source.as_slice().unwrap_or_default();
+		let mut available = (data.len() as u64).saturating_sub(start);
+		if let Some(remaining) = self.remaining() {
+			available = available.min(remaining);
+		}
+		if size as u64 > available {
+			return Err(Error::Truncated{offset:start, needed:size as u64, available});
+		}
+		let bytes = usize::try_from(start).ok().zip(usize::try_from(end).ok())
+			.and_then(|(start,end)|data.get(start..end))
+			.unwrap_or_default(); // only reachable for size 0
+		let ret = f(bytes);
+		self.skip(size);
+		Ok(ret)
+	}
+	pub fn drain(&mut self,size:usize) -> Result<Drain<'_, u8>>{
+		let have = self.buffer.len();
+		if size > have { // make sure, we do have the data
+			self.fetch_at_least(size-have).map_err(|e|match e {
+				// report the whole request, not just the part that wasn't buffered yet
+				Error::Truncated{offset,needed,available} => Error::Truncated{
+					offset:offset-have as u64, needed:needed+have as u64, available:available+have as u64
+				},
+				e => e
+			})?;
 		}
 		self.drained +=size;
 		Ok(self.buffer.drain(..size))
@@ -75,10 +142,34 @@ impl BlockBuf {
 	///
 	/// - the buffer starts at pos in the source file
 	/// - endianess describes the endianess of the file
-	pub fn new(	source:Arc<dyn FileExt>, pos:u64, endianess: Endian) -> Result<Self>{
-		Ok(Self{source, start_in_file:pos, drained:0, endianess, buffer:vec![]})
+	pub fn new(	source:Arc<dyn Source>, pos:u64, endianess: Endian) -> Result<Self>{
+		Ok(Self{source, start_in_file:pos, drained:0, endianess, buffer:vec![], size:None, limits:Limits::default()})
 		//the first drain will initialize buffer at pos with at least 1k
 	}
+	/// Replace the limits this buffer (and all buffers spliced off from it) checks against.
+	pub fn with_limits(self, limits:Limits) -> Self{
+		Self{limits, ..self}
+	}
+	pub fn limits(&self) -> &Limits{&self.limits}
+	/// Restrict the buffer to size bytes from its beginning.
+	///
+	/// - all reads beyond that will return [Error::Truncated]
+	/// - the buffer can only be restricted further, never extended
+	/// - sizes bigger than [Limits::max_block_size] will return [Error::LimitExceeded]
+	pub fn limit_to(&mut self, size:u64) -> Result<&mut BlockBuf>{
+		if size > self.limits.max_block_size {
+			return Err(Error::LimitExceeded{what:"block size".to_string(), value:size, limit:self.limits.max_block_size});
+		}
+		self.size = Some(self.size.map_or(size,|s|s.min(size)));
+		if let Some(remaining) = self.remaining() {
+			self.buffer.truncate(remaining.min(usize::MAX as u64) as usize);
+		}
+		Ok(self)
+	}
+	/// amount of bytes that can still be read if the buffer is restricted by [BlockBuf::limit_to]
+	pub fn remaining(&self) -> Option<u64>{
+		self.size.map(|s|s.saturating_sub(self.drained as u64))
+	}
 	/// Grows or shrinks the buffer and reads data from the source file if necessary.
 	///
 	/// **Notice that newsize ignores already drained data.**
@@ -86,14 +177,14 @@ impl BlockBuf {
 	/// But the returned "size" will still be 20k, or probably more, as the read intentionally "overshoots"
 	/// **Notice that, while this will read at least the requested data, ist probably going to read more**
 	/// failing to read at least the requested data will return an IO error
-	pub fn resize(&mut self, newsize:usize) -> std::io::Result<usize>{
+	pub fn resize(&mut self, newsize:usize) -> Result<usize>{
 		let oldsize=self.buffer.len();//save old length for later
-		let newsize = newsize-self.drained;
+		let newsize = newsize.saturating_sub(self.drained);
 		if newsize > oldsize { // needs to grow, fill new bytes accordingly
 			let red= self.fetch_at_least(newsize-oldsize)?;
 			Ok(red+self.drained)
 		} else { // we already have everything, maybe can even cut off some bytes at the end
-			self.buffer.shrink_to(newsize);
+			self.buffer.truncate(newsize);
 			Ok(newsize+self.drained)
 		} // we actually shrunk the buffer
 	}
@@ -101,14 +192,68 @@ impl BlockBuf {
 	///
 	/// - newpos is meant from the beginning of the buffer (aka the position it was originally created at)
 	/// - trying to skip to a position that was already drained will return an error and has no other effect
+	/// - use [BlockBuf::seek_to] to go backwards
 	pub fn skip_to(&mut self, newpos:u64) -> Result<&mut BlockBuf>{
 		if newpos < self.drained as u64{
-			Err(std::io::Error::new(std::io::ErrorKind::Other,"Cannot skip backwards").into())
+			Err(Error::InvalidData(format!("Cannot skip backwards from {} to {newpos}",self.drained)))
 		} else {
-			self.skip(newpos as usize - self.drained);
+			let len = usize::try_from(newpos-self.drained as u64)
+				.map_err(|_|Error::InvalidData(format!("Cannot skip from {} to {newpos}",self.drained)))?;
+			self.skip(len);
+			Ok(self)
+		}
+	}
+	/// the current position, meant from the beginning of the buffer
+	pub fn position(&self) -> u64{self.drained as u64}
+	/// Go to a specific position, forwards or backwards.
+	///
+	/// - newpos is meant from the beginning of the buffer, so a buffer can never go back before its beginning
+	/// - going backwards drops the buffered data, it will be read again from the source when needed
+	pub fn seek_to(&mut self, newpos:u64) -> Result<&mut BlockBuf>{
+		if newpos < self.drained as u64{
+			self.drained = newpos as usize;
+			self.buffer = vec![];
 			Ok(self)
+		} else {
+			self.skip_to(newpos)
 		}
 	}
+	/// Go back to the beginning of the buffer (see [BlockBuf::seek_to]).
+	pub fn rewind(&mut self) -> &mut BlockBuf{
+		self.drained = 0;
+		self.buffer = vec![];
+		self
+	}
+	/// Run f at the given position without moving this buffer.
+	///
+	/// - pos is meant from the beginning of the buffer
+	/// - f gets a buffer of its own over the same block, so the data buffered here is kept as it is
+	/// - f starts with (up to 1k of) the bytes already buffered at pos, everything else is read from the source again
+	pub fn at<R>(&self, pos:u64, f:impl FnOnce(&mut BlockBuf)->Result<R>) -> Result<R>{
+		let buffered = usize::try_from(pos).ok()
+			.and_then(|pos|pos.checked_sub(self.drained))
+			.filter(|_|self.source.as_slice().is_none()) // in memory, f borrows from the source anyway
+			.and_then(|offset|self.buffer.get(offset..))
+			.map_or(vec![],|bytes|bytes[..bytes.len().min(PEEK_CHUNK)].to_vec());
+		let mut view = BlockBuf{
+			source: self.source.clone(),
+			start_in_file: self.start_in_file,
+			drained: 0,
+			buffer: vec![],
+			size: self.size,
+			limits: self.limits.clone(),
+			endianess: self.endianess.clone()
+		};
+		view.seek_to(pos)?;
+		view.buffer = buffered;
+		f(&mut view)
+	}
+	/// Run f at the current position without draining anything (see [BlockBuf::at]).
+	///
+	/// E.g. `buffer.peek(|b|b.get_ascii::<2>())` to look at a schema tag before deciding how to read the rest.
+	pub fn peek<R>(&self, f:impl FnOnce(&mut BlockBuf)->Result<R>) -> Result<R>{
+		self.at(self.position(),f)
+	}
 	/// splices of a block buffer with the given size that starts at the current position
 	///
 	/// - drains all bytes from the this buffer, you'll have to take them back
@@ -121,6 +266,8 @@ impl BlockBuf {
 			start_in_file: self.start_in_file+self.drained as u64,
 			drained: 0,
 			buffer,
+			size: self.remaining(),
+			limits: self.limits.clone(),
 			endianess: self.endianess.clone()
 		}
 	}
@@ -129,12 +276,18 @@ impl BlockBuf {
 	/// - drains size bytes from the this buffer
 	/// - clones the source file object
 	pub fn splice(&mut self, size:usize) -> Result<BlockBuf>{
-		let buffer = self.drain(size)?.collect();
+		let start_in_file = self.file_position(0)?;
+		let buffer = match self.source.as_slice() {
+			Some(_) => self.with_bytes(size,|_|vec![])?, // no need to copy, the splice will borrow from the source as well
+			None => self.drain(size)?.collect()
+		};
 		Ok(BlockBuf{
 			source: self.source.clone(),
-			start_in_file: self.start_in_file+self.drained as u64,
+			start_in_file,
 			drained: 0,
 			buffer,
+			size: Some(size as u64),
+			limits: self.limits.clone(),
 			endianess: self.endianess.clone()
 		})
 	}
@@ -146,26 +299,32 @@ impl BlockBuf {
 		BlockBuf{
 			start_in_file: self.start_in_file+self.drained as u64,
 			drained: 0,
+			size: self.remaining(),
 			..self
 		}
 	}
 	/// Creates a DataFromFiles at the current position with the given size.
 	///
 	/// - drains size bytes from the buffer.
-	/// - this does not actually read any data, and thus cannot fail
-	pub fn get_cached_data(&mut self,size:usize) -> DataFromFile{
-		let ret= DataFromFile::new(&self.source,self.start_in_file+self.drained as u64,size);
+	/// - this does not actually read any data, it only fails if size goes beyond the block or the position overflows
+	pub fn get_cached_data(&mut self,size:usize) -> Result<DataFromFile>{
+		let pos = self.file_position(0)?;
+		self.file_position(size)?;
+		if let Some(available) = self.remaining() {
+			if size as u64 > available {
+				return Err(Error::Truncated{offset:pos, needed:size as u64, available});
+			}
+		}
+		let ret= DataFromFile::new(&self.source,pos,size);
 		self.skip(size);
-		ret
+		Ok(ret)
 	}
 	/// Get a scalar value from the buffer.
 	///
 	/// - drains size_of::<T>() bytes from the buffer.
 	/// - will convert endianess if necessary
 	pub fn get_scalar<T:bytemuck::AnyBitPattern+ByteSwapper>(&mut self)->Result<T>{
-		let ret:T = bytemuck::from_bytes::<T>(
-			self.drain(size_of::<T>())?.as_slice()
-		).clone();
+		let ret:T = self.with_bytes(size_of::<T>(),bytemuck::pod_read_unaligned)?;
 		Ok(self.swap_bytes_if_needed(ret))
 	}
 	/// Get an array of scalar values from the buffer.
@@ -173,36 +332,37 @@ impl BlockBuf {
 	/// - drains N * size_of::<T>() bytes from the buffer.
 	/// - will convert endianess if necessary
 	pub fn get_array<const N:usize,T:bytemuck::AnyBitPattern+ByteSwapper>(&mut self)->Result<[T;N]>{
-		// make sure buffer is actually big enough so self.get_scalar() won't fail
-		self.fetch_at_least(N*size_of::<T>())?;
-		Ok(std::array::from_fn(|_|self.get_scalar().unwrap()))
+		let size = size_of::<T>();
+		let endianess = self.endianess.clone();
+		self.with_bytes(N*size,|bytes|std::array::from_fn(|i|
+			endianess.swap_bytes_if_needed(bytemuck::pod_read_unaligned(&bytes[i*size..(i+1)*size]))
+		))
 	}
 	/// Get an vector of scalar values from the buffer.
 	///
 	/// - drains len * size_of::<T>() bytes from the buffer.
 	/// - will convert endianess if necessary
 	pub fn get_vec<T:ByteSwapper+Pod>(&mut self,len:usize) -> Result<Vec<T>>{
+		self.check_elements(len)?;
 		std::iter::from_fn(||Some(self.get_scalar()))
 			.take(len).collect()
 	}
 	/// Drain given amount of bytes and try to interpret them as string.
 	///
 	/// - always drains len bytes from the buffer.
-	pub fn get_utf8(&mut self, len:usize) -> crate::Result<String>{
-		let bytes:Vec<u8> = self.drain(len)?.collect();
-		String::from_utf8(bytes)
-			.or_else(|e|Err(e.into()))
+	pub fn get_utf8(&mut self, len:usize) -> Result<String>{
+		let bytes = self.with_bytes(len,<[u8]>::to_vec)?;
+		Ok(String::from_utf8(bytes)?)
 	}
 	/// Drain given amount of bytes and try to interpret them as cstring.
 	///
 	/// - always drains LEN bytes from the buffer even if null.
 	/// - the returned string will stop at the the first encountered null-terminator if there is any.
 	pub fn get_ascii<const LEN: usize>(&mut self) -> Result<String> {
-		self.drain(LEN)
-			.map(|drained|String::from_utf8_lossy(drained.as_slice())
-				.trim_end_matches('\0')
-				.to_string()
-			)
+		self.with_bytes(LEN,|bytes|String::from_utf8_lossy(bytes)
+			.trim_end_matches('\0')
+			.to_string()
+		)
 	}
 	/// create an object by reading data from the buffer
 	///
@@ -220,6 +380,7 @@ impl BlockBuf {
 	/// create a vector of objects by reading all remaining data from the buffer
 	pub fn read_vec<T>(&mut self,len:usize) -> Result<Vec<T>> where T:BlockRead
 	{
+		self.check_elements(len)?;
 		std::iter::from_fn(||Some(self.read())).take(len).collect()
 	}
 }
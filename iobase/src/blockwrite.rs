@@ -0,0 +1,106 @@
+use crate::Endian;
+use std::fmt::{Debug, Formatter};
+use crate::{Error, Result};
+use bytemuck::Pod;
+use crate::basic::ByteSwapper;
+pub use iobase_derive::BlockWrite;
+
+/// The writing counterpart of [BlockBuf](crate::blockbuf::BlockBuf).
+///
+/// Collects serialized data in memory, so it can be written to a file in one go.
+pub struct BlockWriter{
+	buffer:Vec<u8>,
+	endianess:Endian
+}
+
+impl BlockWriter {
+	/// Create a new, empty writer.
+	///
+	/// - endianess describes the endianess of the file that is to be written
+	pub fn new(endianess: Endian) -> Self{
+		Self{buffer:vec![],endianess}
+	}
+	/// amount of bytes written so far
+	pub fn len(&self) -> usize {self.buffer.len()}
+	pub fn is_empty(&self) -> bool {self.buffer.is_empty()}
+	/// consumes the writer and returns the written bytes
+	pub fn into_inner(self) -> Vec<u8> {self.buffer}
+	/// Append raw bytes.
+	pub fn put_bytes(&mut self, bytes:&[u8]) -> &mut BlockWriter{
+		self.buffer.extend_from_slice(bytes);
+		self
+	}
+	/// Append a scalar value.
+	///
+	/// - appends size_of::<T>() bytes.
+	/// - will convert endianess if necessary
+	pub fn put_scalar<T:Pod+ByteSwapper>(&mut self, value:T) -> &mut BlockWriter{
+		let value = self.endianess.swap_bytes_if_needed(value);
+		self.buffer.extend_from_slice(bytemuck::bytes_of(&value));
+		self
+	}
+	/// Append an array of scalar values.
+	///
+	/// - appends N * size_of::<T>() bytes.
+	/// - will convert endianess if necessary
+	pub fn put_array<const N:usize,T:Pod+ByteSwapper>(&mut self, values:&[T;N]) -> &mut BlockWriter{
+		for v in values{
+			self.put_scalar(*v);
+		}
+		self
+	}
+	/// Append a string as cstring of fixed length.
+	///
+	/// - always appends LEN bytes, the remainder is filled with null.
+	/// - strings longer than LEN will return an error and have no other effect.
+	pub fn put_ascii<const LEN: usize>(&mut self, s:&str) -> Result<&mut BlockWriter>{
+		if s.len() > LEN {
+			return Err(Error::InvalidData(format!("\"{s}\" does not fit into {LEN} bytes")));
+		}
+		self.buffer.extend_from_slice(s.as_bytes());
+		self.buffer.resize(self.buffer.len()+LEN-s.len(),0);
+		Ok(self)
+	}
+	/// Fills up with null until the given position is reached
+	///
+	/// - newpos is meant from the beginning of the writer
+	/// - trying to pad to a position that was already written will return an error and has no other effect
+	pub fn pad_to(&mut self, newpos:usize) -> Result<&mut BlockWriter>{
+		if newpos < self.buffer.len(){
+			Err(Error::InvalidData(format!("Cannot pad backwards from {} to {newpos}",self.buffer.len())))
+		} else {
+			self.buffer.resize(newpos,0);
+			Ok(self)
+		}
+	}
+	/// append an object by serializing it
+	///
+	/// T::write is run with a fresh writer.
+	/// Because of that T::write will see a writer with no written bytes, just like BlockRead::read does.
+	pub fn write<T>(&mut self, obj:&T) -> Result<&mut BlockWriter> where T:BlockWrite{
+		let mut local = BlockWriter::new(self.endianess.clone());
+		obj.write(&mut local)?;
+		self.buffer.append(&mut local.buffer);
+		Ok(self)
+	}
+	/// append all objects of a slice
+	pub fn write_vec<T>(&mut self, objs:&[T]) -> Result<&mut BlockWriter> where T:BlockWrite{
+		for obj in objs{
+			self.write(obj)?;
+		}
+		Ok(self)
+	}
+}
+
+pub trait BlockWrite{
+	fn write(&self, buffer:&mut BlockWriter) -> Result<()>;
+}
+
+impl Debug for BlockWriter{
+	fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+		f.debug_struct("BlockWriter")
+			.field("endianess",&self.endianess)
+			.field("written bytes",&self.buffer.len())
+			.finish()
+	}
+}
@@ -0,0 +1,185 @@
+use std::collections::{BTreeMap, HashMap};
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::sync::{Arc, Mutex, MutexGuard, OnceLock, Weak};
+
+/// Something holding a cached value that can be dropped to free memory.
+pub(crate) trait Evict: Send+Sync{
+	/// drop the value, but only if it is still the one of the given generation
+	fn evict(&self, generation:u64);
+	/// true if the value of the given generation is stored
+	fn holds(&self, generation:u64) -> bool;
+}
+
+struct Entry{
+	size:usize,
+	generation:u64,
+	last_use:u64,
+	slot:Weak<dyn Evict>
+}
+
+#[derive(Default)]
+struct Lru{
+	budget:usize,
+	used:usize,
+	tick:u64,
+	entries:HashMap<u64,Entry>,
+	/// ids of the entries by their last use
+	order:BTreeMap<u64,u64>
+}
+
+impl Lru {
+	fn remove(&mut self, id:u64) -> Option<Entry>{
+		let entry = self.entries.remove(&id)?;
+		self.order.remove(&entry.last_use);
+		self.used -= entry.size;
+		Some(entry)
+	}
+	/// remove least recently used entries until the budget is met and return them
+	fn over_budget(&mut self) -> Vec<Entry>{
+		let mut ret = vec![];
+		while self.used > self.budget {
+			let Some((_,id)) = self.order.pop_first() else {break};
+			ret.extend(self.remove(id));
+		}
+		ret
+	}
+}
+
+/// Counters and current state of a [CacheManager].
+#[derive(Debug,Clone,Default,PartialEq)]
+pub struct CacheStats{
+	/// requests that were answered from the cache
+	pub hits:u64,
+	/// requests that had to produce the value
+	pub misses:u64,
+	/// values that were dropped to stay within the budget
+	pub evictions:u64,
+	/// amount of values currently stored
+	pub entries:usize,
+	/// estimated memory used by the stored values in bytes
+	pub used:usize,
+	/// maximum of memory the stored values may use in bytes
+	pub budget:usize
+}
+
+/// Keeps track of all values stored in [crate::basic::Cached] (and thus [crate::DataFromFile]).
+///
+/// If the stored values use more memory than the budget, the least recently used ones are dropped.
+/// They will be produced again when they are needed the next time.
+/// All caches use the [CacheManager::global] manager unless told otherwise.
+pub struct CacheManager{
+	lru:Mutex<Lru>,
+	next_id:AtomicU64,
+	next_generation:AtomicU64,
+	hits:AtomicU64,
+	misses:AtomicU64,
+	evictions:AtomicU64
+}
+
+impl CacheManager {
+	/// default budget of the global manager (1GB)
+	pub const DEFAULT_BUDGET:usize = 1<<30;
+	pub fn new(budget:usize) -> Self{
+		CacheManager{
+			lru:Mutex::new(Lru{budget,..Default::default()}),
+			next_id:AtomicU64::new(0),
+			next_generation:AtomicU64::new(0),
+			hits:AtomicU64::new(0),
+			misses:AtomicU64::new(0),
+			evictions:AtomicU64::new(0)
+		}
+	}
+	/// the manager used by all caches unless told otherwise
+	pub fn global() -> &'static Arc<CacheManager>{
+		static GLOBAL:OnceLock<Arc<CacheManager>> = OnceLock::new();
+		GLOBAL.get_or_init(||Arc::new(CacheManager::new(Self::DEFAULT_BUDGET)))
+	}
+	fn lock(&self) -> MutexGuard<'_, Lru>{
+		self.lru.lock().unwrap_or_else(|e|e.into_inner())
+	}
+	/// Change the budget in bytes.
+	///
+	/// - if more memory than that is used already, values are dropped right away
+	pub fn set_budget(&self, budget:usize){
+		let victims = {
+			let mut lru = self.lock();
+			lru.budget = budget;
+			lru.over_budget()
+		};
+		self.evict(victims);
+	}
+	pub fn stats(&self) -> CacheStats{
+		let lru = self.lock();
+		CacheStats{
+			hits:self.hits.load(Ordering::Relaxed),
+			misses:self.misses.load(Ordering::Relaxed),
+			evictions:self.evictions.load(Ordering::Relaxed),
+			entries:lru.entries.len(),
+			used:lru.used,
+			budget:lru.budget
+		}
+	}
+	/// a new id for a value that is to be stored
+	pub(crate) fn register(&self) -> u64{
+		self.next_id.fetch_add(1,Ordering::Relaxed)
+	}
+	/// Get a generation for a value that is about to be produced.
+	///
+	/// Generations increase, so a value can never be replaced by one that was produced before it.
+	pub(crate) fn generation(&self) -> u64{
+		self.misses.fetch_add(1,Ordering::Relaxed);
+		self.next_generation.fetch_add(1,Ordering::Relaxed)
+	}
+	/// Record that a value was stored and drop others (or the value itself) if that exceeds the budget.
+	///
+	/// - the slot must not be locked when calling this
+	/// - does nothing if the slot does not hold that generation (anymore), because it was replaced or evicted already
+	pub(crate) fn stored(&self, id:u64, generation:u64, size:usize, slot:Weak<dyn Evict>){
+		let victims = {
+			let mut lru = self.lock();
+			// slots are only ever locked after the manager, so this can't deadlock
+			if !slot.upgrade().is_some_and(|s|s.holds(generation)) {
+				return;
+			}
+			lru.remove(id);
+			lru.tick += 1;
+			let last_use = lru.tick;
+			lru.entries.insert(id,Entry{size,generation,last_use,slot});
+			lru.order.insert(last_use,id);
+			lru.used += size;
+			lru.over_budget()
+		};
+		self.evict(victims);
+	}
+	/// record that the value with the given id was used again
+	pub(crate) fn used(&self, id:u64){
+		self.hits.fetch_add(1,Ordering::Relaxed);
+		let mut lru = self.lock();
+		lru.tick += 1;
+		let tick = lru.tick;
+		if let Some(entry) = lru.entries.get_mut(&id) {
+			let last_use = std::mem::replace(&mut entry.last_use,tick);
+			lru.order.remove(&last_use);
+			lru.order.insert(tick,id);
+		}
+	}
+	/// forget about a value that was dropped by its owner
+	pub(crate) fn forget(&self, id:u64){
+		self.lock().remove(id);
+	}
+	/// evict values outside the lock, as evicting needs to lock the value itself
+	fn evict(&self, victims:Vec<Entry>){
+		for entry in victims {
+			if let Some(slot) = entry.slot.upgrade() {
+				slot.evict(entry.generation);
+				self.evictions.fetch_add(1,Ordering::Relaxed);
+			}
+		}
+	}
+}
+
+impl std::fmt::Debug for CacheManager{
+	fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+		write!(f,"CacheManager {:?}",self.stats())
+	}
+}
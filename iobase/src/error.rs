@@ -0,0 +1,104 @@
+use std::fmt::{Debug, Display, Formatter};
+
+/// Errors of all layers of reading (and writing) zisraw files.
+///
+/// Errors that happen while a segment is parsed are wrapped into [Error::Segment],
+/// so the segment type and its position in the file are known.
+#[derive(Debug)]
+pub enum Error{
+	/// the underlying file operation failed
+	Io(std::io::Error),
+	/// the data ended before the structure being read was complete
+	Truncated{offset:u64, needed:u64, available:u64},
+	/// a segment or structure did not have the expected type
+	BadMagic{expected:String, found:String},
+	/// the payload uses a compression that cannot be decoded
+	UnsupportedCompression(String),
+	/// embedded xml could not be parsed or does not contain what was looked for
+	Xml(String),
+	/// the database failed
+	Sql(Box<dyn std::error::Error+Send+Sync>),
+	/// something that was looked for does not exist
+	NotFound(String),
+	/// data is present but not valid
+	InvalidData(String),
+	/// a size or count read from the file exceeds the configured limits
+	LimitExceeded{what:String, value:u64, limit:u64},
+	/// an error that occurred while reading the segment of the given type at the given position
+	Segment{segment:String, offset:u64, cause:Box<Error>}
+}
+
+impl Error {
+	/// Attach the segment type and its position in the file to an error.
+	///
+	/// Errors that already know their segment are left alone, so the innermost segment wins.
+	pub fn in_segment(self, segment:&str, offset:u64) -> Error{
+		match self {
+			Error::Segment{..} => self,
+			cause => Error::Segment{segment:segment.to_string(), offset, cause:Box::new(cause)}
+		}
+	}
+	/// type and position of the segment the error occurred in (if known)
+	pub fn segment(&self) -> Option<(&str,u64)>{
+		match self {
+			Error::Segment{segment,offset,..} => Some((segment.as_str(),*offset)),
+			_ => None
+		}
+	}
+	/// the actual cause of the error without segment information
+	pub fn cause(&self) -> &Error{
+		match self {
+			Error::Segment{cause,..} => cause.cause(),
+			e => e
+		}
+	}
+	/// true if something that was looked for (including files) does not exist
+	pub fn is_not_found(&self) -> bool{
+		match self.cause() {
+			Error::NotFound(_) => true,
+			Error::Io(e) => e.kind() == std::io::ErrorKind::NotFound,
+			_ => false
+		}
+	}
+}
+
+impl Display for Error{
+	fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+		match self {
+			Error::Io(e) => Display::fmt(e,f),
+			Error::Truncated{offset,needed,available} =>
+				write!(f,"data ended at {} ({needed} bytes needed, {available} available)",offset.saturating_add(*available)),
+			Error::BadMagic{expected,found} => write!(f,"expected {expected} but found \"{found}\""),
+			Error::UnsupportedCompression(c) => write!(f,"unsupported compression {c}"),
+			Error::Xml(e) => write!(f,"xml error: {e}"),
+			Error::Sql(e) => write!(f,"sql error: {e}"),
+			Error::NotFound(what) => write!(f,"{what} not found"),
+			Error::InvalidData(e) => Display::fmt(e,f),
+			Error::LimitExceeded{what,value,limit} => write!(f,"{what} {value} exceeds the limit of {limit}"),
+			Error::Segment{segment,offset,cause} => write!(f,"in {segment} segment at {offset}: {cause}")
+		}
+	}
+}
+
+impl std::error::Error for Error{
+	fn source(&self) -> Option<&(dyn std::error::Error + 'static)> {
+		match self {
+			Error::Io(e) => Some(e),
+			Error::Sql(e) => Some(e.as_ref()),
+			Error::Segment{cause,..} => Some(cause.as_ref()),
+			_ => None
+		}
+	}
+}
+
+impl From<std::io::Error> for Error{
+	fn from(e: std::io::Error) -> Self {Error::Io(e)}
+}
+
+impl From<std::string::FromUtf8Error> for Error{
+	fn from(e: std::string::FromUtf8Error) -> Self {Error::InvalidData(e.to_string())}
+}
+
+impl From<&str> for Error{
+	fn from(e: &str) -> Self {Error::InvalidData(e.to_string())}
+}
@@ -1,32 +1,200 @@
-use std::error;
-use basic::Cached;
-use std::os::unix::fs::FileExt;
+use basic::{ByteSwapper, Cached};
+use crate::source::Source;
+use std::io::{Read, Seek, SeekFrom};
+use std::ops::{Deref, Range};
 use std::sync::Arc;
 
 pub mod basic;
 pub mod blockbuf;
+pub mod blockwrite;
+pub mod cache;
+pub mod source;
+pub mod error;
+
+pub use error::Error;
 
 #[derive(Debug,Clone)]
 pub enum Endian{Big,Little}
 
-pub type Result<T> = std::result::Result<T, Box<dyn error::Error>>;
+impl Endian {
+	/// convert between native endianess and this endianess (works in both directions)
+	pub fn swap_bytes_if_needed<T:ByteSwapper>(&self,t:T)->T{
+		#[cfg(target_endian = "little")]
+		{
+			match self {
+				Endian::Big => t.swap_bytes(),
+				Endian::Little => t
+			}
+		}
+		#[cfg(not(target_endian = "little"))]
+		{
+			match self {
+				Endian::Big => t,
+				Endian::Little => t.swap_bytes()
+			}
+		}
+	}
+}
+
+pub type Result<T> = std::result::Result<T, Error>;
 
+/// Data of a given size at a given position in a file.
+///
+/// - nothing is read before it is needed
+/// - the data can be read all at once with [DataFromFile::get], which keeps it cached
+/// - or in parts with [DataFromFile::read_range], [Read] and [Seek] or as a [Source], which don't cache anything
 #[derive(Debug)]
 pub struct DataFromFile{
-    cache: Cached<(Arc<dyn FileExt>,u64,usize),Vec<u8>>
+    cache: Cached<(Arc<dyn Source>,u64,usize),Vec<u8>>,
+    /// current position for [Read] and [Seek]
+    cursor: u64
 }
 impl DataFromFile {
-    pub fn new(file:&Arc<dyn FileExt>, pos:u64,size:usize)->Self{
+    pub fn new(file:&Arc<dyn Source>, pos:u64,size:usize)->Self{
         Self{
-            cache: Cached::new((file.clone(),pos,size),Self::produce)
+            cache: Cached::with_weight((file.clone(),pos,size),Self::produce,|_,data|data.len()),
+            cursor: 0
+        }
+    }
+    /// Get the data, reading it from the file on first use.
+    ///
+    /// If the source is in memory (e.g. [source::Mapped]) the data is borrowed from there instead, so nothing is copied.
+    pub fn get(&self)->Result<Payload<'_>>{
+        let (file,pos,size) = &self.cache.source;
+        match file.as_slice() {
+            Some(data) => Self::borrow(data,*pos,*size).map(Payload::Borrowed),
+            None => self.cache.get().map(Payload::Cached)
+        }
+    }
+    /// Get a part of the data without reading (or caching) the rest of it.
+    ///
+    /// - range is relative to the start of the data
+    /// - if the whole data is cached already (or in memory) the part is taken from there
+    pub fn read_range(&self, range:Range<u64>)->Result<Payload<'_>>{
+        let (file,pos,size) = &self.cache.source;
+        if range.start > range.end || range.end > *size as u64 {
+            return Err(Error::InvalidData(format!("Range {range:?} is not within the {size} bytes of data")));
+        }
+        let len = (range.end-range.start) as usize;
+        if let Some(data) = file.as_slice() {
+            return Self::borrow(data,pos+range.start,len).map(Payload::Borrowed);
+        }
+        if let Some(data) = self.cache.get_stored() {
+            return Ok(Payload::Owned(data[range.start as usize..range.end as usize].to_vec()));
+        }
+        Self::read_chunked(file,pos+range.start,len).map(Payload::Owned)
+    }
+    /// true if the data is cached, so [DataFromFile::get] doesn't have to read it
+    pub fn is_cached(&self)->bool{self.cache.is_stored()}
+    /// size of the data in bytes (without reading it)
+    pub fn size(&self)->usize{self.cache.source.2}
+    fn borrow(data:&[u8],pos:u64,size:usize)->Result<&[u8]>{
+        let start = usize::try_from(pos).unwrap_or(usize::MAX).min(data.len());
+        let available = data.len()-start;
+        if size > available {
+            return Err(Error::Truncated{offset:pos, needed:size as u64, available:available as u64});
         }
+        Ok(&data[start..start+size])
+    }
+    fn produce(source:&(Arc<dyn Source>,u64,usize))->Result<Vec<u8>>{
+        let (file,pos,size) = source;
+        Self::read_chunked(file,*pos,*size)
     }
-    pub fn get(&mut self)->Result<&Vec<u8>>{self.cache.get()}
-    fn produce(source:&(Arc<dyn FileExt>,u64,usize))->Result<Vec<u8>>{
-        let mut buff = vec![0;source.2];
-        source.0.read_exact_at(buff.as_mut_slice(),source.1)?;
+    /// reads the data chunk by chunk, so a corrupt size can't allocate more memory than the file actually has
+    fn read_chunked(file:&Arc<dyn Source>,pos:u64,size:usize)->Result<Vec<u8>>{
+        const CHUNK:usize = 1<<20;
+        let mut buff = Vec::new();
+        while buff.len() < size {
+            let red = buff.len();
+            buff.resize(red+CHUNK.min(size-red),0);
+            match file.read_exact_at(&mut buff[red..],pos+red as u64) {
+                Ok(()) => {}
+                Err(e) if e.kind() == std::io::ErrorKind::UnexpectedEof =>
+                    return Err(Error::Truncated{offset:pos, needed:size as u64, available:red as u64}),
+                Err(e) => return Err(e.into())
+            }
+        }
         Ok(buff)
     }
 }
 
+/// Reading the data as a source of its own (e.g. to parse a file embedded as attachment).
+impl Source for DataFromFile {
+    fn read_at(&self, buf: &mut [u8], offset: u64) -> std::io::Result<usize> {
+        let size = self.size() as u64;
+        if offset >= size {
+            return Ok(0);
+        }
+        let n = buf.len().min((size-offset).min(usize::MAX as u64) as usize);
+        if let Some(data) = self.cache.get_stored() {
+            buf[..n].copy_from_slice(&data[offset as usize..offset as usize+n]);
+            return Ok(n);
+        }
+        let (file,pos,_) = &self.cache.source;
+        file.read_at(&mut buf[..n],pos+offset)
+    }
+    fn len(&self) -> std::io::Result<u64> {Ok(self.size() as u64)}
+}
 
+impl Read for DataFromFile {
+    fn read(&mut self, buf: &mut [u8]) -> std::io::Result<usize> {
+        let red = self.read_at(buf,self.cursor)?;
+        if red == 0 && !buf.is_empty() && self.cursor < self.size() as u64 {
+            return Err(std::io::Error::new(std::io::ErrorKind::UnexpectedEof,"file ended before the data"));
+        }
+        self.cursor += red as u64;
+        Ok(red)
+    }
+}
+
+impl Seek for DataFromFile {
+    fn seek(&mut self, pos: SeekFrom) -> std::io::Result<u64> {
+        let new = match pos {
+            SeekFrom::Start(p) => Some(p),
+            SeekFrom::End(p) => (self.size() as u64).checked_add_signed(p),
+            SeekFrom::Current(p) => self.cursor.checked_add_signed(p)
+        };
+        match new {
+            Some(new) => {
+                self.cursor = new;
+                Ok(new)
+            }
+            None => Err(std::io::Error::new(std::io::ErrorKind::InvalidInput,"invalid seek to a negative or overflowing position"))
+        }
+    }
+}
+
+/// Data from a [DataFromFile], either borrowed from an in-memory source, cached or read just for this.
+#[derive(Debug,Clone)]
+pub enum Payload<'a>{
+    Borrowed(&'a [u8]),
+    Cached(Arc<Vec<u8>>),
+    Owned(Vec<u8>)
+}
+
+impl Deref for Payload<'_> {
+    type Target = [u8];
+    fn deref(&self) -> &[u8] {
+        match self {
+            Payload::Borrowed(data) => data,
+            Payload::Cached(data) => data,
+            Payload::Owned(data) => data
+        }
+    }
+}
+
+impl AsRef<[u8]> for Payload<'_> {
+    fn as_ref(&self) -> &[u8] {self}
+}
+
+impl PartialEq for Payload<'_> {
+    fn eq(&self, other: &Self) -> bool {**self == **other}
+}
+
+impl PartialEq<[u8]> for Payload<'_> {
+    fn eq(&self, other: &[u8]) -> bool {**self == *other}
+}
+
+impl<const N:usize> PartialEq<&[u8;N]> for Payload<'_> {
+    fn eq(&self, other: &&[u8;N]) -> bool {**self == other[..]}
+}
@@ -0,0 +1,214 @@
+use std::fmt::Debug;
+use std::fs::File;
+use std::io::{ErrorKind, Read, Seek, SeekFrom};
+use std::os::unix::fs::FileExt;
+use std::path::Path;
+use std::sync::{Arc, Mutex};
+use memmap2::Mmap;
+
+/// Anything data can be read from at arbitrary positions.
+///
+/// All readers take their data from a shared `Arc<dyn Source>`, so files, memory and
+/// parts of other sources can be used interchangeably.
+pub trait Source: Send+Sync{
+	/// Read into buf starting at pos.
+	///
+	/// - returns the amount of bytes red, which is 0 if pos is at or beyond the end of the source
+	/// - may read less than buf.len() even if there is more data
+	fn read_at(&self, buf:&mut [u8], pos:u64) -> std::io::Result<usize>;
+	/// total length of the source in bytes
+	fn len(&self) -> std::io::Result<u64>;
+	fn is_empty(&self) -> std::io::Result<bool>{Ok(self.len()? == 0)}
+	/// Read exactly buf.len() bytes starting at pos.
+	///
+	/// - fails with [ErrorKind::UnexpectedEof] if the source ends before
+	fn read_exact_at(&self, mut buf:&mut [u8], mut pos:u64) -> std::io::Result<()>{
+		while !buf.is_empty() {
+			match self.read_at(buf, pos) {
+				Ok(0) => return Err(std::io::Error::new(ErrorKind::UnexpectedEof,"failed to fill whole buffer")),
+				Ok(n) => {
+					buf = &mut buf[n..];
+					pos += n as u64;
+				}
+				Err(ref e) if e.kind() == ErrorKind::Interrupted => {} // just try again
+				Err(e) => return Err(e)
+			}
+		}
+		Ok(())
+	}
+	/// The whole source as one slice if it is in memory (or mapped into it).
+	///
+	/// Readers use this to borrow data instead of copying it.
+	fn as_slice(&self) -> Option<&[u8]>{None}
+}
+
+impl Debug for dyn Source{
+	fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+		match self.len() {
+			Ok(len) => write!(f,"Source of {len} bytes"),
+			Err(e) => write!(f,"Source of unknown length ({e})")
+		}
+	}
+}
+
+impl Source for File{
+	fn read_at(&self, buf: &mut [u8], pos: u64) -> std::io::Result<usize> {
+		FileExt::read_at(self,buf,pos)
+	}
+	fn len(&self) -> std::io::Result<u64> {
+		Ok(self.metadata()?.len())
+	}
+}
+
+/// reads from a slice the same way files do (short reads at the end, nothing beyond)
+fn read_slice(data:&[u8], buf:&mut [u8], pos:u64) -> std::io::Result<usize>{
+	let start = usize::try_from(pos).unwrap_or(usize::MAX).min(data.len());
+	let n = buf.len().min(data.len()-start);
+	buf[..n].copy_from_slice(&data[start..start+n]);
+	Ok(n)
+}
+
+impl Source for Vec<u8>{
+	fn read_at(&self, buf: &mut [u8], pos: u64) -> std::io::Result<usize> {read_slice(self,buf,pos)}
+	fn len(&self) -> std::io::Result<u64> {Ok(Vec::len(self) as u64)}
+	fn as_slice(&self) -> Option<&[u8]> {Some(self)}
+}
+
+impl Source for Box<[u8]>{
+	fn read_at(&self, buf: &mut [u8], pos: u64) -> std::io::Result<usize> {read_slice(self,buf,pos)}
+	fn len(&self) -> std::io::Result<u64> {Ok(<[u8]>::len(self) as u64)}
+	fn as_slice(&self) -> Option<&[u8]> {Some(self)}
+}
+
+#[cfg(feature = "bytes")]
+impl Source for bytes::Bytes{
+	fn read_at(&self, buf: &mut [u8], pos: u64) -> std::io::Result<usize> {read_slice(self,buf,pos)}
+	fn len(&self) -> std::io::Result<u64> {Ok(bytes::Bytes::len(self) as u64)}
+	fn as_slice(&self) -> Option<&[u8]> {Some(self)}
+}
+
+/// A file mapped into memory.
+///
+/// Reading from it does not copy any data, [crate::DataFromFile] and [crate::blockbuf::BlockBuf] borrow directly from the mapping.
+/// **The file must not be truncated or modified (by this or any other process) while it is mapped.**
+/// Doing so results in undefined behaviour (most likely a SIGBUS).
+#[derive(Debug)]
+pub struct Mapped(Mmap);
+
+impl Mapped {
+	/// Map the whole file read only.
+	pub fn new(file:&File) -> std::io::Result<Self>{
+		// SAFETY: the mapping is read only, modification of the file is ruled out by the contract documented above
+		let map = unsafe {Mmap::map(file)?};
+		Ok(Mapped(map))
+	}
+	/// Open and map the file at path.
+	pub fn open<P:AsRef<Path>>(path:P) -> std::io::Result<Self>{
+		Self::new(&File::open(path)?)
+	}
+}
+
+impl Source for Mapped{
+	fn read_at(&self, buf: &mut [u8], pos: u64) -> std::io::Result<usize> {read_slice(&self.0,buf,pos)}
+	fn len(&self) -> std::io::Result<u64> {Ok(self.0.len() as u64)}
+	fn as_slice(&self) -> Option<&[u8]> {Some(&self.0)}
+}
+
+/// A part of another source.
+///
+/// Positions are relative to the start of the range and nothing beyond its end can be read.
+#[derive(Debug,Clone)]
+pub struct SubRange{
+	source:Arc<dyn Source>,
+	start:u64,
+	len:u64
+}
+
+impl SubRange {
+	/// The range of len bytes starting at start in source.
+	///
+	/// - the range may go beyond the end of source, it will just end early then
+	pub fn new(source:Arc<dyn Source>, start:u64, len:u64) -> Self{
+		SubRange{source,start,len}
+	}
+}
+
+impl Source for SubRange{
+	fn read_at(&self, buf: &mut [u8], pos: u64) -> std::io::Result<usize> {
+		if pos >= self.len {
+			return Ok(0);
+		}
+		let n = buf.len().min((self.len-pos).min(usize::MAX as u64) as usize);
+		match self.start.checked_add(pos) {
+			Some(pos) => self.source.read_at(&mut buf[..n],pos),
+			None => Ok(0)
+		}
+	}
+	fn len(&self) -> std::io::Result<u64> {
+		let available = self.source.len()?.saturating_sub(self.start);
+		Ok(available.min(self.len))
+	}
+	fn as_slice(&self) -> Option<&[u8]> {
+		let data = self.source.as_slice()?;
+		let start = usize::try_from(self.start).unwrap_or(usize::MAX).min(data.len());
+		let len = usize::try_from(self.len).unwrap_or(usize::MAX).min(data.len()-start);
+		Some(&data[start..start+len])
+	}
+}
+
+/// Data of another source that was read in advance.
+///
+/// Reads within that data are served from memory, everything else is read from the source.
+#[derive(Debug)]
+pub struct Prefetched{
+	source:Arc<dyn Source>,
+	start:u64,
+	data:Vec<u8>
+}
+
+impl Prefetched {
+	/// data was read from source at start
+	pub fn new(source:Arc<dyn Source>, start:u64, data:Vec<u8>) -> Self{
+		Prefetched{source,start,data}
+	}
+	/// the range of the source that was prefetched
+	pub fn range(&self) -> std::ops::Range<u64>{
+		self.start..self.start+self.data.len() as u64
+	}
+}
+
+impl Source for Prefetched{
+	fn read_at(&self, buf: &mut [u8], pos: u64) -> std::io::Result<usize> {
+		if self.range().contains(&pos) {
+			read_slice(&self.data,buf,pos-self.start)
+		} else {
+			self.source.read_at(buf,pos)
+		}
+	}
+	fn len(&self) -> std::io::Result<u64> {self.source.len()}
+}
+
+/// Adapter for anything that implements [Read] and [Seek].
+///
+/// Reads are serialized, as each read has to seek first.
+#[derive(Debug)]
+pub struct ReadSeek<R:Read+Seek>(Mutex<R>);
+
+impl<R:Read+Seek> ReadSeek<R> {
+	pub fn new(reader:R) -> Self{ReadSeek(Mutex::new(reader))}
+	pub fn into_inner(self) -> R{
+		self.0.into_inner().unwrap_or_else(|e|e.into_inner())
+	}
+}
+
+impl<R:Read+Seek+Send> Source for ReadSeek<R>{
+	fn read_at(&self, buf: &mut [u8], pos: u64) -> std::io::Result<usize> {
+		let mut reader = self.0.lock().unwrap_or_else(|e|e.into_inner());
+		reader.seek(SeekFrom::Start(pos))?;
+		reader.read(buf)
+	}
+	fn len(&self) -> std::io::Result<u64> {
+		let mut reader = self.0.lock().unwrap_or_else(|e|e.into_inner());
+		reader.seek(SeekFrom::End(0))
+	}
+}
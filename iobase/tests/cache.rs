@@ -0,0 +1,93 @@
+use std::sync::Arc;
+use std::sync::atomic::{AtomicUsize, Ordering};
+use iobase::basic::Cached;
+use iobase::cache::{CacheManager, CacheStats};
+use iobase::Error;
+
+/// a cache whose value weighs as much as its source says
+fn cache(manager:&Arc<CacheManager>, weight:usize) -> Cached<usize,String> {
+	Cached::managed_by(weight, |w|Ok(format!("value {w}")), |w,_|*w, manager)
+}
+
+#[test]
+fn least_recently_used_is_evicted() {
+	let manager = Arc::new(CacheManager::new(100));
+	let a = cache(&manager, 50);
+	let b = cache(&manager, 40);
+	assert_eq!(*a.get().unwrap(), "value 50");
+	let held = b.get().unwrap();
+	assert_eq!(*a.get().unwrap(), "value 50");
+	assert_eq!(manager.stats(), CacheStats{hits:1, misses:2, evictions:0, entries:2, used:90, budget:100});
+
+	// b is the least recently used
+	let c = cache(&manager, 30);
+	c.get().unwrap();
+	assert!(a.is_stored() && !b.is_stored() && c.is_stored());
+	assert_eq!(manager.stats(), CacheStats{hits:1, misses:3, evictions:1, entries:2, used:80, budget:100});
+	// evicted values that are still in use stay valid and are produced again when needed
+	assert_eq!(*held, "value 40");
+	assert_eq!(*b.get().unwrap(), "value 40");
+	assert_eq!(manager.stats().misses, 4);
+	assert!(!a.is_stored());
+
+	drop(b);
+	assert_eq!(manager.stats().used, 30);
+	manager.set_budget(0);
+	assert!(!c.is_stored());
+	assert_eq!(manager.stats(), CacheStats{hits:1, misses:4, evictions:3, entries:0, used:0, budget:0});
+}
+
+#[test]
+fn oversized_and_failing_values() {
+	let manager = Arc::new(CacheManager::new(100));
+	let big = cache(&manager, 1000);
+	assert_eq!(*big.get().unwrap(), "value 1000");
+	assert!(!big.is_stored());
+	assert_eq!(manager.stats().used, 0);
+
+	let failing:Cached<(),String> = Cached::managed_by((), |_|Err(Error::NotFound("value".into())), |_,_|1, &manager);
+	assert!(failing.get().unwrap_err().is_not_found());
+	assert_eq!(manager.stats().entries, 0);
+}
+
+#[test]
+fn shared_between_threads() {
+	let manager = Arc::new(CacheManager::new(1000));
+	let caches:Vec<_> = (1..=20).map(|w|cache(&manager, w*10)).collect();
+	std::thread::scope(|s| {
+		for t in 0..8 {
+			let caches = &caches;
+			s.spawn(move || {
+				for i in 0..1000 {
+					let c = &caches[(i*7+t)%caches.len()];
+					assert_eq!(*c.get().unwrap(), format!("value {}", c.source));
+				}
+			});
+		}
+	});
+	let stats = manager.stats();
+	assert_eq!(stats.hits+stats.misses, 8000);
+	assert!(stats.used <= 1000);
+	assert_eq!(stats.used, caches.iter().filter(|c|c.is_stored()).map(|c|c.source).sum::<usize>());
+}
+
+#[test]
+fn produced_once() {
+	// the source counts how often the value was produced, the first try fails
+	let manager = Arc::new(CacheManager::new(1000));
+	let once:Cached<AtomicUsize,usize> = Cached::managed_by(AtomicUsize::new(0), |count| {
+		std::thread::sleep(std::time::Duration::from_millis(10)); // slow enough for the threads to meet
+		match count.fetch_add(1, Ordering::SeqCst) {
+			0 => Err(Error::NotFound("value".into())),
+			n => Ok(n)
+		}
+	}, |_,_|1, &manager);
+	assert!(once.get().is_err());
+	assert!(!once.is_stored());
+	std::thread::scope(|s| {
+		for _ in 0..8 {
+			s.spawn(|| assert_eq!(*once.get().unwrap(), 1));
+		}
+	});
+	assert_eq!(once.source.load(Ordering::SeqCst), 2);
+}
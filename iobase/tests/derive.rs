@@ -0,0 +1,44 @@
+use std::sync::Arc;
+use iobase::blockbuf::{BlockBuf, BlockRead};
+use iobase::blockwrite::{BlockWrite, BlockWriter};
+use iobase::source::Source;
+use iobase::Endian;
+
+#[derive(Debug,PartialEq,BlockRead,BlockWrite)]
+struct Element{
+	#[block(ascii = 4)]
+	name:String,
+	value:i16
+}
+
+#[derive(Debug,PartialEq,BlockRead,BlockWrite)]
+struct Block{
+	version:[u16;2],
+	#[block(offset = 8)]
+	size:u64,
+	#[block(count = "u8", elements_at = 24)]
+	elements:Vec<Element>,
+	#[block(nested)]
+	last:Element
+}
+
+#[test]
+fn round_trip() {
+	let block = Block{
+		version:[1,2],
+		size:0x0102030405060708,
+		elements:vec![Element{name:"X".into(),value:-1},Element{name:"ABCD".into(),value:7}],
+		last:Element{name:"Z".into(),value:3}
+	};
+	let mut writer = BlockWriter::new(Endian::Big);
+	writer.write(&block).unwrap();
+	let data = writer.into_inner();
+	assert_eq!(data.len(), 24+3*6);
+	assert_eq!(&data[..16], &[0,1,0,2,0,0,0,0,1,2,3,4,5,6,7,8]);
+	assert_eq!(data[16], 2); // the count, followed by padding up to the elements
+	assert_eq!(&data[24..30], b"X\0\0\0\xff\xff");
+
+	let source:Arc<dyn Source> = Arc::new(data);
+	let mut buffer = BlockBuf::new(source, 0, Endian::Big).unwrap();
+	assert_eq!(Block::read(&mut buffer).unwrap(), block);
+}
@@ -0,0 +1,99 @@
+use std::io::Cursor;
+use std::sync::Arc;
+use iobase::blockbuf::BlockBuf;
+use iobase::source::{Mapped, ReadSeek, Source, SubRange};
+use iobase::{DataFromFile, Endian, Error};
+
+fn check(source:&dyn Source, expected:&[u8]) {
+	assert_eq!(source.len().unwrap(), expected.len() as u64);
+	let mut buf = vec![0; expected.len()];
+	source.read_exact_at(&mut buf, 0).unwrap();
+	assert_eq!(buf, expected);
+	// short reads at the end, nothing beyond
+	let mut buf = [0; 4];
+	assert_eq!(source.read_at(&mut buf, expected.len() as u64 - 2).unwrap(), 2);
+	assert_eq!(source.read_at(&mut buf, expected.len() as u64 + 10).unwrap(), 0);
+	assert!(source.read_exact_at(&mut buf, expected.len() as u64 - 2).is_err());
+}
+
+#[test]
+fn sources() {
+	let data:Vec<u8> = (0..100).collect();
+	check(&data, &data);
+	check(&data.clone().into_boxed_slice(), &data);
+	check(&ReadSeek::new(Cursor::new(data.clone())), &data);
+
+	let path = std::path::Path::new(env!("CARGO_TARGET_TMPDIR")).join("source.bin");
+	std::fs::write(&path, &data).unwrap();
+	check(&std::fs::File::open(&path).unwrap(), &data);
+	check(&Mapped::open(&path).unwrap(), &data);
+
+	let shared:Arc<dyn Source> = Arc::new(data.clone());
+	check(&SubRange::new(shared.clone(), 10, 20), &data[10..30]);
+	// ranges going beyond their source just end early
+	check(&SubRange::new(shared.clone(), 90, 20), &data[90..]);
+	check(&SubRange::new(Arc::new(SubRange::new(shared, 10, 50)), 5, 10), &data[15..25]);
+}
+
+#[test]
+fn block_buf_from_sub_range() {
+	let data:Vec<u8> = (0..=255).collect();
+	let range:Arc<dyn Source> = Arc::new(SubRange::new(Arc::new(data), 16, 8));
+	let mut buffer = BlockBuf::new(range, 0, Endian::Big).unwrap();
+	assert_eq!(buffer.get_scalar::<u32>().unwrap(), 0x10111213);
+	assert_eq!(buffer.get_array::<2,u16>().unwrap(), [0x1415, 0x1617]);
+	assert!(matches!(buffer.get_scalar::<u8>(), Err(Error::Truncated{offset:8, needed:1, available:0})));
+}
+
+#[test]
+fn borrowed_from_memory() {
+	let data:Vec<u8> = (0..=255).collect();
+	let path = std::path::Path::new(env!("CARGO_TARGET_TMPDIR")).join("borrowed.bin");
+	std::fs::write(&path, &data).unwrap();
+	let mapped:Arc<dyn Source> = Arc::new(Mapped::open(&path).unwrap());
+	let range:Arc<dyn Source> = Arc::new(SubRange::new(mapped.clone(), 16, 32));
+	assert_eq!(range.as_slice(), Some(&data[16..48]));
+
+	// data is handed out directly from the mapping
+	let payload = DataFromFile::new(&mapped, 100, 10);
+	assert_eq!(payload.get().unwrap().as_ptr(), mapped.as_slice().unwrap()[100..].as_ptr());
+	assert!(matches!(DataFromFile::new(&range, 30, 10).get(), Err(Error::Truncated{offset:30, needed:10, available:2})));
+
+	// buffers behave the same no matter if they borrow or read
+	let file:Arc<dyn Source> = Arc::new(std::fs::File::open(&path).unwrap());
+	for source in [mapped, file] {
+		let mut buffer = BlockBuf::new(source, 4, Endian::Little).unwrap();
+		buffer.limit_to(12).unwrap();
+		assert_eq!(buffer.get_scalar::<u32>().unwrap(), 0x07060504);
+		let mut spliced = buffer.splice(4).unwrap();
+		assert_eq!(spliced.get_array::<2,u16>().unwrap(), [0x0908, 0x0b0a]);
+		assert_eq!(buffer.get_utf8(2).unwrap().as_bytes(), [12, 13]);
+		let e = buffer.get_scalar::<u32>();
+		assert!(matches!(e, Err(Error::Truncated{offset:14, needed:4, available:2})), "{e:?}");
+		assert_eq!(buffer.get_scalar::<u16>().unwrap(), 0x0f0e);
+	}
+}
+
+#[test]
+fn random_access() {
+	let data:Vec<u8> = (0..=255).collect();
+	let path = std::path::Path::new(env!("CARGO_TARGET_TMPDIR")).join("random_access.bin");
+	std::fs::write(&path, &data).unwrap();
+	let file:Arc<dyn Source> = Arc::new(std::fs::File::open(&path).unwrap());
+	for source in [Arc::new(data) as Arc<dyn Source>, file] {
+		let mut buffer = BlockBuf::new(source, 16, Endian::Big).unwrap();
+		buffer.limit_to(32).unwrap();
+		// peeking doesn't drain anything
+		assert_eq!(buffer.peek(|b|b.get_scalar::<u16>()).unwrap(), 0x1011);
+		assert_eq!(buffer.position(), 0);
+		assert_eq!(buffer.get_scalar::<u32>().unwrap(), 0x10111213);
+		// reading at a position goes back to where we were, even on errors
+		assert_eq!(buffer.at(30, |b|b.get_scalar::<u16>()).unwrap(), 0x2e2f);
+		assert!(matches!(buffer.at(31, |b|b.get_scalar::<u16>()), Err(Error::Truncated{offset:47, needed:2, available:1})));
+		assert_eq!(buffer.position(), 4);
+		assert!(buffer.skip_to(2).is_err());
+		assert_eq!(buffer.seek_to(2).unwrap().get_scalar::<u16>().unwrap(), 0x1213);
+		assert_eq!(buffer.seek_to(8).unwrap().get_scalar::<u8>().unwrap(), 0x18);
+		assert_eq!(buffer.rewind().get_scalar::<u8>().unwrap(), 0x10);
+	}
+}
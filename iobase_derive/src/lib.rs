@@ -0,0 +1,172 @@
+//! Derive macros for `iobase::blockbuf::BlockRead` and `iobase::blockwrite::BlockWrite` (re-exported there).
+//!
+//! Fields are read (and written) in the order they are declared.
+//! How a field is stored is derived from its type and can be changed with `#[block(...)]`:
+//! - `offset = N` the field starts at byte N of the block (padding is skipped when reading and written as zeros)
+//! - `ascii = N` a String stored as N bytes (null padded)
+//! - `guid` an uuid stored as 16 bytes (the crate using it must depend on `uuid`)
+//! - `count = "T"` a `Vec` of `BlockRead` elements that is preceded by its length stored as T
+//! - `elements_at = N` the elements of a counted `Vec` start at byte N of the block (after the count)
+//! - `nested` a field that is a `BlockRead` itself
+//! - `with = "module"` use `module::read(&mut BlockBuf)->Result<T>` and `module::write(&T,&mut BlockWriter)->Result<()>`
+//!
+//! Everything else must be a scalar or an array of scalars.
+use proc_macro::TokenStream;
+use quote::quote;
+use syn::{parse_macro_input, Data, DeriveInput, Fields, Ident, LitInt, LitStr, Path, Type};
+
+enum Kind{
+	Scalar,
+	Array,
+	Ascii(LitInt),
+	Guid,
+	Counted{count:Type, elements_at:Option<LitInt>},
+	Nested,
+	With(Path)
+}
+
+struct Field{
+	name:Ident,
+	offset:Option<LitInt>,
+	kind:Kind
+}
+
+fn parse_fields(input:&DeriveInput) -> syn::Result<Vec<Field>>{
+	let Data::Struct(data) = &input.data else {
+		return Err(syn::Error::new_spanned(input,"BlockRead and BlockWrite can only be derived for structs"));
+	};
+	let Fields::Named(fields) = &data.fields else {
+		return Err(syn::Error::new_spanned(input,"BlockRead and BlockWrite can only be derived for structs with named fields"));
+	};
+	fields.named.iter().map(|f|{
+		let name = f.ident.clone().expect("named field");
+		let mut offset = None;
+		let mut kind = match f.ty {
+			Type::Array(_) => Kind::Array,
+			_ => Kind::Scalar
+		};
+		let mut count = None;
+		let mut elements_at = None;
+		for attr in f.attrs.iter().filter(|a|a.path().is_ident("block")) {
+			attr.parse_nested_meta(|meta|{
+				if meta.path.is_ident("offset") {
+					offset = Some(meta.value()?.parse()?);
+				} else if meta.path.is_ident("ascii") {
+					kind = Kind::Ascii(meta.value()?.parse()?);
+				} else if meta.path.is_ident("guid") {
+					kind = Kind::Guid;
+				} else if meta.path.is_ident("count") {
+					count = Some(meta.value()?.parse::<LitStr>()?.parse()?);
+				} else if meta.path.is_ident("elements_at") {
+					elements_at = Some(meta.value()?.parse()?);
+				} else if meta.path.is_ident("nested") {
+					kind = Kind::Nested;
+				} else if meta.path.is_ident("with") {
+					kind = Kind::With(meta.value()?.parse::<LitStr>()?.parse()?);
+				} else {
+					return Err(meta.error("unknown block attribute"));
+				}
+				Ok(())
+			})?;
+		}
+		match (count,elements_at) {
+			(Some(count),elements_at) => kind = Kind::Counted{count,elements_at},
+			(None,Some(e)) => return Err(syn::Error::new_spanned(e,"elements_at needs a count")),
+			(None,None) => {}
+		}
+		Ok(Field{name,offset,kind})
+	}).collect()
+}
+
+/// `#[derive(BlockRead)]`, see the crate documentation for the attributes
+#[proc_macro_derive(BlockRead, attributes(block))]
+pub fn derive_block_read(input:TokenStream) -> TokenStream{
+	let input = parse_macro_input!(input as DeriveInput);
+	let fields = match parse_fields(&input) {
+		Ok(fields) => fields,
+		Err(e) => return e.to_compile_error().into()
+	};
+	let reads = fields.iter().map(|f|{
+		let name = &f.name;
+		let skip = f.offset.as_ref().map(|o|quote!{buffer.skip_to(#o)?;});
+		let read = match &f.kind {
+			Kind::Scalar => quote!{buffer.get_scalar()?},
+			Kind::Array => quote!{buffer.get_array()?},
+			Kind::Ascii(len) => quote!{buffer.get_ascii::<#len>()?},
+			Kind::Guid => quote!{::uuid::Uuid::from_bytes(buffer.get_array()?)},
+			Kind::Nested => quote!{buffer.read()?},
+			Kind::With(module) => quote!{#module::read(buffer)?},
+			Kind::Counted{count,elements_at} => {
+				let what = format!("{name} count");
+				let skip = elements_at.as_ref().map(|e|quote!{buffer.skip_to(#e)?;});
+				quote!{{
+					let count:#count = buffer.get_scalar()?;
+					let count = usize::try_from(count)
+						.map_err(|_|::iobase::Error::InvalidData(format!("Invalid {} {}",#what,count)))?;
+					#skip
+					buffer.read_vec(count)?
+				}}
+			}
+		};
+		quote!{
+			#skip
+			let #name = #read;
+		}
+	});
+	let names = fields.iter().map(|f|&f.name);
+	let ident = &input.ident;
+	let (impl_generics, ty_generics, where_clause) = input.generics.split_for_impl();
+	quote!{
+		impl #impl_generics ::iobase::blockbuf::BlockRead for #ident #ty_generics #where_clause {
+			#[allow(non_snake_case)]
+			fn read(buffer:&mut ::iobase::blockbuf::BlockBuf) -> ::iobase::Result<Self> {
+				#(#reads)*
+				Ok(#ident{#(#names),*})
+			}
+		}
+	}.into()
+}
+
+/// `#[derive(BlockWrite)]`, the counterpart of `#[derive(BlockRead)]` using the same attributes
+#[proc_macro_derive(BlockWrite, attributes(block))]
+pub fn derive_block_write(input:TokenStream) -> TokenStream{
+	let input = parse_macro_input!(input as DeriveInput);
+	let fields = match parse_fields(&input) {
+		Ok(fields) => fields,
+		Err(e) => return e.to_compile_error().into()
+	};
+	let writes = fields.iter().map(|f|{
+		let value = &f.name;
+		let pad = f.offset.as_ref().map(|o|quote!{buffer.pad_to(#o)?;});
+		let write = match &f.kind {
+			Kind::Scalar => quote!{buffer.put_scalar(self.#value);},
+			Kind::Array => quote!{buffer.put_array(&self.#value);},
+			Kind::Ascii(len) => quote!{buffer.put_ascii::<#len>(&self.#value)?;},
+			Kind::Guid => quote!{buffer.put_bytes(self.#value.as_bytes());},
+			Kind::Nested => quote!{buffer.write(&self.#value)?;},
+			Kind::With(module) => quote!{#module::write(&self.#value,buffer)?;},
+			Kind::Counted{count,elements_at} => {
+				let pad = elements_at.as_ref().map(|e|quote!{buffer.pad_to(#e)?;});
+				quote!{
+					buffer.put_scalar(self.#value.len() as #count);
+					#pad
+					buffer.write_vec(&self.#value)?;
+				}
+			}
+		};
+		quote!{
+			#pad
+			#write
+		}
+	});
+	let ident = &input.ident;
+	let (impl_generics, ty_generics, where_clause) = input.generics.split_for_impl();
+	quote!{
+		impl #impl_generics ::iobase::blockwrite::BlockWrite for #ident #ty_generics #where_clause {
+			fn write(&self, buffer:&mut ::iobase::blockwrite::BlockWriter) -> ::iobase::Result<()> {
+				#(#writes)*
+				Ok(())
+			}
+		}
+	}.into()
+}
@@ -0,0 +1,66 @@
+#[path = "../tests/common/mod.rs"]
+mod common;
+
+use std::hint::black_box;
+use criterion::{criterion_group, criterion_main, BenchmarkId, Criterion, Throughput};
+use euclid::Rect;
+use pyramid::{PixelSpace, Plane, Pyramid, Tile};
+use common::Synthetic;
+
+const TILE:i32 = 512;
+const OVERLAP:i32 = 26;
+
+/// a directory of count overlapping tiles in a square mosaic, spread over the given number of channels
+fn mosaic(count:usize, channels:i32) -> Vec<Box<dyn Tile>> {
+	let per_plane = count/channels as usize;
+	let columns = (per_plane as f64).sqrt().ceil() as usize;
+	(0..channels).flat_map(|c|(0..per_plane).map(move |m|{
+		let (x, y) = ((m%columns) as i32, (m/columns) as i32);
+		Synthetic::new(x*(TILE-OVERLAP), y*(TILE-OVERLAP), TILE, TILE).plane(Plane{c, z:0, t:0}).id(m as i32).boxed()
+	})).collect()
+}
+
+/// viewports of 2000x1000 pixels on a diagonal through the mosaic
+fn viewports(pyramid:&Pyramid) -> Vec<Rect<i32, PixelSpace>> {
+	let frame = pyramid.frame().unwrap();
+	(0..100).map(|n|euclid::rect(frame.width()*n/100, frame.height()*n/100, 2000, 1000)).collect()
+}
+
+fn build(c:&mut Criterion) {
+	let mut group = c.benchmark_group("build");
+	group.sample_size(10);
+	for count in [10_000, 100_000] {
+		group.throughput(Throughput::Elements(count as u64));
+		group.bench_function(BenchmarkId::from_parameter(count), |b| b.iter(|| {
+			let pyramid = Pyramid::new(mosaic(count, 4), 2);
+			// the index is built at the first lookup
+			black_box(pyramid.intersecting(0, Plane::default(), euclid::rect(0, 0, 1, 1)).count());
+		}));
+	}
+	group.finish();
+}
+
+fn lookup(c:&mut Criterion) {
+	let mut group = c.benchmark_group("lookup");
+	for count in [10_000, 100_000] {
+		let pyramid = Pyramid::new(mosaic(count, 4), 2);
+		let viewports = viewports(&pyramid);
+		let plane = Plane{c:2, z:0, t:0};
+		pyramid.intersecting(0, plane, viewports[0]).count();
+		group.throughput(Throughput::Elements(viewports.len() as u64));
+		group.bench_function(BenchmarkId::new("index", count), |b| b.iter(|| {
+			for area in &viewports {
+				black_box(pyramid.intersecting(0, plane, *area).count());
+			}
+		}));
+		group.bench_function(BenchmarkId::new("linear", count), |b| b.iter(|| {
+			for area in &viewports {
+				black_box(pyramid.tiles(0, plane).filter(|t|t.frame().intersects(area)).count());
+			}
+		}));
+	}
+	group.finish();
+}
+
+criterion_group!(benches, build, lookup);
+criterion_main!(benches);
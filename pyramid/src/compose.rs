@@ -0,0 +1,203 @@
+//! Composing tiles of the same plane into one image.
+use euclid::{Rect, Size2D};
+use ndarray::Array2;
+use num_complex::Complex;
+use iobase::{Error, Result};
+use crate::downsample::Mean;
+use crate::{Element, Pixel, PixelSpace, Tile};
+
+/// How overlapping tiles are combined.
+#[derive(Debug,Clone,Copy,Default,PartialEq,Eq)]
+pub enum Blend{
+	/// the tile with the highest ordering id is on top
+	#[default]
+	LastWins,
+	/// the tile with the lowest ordering id is on top
+	FirstWins,
+	/// the highest value of all tiles (per component, complex values by their magnitude)
+	Max,
+	/// the mean of all tiles
+	Mean,
+	/// the mean of all tiles, weighted by the distance to their border, so seams fade linearly across the overlap
+	Feather
+}
+
+/// Samples that can be blended.
+pub trait Combine:Mean{
+	/// running weighted sums of the components
+	type Sum:Copy+Default;
+	/// add the value with the given weight to sum
+	fn add_to(self, sum:&mut Self::Sum, weight:f64);
+	/// the weighted mean of everything added to sum, weight being the sum of all weights (zero if there are none)
+	fn mean_of(sum:Self::Sum, weight:f64) -> Self;
+	/// the weighted mean of all values (zero if there are none)
+	fn weighted_mean(values:impl Iterator<Item=(Self,f64)>) -> Self{
+		let (mut sum,mut weight) = (Self::Sum::default(),0.0);
+		for (v,w) in values {
+			v.add_to(&mut sum,w);
+			weight += w;
+		}
+		Self::mean_of(sum,weight)
+	}
+	fn maximum(self, other:Self) -> Self;
+	fn minimum(self, other:Self) -> Self;
+}
+
+macro_rules! integer_combine {
+	($($t:ty),*) => {$(
+		impl Combine for $t{
+			type Sum = f64;
+			fn add_to(self, sum:&mut f64, weight:f64){*sum += self as f64*weight}
+			fn mean_of(sum:f64, weight:f64) -> Self{
+				if weight > 0.0 {(sum/weight).round() as $t} else {0}
+			}
+			fn maximum(self, other:Self) -> Self{self.max(other)}
+			fn minimum(self, other:Self) -> Self{self.min(other)}
+		}
+	)*}
+}
+integer_combine!(u8,u16,u32,u64);
+
+impl Combine for f32{
+	type Sum = f64;
+	fn add_to(self, sum:&mut f64, weight:f64){*sum += self as f64*weight}
+	fn mean_of(sum:f64, weight:f64) -> Self{
+		if weight > 0.0 {(sum/weight) as f32} else {0.0}
+	}
+	fn maximum(self, other:Self) -> Self{self.max(other)}
+	fn minimum(self, other:Self) -> Self{self.min(other)}
+}
+
+impl Combine for Complex<f32>{
+	type Sum = (f64,f64);
+	fn add_to(self, sum:&mut (f64,f64), weight:f64){
+		self.re.add_to(&mut sum.0,weight);
+		self.im.add_to(&mut sum.1,weight);
+	}
+	fn mean_of(sum:(f64,f64), weight:f64) -> Self{
+		Complex::new(f32::mean_of(sum.0,weight),f32::mean_of(sum.1,weight))
+	}
+	fn maximum(self, other:Self) -> Self{
+		if other.norm_sqr() > self.norm_sqr() {other} else {self}
+	}
+	fn minimum(self, other:Self) -> Self{
+		if other.norm_sqr() < self.norm_sqr() {other} else {self}
+	}
+}
+
+impl<A:Combine,B:Combine,C:Combine> Combine for (A,B,C){
+	type Sum = (A::Sum,B::Sum,C::Sum);
+	fn add_to(self, sum:&mut Self::Sum, weight:f64){
+		self.0.add_to(&mut sum.0,weight);
+		self.1.add_to(&mut sum.1,weight);
+		self.2.add_to(&mut sum.2,weight);
+	}
+	fn mean_of(sum:Self::Sum, weight:f64) -> Self{
+		(A::mean_of(sum.0,weight),B::mean_of(sum.1,weight),C::mean_of(sum.2,weight))
+	}
+	fn maximum(self, other:Self) -> Self{
+		(self.0.maximum(other.0),self.1.maximum(other.1),self.2.maximum(other.2))
+	}
+	fn minimum(self, other:Self) -> Self{
+		(self.0.minimum(other.0),self.1.minimum(other.1),self.2.minimum(other.2))
+	}
+}
+
+impl<A:Combine,B:Combine,C:Combine,D:Combine> Combine for (A,B,C,D){
+	type Sum = (A::Sum,B::Sum,C::Sum,D::Sum);
+	fn add_to(self, sum:&mut Self::Sum, weight:f64){
+		self.0.add_to(&mut sum.0,weight);
+		self.1.add_to(&mut sum.1,weight);
+		self.2.add_to(&mut sum.2,weight);
+		self.3.add_to(&mut sum.3,weight);
+	}
+	fn mean_of(sum:Self::Sum, weight:f64) -> Self{
+		(A::mean_of(sum.0,weight),B::mean_of(sum.1,weight),C::mean_of(sum.2,weight),D::mean_of(sum.3,weight))
+	}
+	fn maximum(self, other:Self) -> Self{
+		(self.0.maximum(other.0),self.1.maximum(other.1),self.2.maximum(other.2),self.3.maximum(other.3))
+	}
+	fn minimum(self, other:Self) -> Self{
+		(self.0.minimum(other.0),self.1.minimum(other.1),self.2.minimum(other.2),self.3.minimum(other.3))
+	}
+}
+
+/// a tile with its pixels
+struct Part{
+	frame:Rect<i32, PixelSpace>,
+	stored:Size2D<u32, PixelSpace>,
+	pixel:Pixel
+}
+
+/// Compose tiles into one image of area.
+///
+/// - area is given in full resolution pixels, the image has a resolution of 1/scale (the scale of the tiles' level)
+/// - each image pixel takes the tile pixels at its center, so tiles may even have different scales
+/// - overlapping tiles are combined as given by blend, pixels not covered by any tile are zero
+/// - returns None if there are no tiles, all tiles must have the same pixel type
+pub fn compose<'a>(tiles:impl IntoIterator<Item=&'a dyn Tile>, area:Rect<i32, PixelSpace>, scale:f64, blend:Blend) -> Result<Option<Pixel>>{
+	let mut tiles:Vec<&dyn Tile> = tiles.into_iter().collect();
+	tiles.sort_by_key(|t|t.ordering_id());
+	let mut parts = Vec::with_capacity(tiles.len());
+	for tile in tiles {
+		parts.push(Part{frame:tile.frame(), stored:tile.stored_size(), pixel:tile.pixel()?});
+	}
+	let Some(first) = parts.first() else {return Ok(None)};
+	let scale = if scale > 0.0 {scale} else {1.0};
+	let size = ((area.size.width.max(0) as f64/scale).ceil() as usize,(area.size.height.max(0) as f64/scale).ceil() as usize);
+	with_pixel!(&first.pixel, a => compose_typed(a,&parts,area,size,scale,blend)).map(Some)
+}
+
+fn compose_typed<T:Element+Combine>(_:&Array2<T>, parts:&[Part], area:Rect<i32, PixelSpace>, size:(usize,usize), scale:f64, blend:Blend) -> Result<Pixel>{
+	let (width,height) = size;
+	let mut out:Array2<T> = Array2::default((height,width));
+	let mut covered:Array2<bool> = Array2::default((height,width));
+	// running weighted sums for Mean and Feather
+	let mut sums:Array2<(T::Sum,f64)> = match blend {
+		Blend::Mean | Blend::Feather => Array2::from_elem((height,width),Default::default()),
+		_ => Array2::from_shape_vec((0,0),vec![]).expect("an empty array has no shape errors")
+	};
+	for part in parts {
+		let pixels = T::array(&part.pixel)
+			.ok_or(Error::InvalidData("Tiles of different pixel types can't be composed".to_string()))?;
+		let (frame,stored) = (part.frame,part.stored);
+		if frame.is_empty() || stored.is_empty() {
+			continue;
+		}
+		// image pixels whose center might be inside the frame
+		let range = |start:i32,end:i32,origin:i32,len:usize|{
+			let first = (((start-origin) as f64/scale).floor().max(0.0) as usize).min(len);
+			let last = (((end-origin) as f64/scale).ceil().max(0.0) as usize).min(len);
+			first..last
+		};
+		for j in range(frame.min_y(),frame.max_y(),area.min_y(),height) {
+			let cy = area.min_y() as f64+(j as f64+0.5)*scale;
+			let v = (cy-frame.min_y() as f64)*stored.height as f64/frame.size.height as f64;
+			if v < 0.0 || v >= stored.height as f64 {continue}
+			for i in range(frame.min_x(),frame.max_x(),area.min_x(),width) {
+				let cx = area.min_x() as f64+(i as f64+0.5)*scale;
+				let u = (cx-frame.min_x() as f64)*stored.width as f64/frame.size.width as f64;
+				if u < 0.0 || u >= stored.width as f64 {continue}
+				let Some(&value) = pixels.get((v as usize,u as usize)) else {continue};
+				match blend {
+					Blend::LastWins => out[(j,i)] = value,
+					Blend::FirstWins => if !covered[(j,i)] {out[(j,i)] = value},
+					Blend::Max => out[(j,i)] = if covered[(j,i)] {out[(j,i)].maximum(value)} else {value},
+					Blend::Mean | Blend::Feather => {
+						// Feather weighs by the distance to the closest border of the tile, in stored pixels
+						let weight = if blend == Blend::Mean {1.0}
+							else {u.min(stored.width as f64-u).min(v).min(stored.height as f64-v).max(f64::EPSILON)};
+						let (sum,total) = &mut sums[(j,i)];
+						value.add_to(sum,weight);
+						*total += weight;
+					}
+				}
+				covered[(j,i)] = true;
+			}
+		}
+	}
+	if !sums.is_empty() {
+		out.zip_mut_with(&sums,|o,(sum,weight)|if *weight > 0.0 {*o = T::mean_of(*sum,*weight)});
+	}
+	Ok(T::into_pixel(out))
+}
@@ -0,0 +1,107 @@
+//! Downsampling by box averaging, and tiles computed that way.
+use std::sync::Arc;
+use euclid::{Rect, Size2D};
+use ndarray::{s, Array2};
+use num_complex::Complex;
+use iobase::basic::Cached;
+use iobase::Result;
+use crate::{Pixel, PixelSpace, Plane, Tile};
+
+/// Samples that can be averaged.
+pub trait Mean:Copy{
+	/// the mean of all values (must not be empty), integers are rounded to the closest value
+	fn mean(values:impl Iterator<Item=Self>+Clone) -> Self;
+}
+
+macro_rules! integer_mean {
+	($($t:ty),*) => {$(
+		impl Mean for $t{
+			fn mean(values:impl Iterator<Item=Self>+Clone) -> Self{
+				let (sum,n) = values.fold((0u128,0u128),|(s,n),v|(s+v as u128,n+1));
+				((sum+n/2)/n.max(1)) as $t
+			}
+		}
+	)*}
+}
+integer_mean!(u8,u16,u32,u64);
+
+impl Mean for f32{
+	fn mean(values:impl Iterator<Item=Self>+Clone) -> Self{
+		let (sum,n) = values.fold((0f64,0usize),|(s,n),v|(s+v as f64,n+1));
+		(sum/n.max(1) as f64) as f32
+	}
+}
+
+impl<T:Mean> Mean for Complex<T>{
+	fn mean(values:impl Iterator<Item=Self>+Clone) -> Self{
+		Complex::new(T::mean(values.clone().map(|v|v.re)),T::mean(values.map(|v|v.im)))
+	}
+}
+
+impl<A:Mean,B:Mean,C:Mean> Mean for (A,B,C){
+	fn mean(values:impl Iterator<Item=Self>+Clone) -> Self{
+		(A::mean(values.clone().map(|v|v.0)),B::mean(values.clone().map(|v|v.1)),C::mean(values.map(|v|v.2)))
+	}
+}
+
+impl<A:Mean,B:Mean,C:Mean,D:Mean> Mean for (A,B,C,D){
+	fn mean(values:impl Iterator<Item=Self>+Clone) -> Self{
+		(
+			A::mean(values.clone().map(|v|v.0)),B::mean(values.clone().map(|v|v.1)),
+			C::mean(values.clone().map(|v|v.2)),D::mean(values.map(|v|v.3))
+		)
+	}
+}
+
+/// Shrink an array by factor, each new pixel being the mean of a factor x factor block.
+///
+/// - if the size isn't a multiple of factor, the partial blocks at the right and bottom border are averaged over the pixels they have
+pub fn box_average<T:Mean>(array:&Array2<T>, factor:usize) -> Array2<T>{
+	let factor = factor.max(1);
+	let (height,width) = array.dim();
+	Array2::from_shape_fn((height.div_ceil(factor),width.div_ceil(factor)),|(y,x)|{
+		let block = array.slice(s![y*factor..((y+1)*factor).min(height),x*factor..((x+1)*factor).min(width)]);
+		T::mean(block.iter().copied())
+	})
+}
+
+impl Pixel {
+	/// Shrink the pixels by factor (see [box_average]).
+	pub fn downsample(&self, factor:usize) -> Pixel{
+		map_pixel!(self, a => box_average(a,factor))
+	}
+}
+
+/// A tile computed by downsampling another tile, see [crate::Pyramid::generate].
+///
+/// The pixels are computed when they are needed and kept in the global cache.
+pub struct Downsampled{
+	cache:Cached<(Arc<dyn Tile>,usize),Pixel>
+}
+
+impl Downsampled {
+	pub fn new(source:Arc<dyn Tile>, factor:usize) -> Self{
+		Downsampled{cache:Cached::with_weight((source,factor.max(1)),Self::produce,|_,p|p.byte_size())}
+	}
+	/// the tile this one is computed from
+	pub fn source(&self) -> &Arc<dyn Tile>{&self.cache.source.0}
+	/// the factor the source is downsampled by
+	pub fn factor(&self) -> usize{self.cache.source.1}
+	fn produce(source:&(Arc<dyn Tile>,usize)) -> Result<Pixel>{
+		Ok(source.0.pixel()?.downsample(source.1))
+	}
+}
+
+impl Tile for Downsampled{
+	fn frame(&self) -> Rect<i32, PixelSpace> {self.source().frame()}
+	fn stored_size(&self) -> Size2D<u32, PixelSpace> {
+		let factor = self.factor() as u32;
+		let stored = self.source().stored_size();
+		Size2D::new(stored.width.div_ceil(factor),stored.height.div_ceil(factor))
+	}
+	fn plane(&self) -> Plane {self.source().plane()}
+	fn pixel(&self) -> Result<Pixel> {
+		self.cache.get().map(|p|(*p).clone())
+	}
+	fn ordering_id(&self) -> i32 {self.source().ordering_id()}
+}
@@ -0,0 +1,47 @@
+//! Spatial index of the tiles of a level.
+use std::collections::BTreeMap;
+use std::sync::Arc;
+use euclid::Rect;
+use rstar::{RTree, AABB};
+use rstar::primitives::{GeomWithData, Rectangle};
+use crate::{PixelSpace, Plane, Tile};
+
+/// the frame of a tile with its position in the level
+type Entry = GeomWithData<Rectangle<[i32;2]>,usize>;
+
+fn envelope(frame:&Rect<i32, PixelSpace>) -> AABB<[i32;2]>{
+	let (min,max) = (frame.min(),frame.max());
+	AABB::from_corners([min.x,min.y],[max.x,max.y])
+}
+
+/// One R-tree per plane.
+pub(crate) struct Index{
+	planes:BTreeMap<Plane,RTree<Entry>>
+}
+
+impl Index {
+	pub(crate) fn new(tiles:&[Arc<dyn Tile>]) -> Self{
+		let mut entries:BTreeMap<Plane,Vec<Entry>> = BTreeMap::new();
+		for (n,tile) in tiles.iter().enumerate() {
+			let envelope = envelope(&tile.frame());
+			let rectangle = Rectangle::from_corners(envelope.lower(),envelope.upper());
+			entries.entry(tile.plane()).or_default().push(GeomWithData::new(rectangle,n));
+		}
+		Index{planes:entries.into_iter().map(|(p,e)|(p,RTree::bulk_load(e))).collect()}
+	}
+	/// positions of the tiles of plane intersecting with area (as by [Rect::intersects]), in ascending order
+	pub(crate) fn intersecting(&self, plane:Plane, area:&Rect<i32, PixelSpace>) -> Vec<usize>{
+		let Some(tree) = self.planes.get(&plane) else {return vec![]};
+		// the envelopes include their edges, so touching frames have to be filtered out
+		let (min,max) = (area.min(),area.max());
+		let mut found:Vec<usize> = tree.locate_in_envelope_intersecting(&envelope(area))
+			.filter(|e|{
+				let (lower,upper) = (e.geom().lower(),e.geom().upper());
+				lower[0] < max.x && upper[0] > min.x && lower[1] < max.y && upper[1] > min.y
+			})
+			.map(|e|e.data)
+			.collect();
+		found.sort_unstable();
+		found
+	}
+}
@@ -0,0 +1,88 @@
+//! Sorting tiles into pyramid levels.
+//!
+//! Stored sizes are rounded (in either direction, depending on the writer), downscaling doesn't have to be the same
+//! for both axes and pyramids may skip levels. So instead of computing levels from the nominal factor alone,
+//! the actual scale of each level is measured from its tiles and every tile goes into the closest level it fits into.
+use std::sync::Arc;
+use crate::{Level, Tile};
+
+/// minimum size of the bigger axis of a tile, for it to be used to detect the minification factor
+const RELIABLE_SIZE:f64 = 8.0;
+
+/// log of the scale of a tile and its weight
+///
+/// The scales of both axes are averaged weighted by their stored size, as bigger axes are less affected by rounding.
+fn log_scale(tile:&dyn Tile) -> (f64,f64){
+	let (frame,stored) = (tile.frame(),tile.stored_size());
+	let mut sum = 0.0;
+	let mut weight = 0.0;
+	for (f,s) in [(frame.size.width,stored.width),(frame.size.height,stored.height)] {
+		if f > 0 && s > 0 {
+			sum += s as f64*(f as f64/s as f64).ln();
+			weight += s as f64;
+		}
+	}
+	if weight > 0.0 {(sum/weight,weight)} else {(0.0,0.0)}
+}
+
+/// true if the stored size of the tile could result from downscaling its frame by scale (rounding either way)
+fn fits(tile:&dyn Tile, scale:f64) -> bool{
+	let (frame,stored) = (tile.frame(),tile.stored_size());
+	[(frame.size.width,stored.width),(frame.size.height,stored.height)].into_iter()
+		.all(|(f,s)|(f as f64/scale - s as f64).abs() < 1.0)
+}
+
+/// Guess the minification factor from the smallest scale of all reasonably big downscaled tiles (defaults to 2).
+pub(crate) fn detect_factor(tiles:&[Box<dyn Tile>]) -> i32{
+	tiles.iter()
+		.map(|t|log_scale(t.as_ref()))
+		.filter(|&(l,w)|w >= RELIABLE_SIZE && l > 1.5f64.ln())
+		.map(|(l,_)|l.exp())
+		.min_by(f64::total_cmp)
+		.map_or(2,|s|(s.round() as i32).max(2))
+}
+
+/// Sort the tiles into levels of the given minification factor.
+///
+/// - the scale of each level is first measured from the tiles that nominally belong to it
+/// - then every tile goes into the level with the closest scale that it fits into (or just the closest one)
+/// - levels without tiles are kept as gaps with their nominal scale, trailing empty levels are dropped
+pub(crate) fn assign(tiles:Vec<Box<dyn Tile>>, factor:i32) -> Vec<Level>{
+	let ln_factor = (factor.max(2) as f64).ln();
+	let estimates:Vec<(f64,f64)> = tiles.iter().map(|t|log_scale(t.as_ref())).collect();
+
+	let mut sums:Vec<(f64,f64)> = vec![];
+	for &(l,w) in &estimates {
+		let nominal = (l/ln_factor).round().max(0.0) as usize;
+		if sums.len() <= nominal {
+			sums.resize(nominal+1,(0.0,0.0));
+		}
+		sums[nominal].0 += l*w;
+		sums[nominal].1 += w;
+	}
+	let measured:Vec<(usize,f64)> = sums.iter().enumerate()
+		.filter(|(_,(_,w))|*w > 0.0)
+		.map(|(n,(s,w))|(n,s/w))
+		.collect();
+
+	let mut levels:Vec<Vec<Arc<dyn Tile>>> = std::iter::repeat_with(Vec::new).take(sums.len()).collect();
+	for (tile,(l,_)) in tiles.into_iter().zip(estimates) {
+		let nearest = |fitting:bool| measured.iter()
+			.filter(|(_,s)|!fitting || fits(tile.as_ref(),s.exp()))
+			.min_by(|a,b|(a.1-l).abs().total_cmp(&(b.1-l).abs()))
+			.map(|&(n,_)|n);
+		let level = nearest(true).or_else(||nearest(false)).unwrap_or(0);
+		levels[level].push(tile.into());
+	}
+	while levels.last().is_some_and(Vec::is_empty) {
+		levels.pop();
+	}
+
+	levels.into_iter().enumerate().map(|(n,tiles)|{
+		let (sum,weight) = tiles.iter()
+			.map(|t|log_scale(t.as_ref()))
+			.fold((0.0,0.0),|(s,w),(l,lw)|(s+l*lw,w+lw));
+		let scale = if weight > 0.0 {(sum/weight).exp()} else {(ln_factor*n as f64).exp()};
+		Level::new(scale,tiles)
+	}).collect()
+}
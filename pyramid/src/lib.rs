@@ -1,15 +1,100 @@
 use std::cmp::Ordering;
-use euclid::Rect;
+use std::collections::BTreeSet;
+use std::sync::{Arc, OnceLock};
+use euclid::{Rect, Size2D};
 use num_complex::Complex;
 use ndarray::Array2;
+use iobase::Result;
 
+/// Evaluate an expression for the array of any [Pixel] variant.
+macro_rules! with_pixel{
+	($pixel:expr, $a:ident => $e:expr) => {
+		match $pixel {
+			Pixel::Gray8($a) => $e,
+			Pixel::Gray16($a) => $e,
+			Pixel::Gray32($a) => $e,
+			Pixel::Gray64($a) => $e,
+			Pixel::Bgr24($a) => $e,
+			Pixel::Bgr48($a) => $e,
+			Pixel::Bgra32($a) => $e,
+			Pixel::Bgr96Float($a) => $e,
+			Pixel::Gray32Float($a) => $e,
+			Pixel::Gray64ComplexFloat($a) => $e,
+			Pixel::Bgr192ComplexFloat($a) => $e
+		}
+	}
+}
+
+/// Map the array of any [Pixel] variant to a new array of the same variant.
+macro_rules! map_pixel{
+	($pixel:expr, $a:ident => $e:expr) => {
+		match $pixel {
+			Pixel::Gray8($a) => Pixel::Gray8($e),
+			Pixel::Gray16($a) => Pixel::Gray16($e),
+			Pixel::Gray32($a) => Pixel::Gray32($e),
+			Pixel::Gray64($a) => Pixel::Gray64($e),
+			Pixel::Bgr24($a) => Pixel::Bgr24($e),
+			Pixel::Bgr48($a) => Pixel::Bgr48($e),
+			Pixel::Bgra32($a) => Pixel::Bgra32($e),
+			Pixel::Bgr96Float($a) => Pixel::Bgr96Float($e),
+			Pixel::Gray32Float($a) => Pixel::Gray32Float($e),
+			Pixel::Gray64ComplexFloat($a) => Pixel::Gray64ComplexFloat($e),
+			Pixel::Bgr192ComplexFloat($a) => Pixel::Bgr192ComplexFloat($e)
+		}
+	}
+}
+
+mod levels;
+mod index;
+pub mod downsample;
+pub mod compose;
+pub mod real;
+pub mod render;
+pub mod resample;
+pub mod stats;
+pub mod project;
+
+pub use downsample::Downsampled;
+pub use compose::Blend;
+pub use real::PixelToReal;
+pub use resample::Filter;
+pub use project::{Axis, Projection};
+
+/// Full resolution pixels of an image.
 pub struct PixelSpace;
+/// Micrometers, see [real].
 pub struct RealSpace;
 
-pub trait Tile{
+/// The plane of a tile, tiles of different planes never overlap each other.
+#[derive(Debug,Clone,Copy,Default,PartialEq,Eq,PartialOrd,Ord,Hash)]
+pub struct Plane{
+	pub c:i32,
+	pub z:i32,
+	pub t:i32
+}
+
+/// Minification of a tile relative to full resolution, per axis.
+#[derive(Debug,Clone,Copy,PartialEq)]
+pub struct Scale{
+	pub x:f64,
+	pub y:f64
+}
+
+pub trait Tile:Send+Sync{
+	/// area covered by the tile in full resolution pixels
 	fn frame(&self) -> Rect<i32, PixelSpace>;
-	fn pixel(&self)->Pixel;
-	fn level(&self, scaling:i32)->usize;
+	/// size of the stored pixels, smaller than the frame for downscaled tiles
+	fn stored_size(&self) -> Size2D<u32, PixelSpace>;
+	fn plane(&self) -> Plane;
+	/// the pixels of the tile (in stored size)
+	fn pixel(&self) -> Result<Pixel>;
+	/// the scale of the tile, as given by its frame and stored size (empty axes have a scale of 1)
+	fn scale(&self) -> Scale{
+		let (frame,stored) = (self.frame(),self.stored_size());
+		let ratio = |f:i32,s:u32|if f > 0 && s > 0 {f as f64/s as f64} else {1.0};
+		Scale{x:ratio(frame.size.width,stored.width), y:ratio(frame.size.height,stored.height)}
+	}
+	/// tiles with a higher ordering id are drawn on top of tiles with a lower one
 	fn ordering_id(&self) -> i32;
 }
 
@@ -25,7 +110,7 @@ impl PartialOrd for dyn Tile{
 	}
 }
 
-#[derive(Debug)]
+#[derive(Debug,Clone,PartialEq)]
 pub enum Pixel{
 	Gray8(Array2<u8>),
 	Gray16(Array2<u16>),
@@ -40,17 +125,239 @@ pub enum Pixel{
 	Bgr192ComplexFloat(Array2<(Complex<f32>,Complex<f32>,Complex<f32>)>)
 }
 
+impl Pixel {
+	/// width and height in pixels
+	pub fn size(&self) -> Size2D<usize, PixelSpace>{
+		let (height,width) = with_pixel!(self, a => a.dim());
+		Size2D::new(width,height)
+	}
+	/// memory used by the pixels in bytes
+	pub fn byte_size(&self) -> usize{
+		fn element_size<T>(_:&Array2<T>) -> usize{size_of::<T>()}
+		with_pixel!(self, a => a.len()*element_size(a))
+	}
+}
+
+/// The element types of the [Pixel] variants, to get typed arrays in and out of them.
+pub trait Element:Copy+Default+Send+Sync+'static{
+	/// the array of the pixels, if they have this element type
+	fn array(pixel:&Pixel) -> Option<&Array2<Self>>;
+	fn into_pixel(array:Array2<Self>) -> Pixel;
+}
+
+macro_rules! element {
+	($($variant:ident($t:ty)),*) => {$(
+		impl Element for $t{
+			fn array(pixel:&Pixel) -> Option<&Array2<Self>>{
+				match pixel {
+					Pixel::$variant(a) => Some(a),
+					_ => None
+				}
+			}
+			fn into_pixel(array:Array2<Self>) -> Pixel{Pixel::$variant(array)}
+		}
+	)*}
+}
+element!(
+	Gray8(u8), Gray16(u16), Gray32(u32), Gray64(u64),
+	Bgr24((u8,u8,u8)), Bgr48((u16,u16,u16)), Bgra32((u8,u8,u8,u8)), Bgr96Float((f32,f32,f32)),
+	Gray32Float(f32), Gray64ComplexFloat(Complex<f32>), Bgr192ComplexFloat((Complex<f32>,Complex<f32>,Complex<f32>))
+);
+
+/// A level of a [Pyramid].
+pub(crate) struct Level{
+	/// actual minification relative to full resolution, as measured from the tiles
+	scale:f64,
+	tiles:Vec<Arc<dyn Tile>>,
+	/// true if the tiles were generated by [Pyramid::generate]
+	generated:bool,
+	/// built when first needed, must be reset when tiles change
+	index:OnceLock<index::Index>
+}
 
+impl Level {
+	pub(crate) fn new(scale:f64, tiles:Vec<Arc<dyn Tile>>) -> Self{
+		Level{scale, tiles, generated:false, index:OnceLock::new()}
+	}
+	fn index(&self) -> &index::Index{
+		self.index.get_or_init(||index::Index::new(&self.tiles))
+	}
+}
+
+/// Tiles sorted into levels of decreasing resolution.
 pub struct Pyramid{
-	layers:Vec<Vec<Box<dyn Tile>>>
+	layers:Vec<Level>,
+	scaling_factor:i32,
+	transform:Option<PixelToReal>
 }
 
 impl Pyramid{
+	/// Sort tiles into levels, each being about scaling_factor times smaller than the previous one.
+	///
+	/// - level 0 is full resolution
+	/// - tiles go into the closest level, so rounded stored sizes and slightly irregular pyramids don't matter
+	/// - if scaling_factor is less than 2 it is guessed from the tiles
+	/// - missing levels are kept as empty gaps (see [Pyramid::missing_levels])
+	/// - the tiles of each level are sorted by their ordering id
 	pub fn new(tiles:Vec<Box<dyn Tile>>, scaling_factor:i32) -> Self{
-		let mut ret=Pyramid{layers:vec![]};
-		for tile in tiles{
-			ret.layers[tile.level(scaling_factor)].push(tile);
+		let scaling_factor = if scaling_factor < 2 {levels::detect_factor(&tiles)} else {scaling_factor};
+		let mut layers = levels::assign(tiles,scaling_factor);
+		for layer in &mut layers {
+			layer.tiles.sort_by_key(|t|t.ordering_id());
+		}
+		Pyramid{layers, scaling_factor, transform:None}
+	}
+	/// Place the pyramid in real space (see [real]).
+	pub fn with_transform(self, transform:PixelToReal) -> Self{
+		Pyramid{transform:Some(transform), ..self}
+	}
+	/// the transform from pixels into real space, if the pyramid was placed there
+	pub fn transform(&self) -> Option<&PixelToReal>{self.transform.as_ref()}
+	/// the area a pixel frame (e.g. of a tile) covers in real space, if the pyramid was placed there
+	pub fn to_real(&self, frame:Rect<i32, PixelSpace>) -> Option<Rect<f64, RealSpace>>{
+		self.transform.as_ref().map(|t|real::to_real(t,frame))
+	}
+	/// the smallest pixel frame covering an area in real space, if the pyramid was placed there
+	pub fn to_pixels(&self, area:Rect<f64, RealSpace>) -> Option<Rect<i32, PixelSpace>>{
+		self.transform.as_ref().and_then(|t|real::to_pixels(t,area))
+	}
+	pub fn scaling_factor(&self) -> i32{self.scaling_factor}
+	/// number of levels, including missing ones
+	pub fn levels(&self) -> usize{self.layers.len()}
+	/// all tiles of a level, sorted by their ordering id
+	pub fn level(&self, level:usize) -> &[Arc<dyn Tile>]{
+		self.layers.get(level).map_or(&[],|l|l.tiles.as_slice())
+	}
+	/// minification of a level relative to full resolution
+	///
+	/// - measured from the tiles of the level, so it can differ from the nominal scaling_factor^level
+	/// - missing levels and levels beyond the top of the pyramid have their nominal scale
+	pub fn scale(&self, level:usize) -> f64{
+		match self.layers.get(level) {
+			Some(l) => l.scale,
+			None => (self.scaling_factor as f64).powi(level as i32)
+		}
+	}
+	/// levels below the top of the pyramid without any tiles
+	pub fn missing_levels(&self) -> Vec<usize>{
+		self.layers.iter().enumerate().filter(|(_,l)|l.tiles.is_empty()).map(|(n,_)|n).collect()
+	}
+	/// levels with tiles generated by [Pyramid::generate]
+	pub fn generated_levels(&self) -> Vec<usize>{
+		self.layers.iter().enumerate().filter(|(_,l)|l.generated).map(|(n,_)|n).collect()
+	}
+	/// Fill missing levels and add levels up to top with tiles downsampled from the previous level.
+	///
+	/// - nothing is computed here, the pixels of the new tiles are computed and cached when asked for (see [Downsampled])
+	/// - every tile of the previous level gets a downsampled copy, covering the same frame
+	/// - the factor between the levels is the ratio of their scales, rounded to the closest integer
+	pub fn generate(&mut self, top:usize){
+		for n in 1..=top {
+			if self.layers.len() <= n {
+				let scale = self.scale(n);
+				self.layers.push(Level::new(scale,vec![]));
+			}
+			let (previous,level) = self.layers.split_at_mut(n);
+			let (previous,level) = (&previous[n-1],&mut level[0]);
+			if !level.tiles.is_empty() || previous.tiles.is_empty() {
+				continue;
+			}
+			let factor = (level.scale/previous.scale).round().max(1.0) as usize;
+			level.tiles = previous.tiles.iter()
+				.map(|t|Arc::new(Downsampled::new(t.clone(),factor)) as Arc<dyn Tile>)
+				.collect();
+			level.scale = previous.scale*factor as f64;
+			level.generated = true;
+			level.index = OnceLock::new();
 		}
-		ret
+	}
+	/// all planes that have tiles (in any level)
+	pub fn planes(&self) -> BTreeSet<Plane>{
+		self.layers.iter().flat_map(|l|&l.tiles).map(|t|t.plane()).collect()
+	}
+	/// the tiles of a plane in the given level, sorted by their ordering id
+	pub fn tiles(&self, level:usize, plane:Plane) -> impl Iterator<Item=&dyn Tile>{
+		self.level(level).iter().map(|t|t.as_ref()).filter(move |t|t.plane() == plane)
+	}
+	/// the tiles of a plane in the given level that intersect with area (in full resolution pixels), sorted by their ordering id
+	///
+	/// - tiles are looked up in a spatial index, which is built per level at the first lookup
+	pub fn intersecting(&self, level:usize, plane:Plane, area:Rect<i32, PixelSpace>) -> impl Iterator<Item=&dyn Tile>{
+		let layer = self.layers.get(level);
+		let found = layer.map_or(vec![],|l|l.index().intersecting(plane,&area));
+		found.into_iter().filter_map(move |n|layer.map(|l|l.tiles[n].as_ref()))
+	}
+	/// Compose the tiles of a plane in the given level that intersect with area into one image (see [compose::compose]).
+	pub fn compose(&self, level:usize, plane:Plane, area:Rect<i32, PixelSpace>, blend:Blend) -> Result<Option<Pixel>>{
+		compose::compose(self.intersecting(level,plane,area),area,self.scale(level),blend)
+	}
+	/// Compose the tiles covering an area in real space (see [Pyramid::compose] and [Pyramid::to_pixels]).
+	///
+	/// - fails with [iobase::Error::NotFound] if the pyramid wasn't placed in real space
+	pub fn compose_real(&self, level:usize, plane:Plane, area:Rect<f64, RealSpace>, blend:Blend) -> Result<Option<Pixel>>{
+		let area = self.to_pixels(area)
+			.ok_or(iobase::Error::NotFound("Transform into real space".to_string()))?;
+		self.compose(level,plane,area,blend)
+	}
+	/// the coarsest level with tiles that still has at least the resolution of scale (0 if there is none)
+	pub fn level_for(&self, scale:f64) -> usize{
+		// measured scales are a bit off from rounding the stored sizes
+		self.layers.iter().enumerate()
+			.filter(|(_,l)|!l.tiles.is_empty() && l.scale <= scale*1.01)
+			.max_by(|(_,a),(_,b)|a.scale.total_cmp(&b.scale))
+			.map_or(0,|(n,_)|n)
+	}
+	/// Compose an area of a plane into an image of the given size.
+	///
+	/// - the tiles are taken from [Pyramid::level_for] the finer of the scales along x and y, and resampled to size
+	/// - see [Pyramid::compose] and [Pixel::resample]
+	pub fn resampled(&self, plane:Plane, area:Rect<i32, PixelSpace>, size:Size2D<usize, PixelSpace>, filter:Filter, blend:Blend) -> Result<Option<Pixel>>{
+		let scale = (area.size.width as f64/size.width as f64).min(area.size.height as f64/size.height as f64);
+		let level = self.level_for(scale);
+		Ok(self.compose(level,plane,area,blend)?.map(|p|p.resample(size,filter)))
+	}
+	/// Statistics of each component of a plane in a level (see [stats]), only full resolution has the original values.
+	///
+	/// - only the parts of the tiles inside area are measured, or all tiles if there is no area
+	/// - overlapping tiles are measured as [Pyramid::compose] shows them with [Blend::LastWins], so each pixel counts once
+	/// - the histogram of values without their own bin covers exactly the finite values, if it has to grow while going
+	///   through the tiles they are read a second time; percentiles of them are still the start of their bin
+	pub fn statistics(&self, level:usize, plane:Plane, area:Option<Rect<i32, PixelSpace>>) -> Result<Vec<stats::Statistics>>{
+		let Some(layer) = self.layers.get(level) else {return Ok(vec![])};
+		let index = layer.index();
+		let found = match area {
+			Some(area) => index.intersecting(plane,&area),
+			None => (0..layer.tiles.len()).filter(|&n|layer.tiles[n].plane() == plane).collect()
+		};
+		stats::collect(|f|{
+			for &n in &found {
+				let tile = &layer.tiles[n];
+				let frame = tile.frame();
+				// tiles later in the level are on top
+				let on_top:Vec<_> = index.intersecting(plane,&frame).into_iter()
+					.filter(|&m|m > n)
+					.map(|m|layer.tiles[m].frame())
+					.collect();
+				let (pixel,shown) = stats::visible(&tile.pixel()?,frame,area.unwrap_or(frame),&on_top);
+				f(&pixel,&shown)?;
+			}
+			Ok(())
+		})
+	}
+	/// Project the planes along an axis into one (see [project::project]).
+	///
+	/// - plane selects the channel and the position on the other axis, its position on axis is ignored
+	/// - each plane is composed from the tiles in area (see [Pyramid::compose]), planes without any tiles there are left out
+	/// - planes are composed one after another, so only one of them is kept in memory besides the result
+	pub fn project(&self, level:usize, plane:Plane, axis:Axis, area:Rect<i32, PixelSpace>, projection:Projection, blend:Blend) -> Result<Option<Pixel>>{
+		let stack = self.planes().into_iter().filter(|p|match axis {
+			Axis::Z => p.c == plane.c && p.t == plane.t,
+			Axis::T => p.c == plane.c && p.z == plane.z
+		});
+		project::project(stack.filter_map(|p|self.compose(level,p,area,blend).transpose()),projection)
+	}
+	/// the area covered by all tiles of the full resolution level
+	pub fn frame(&self) -> Option<Rect<i32, PixelSpace>>{
+		self.level(0).iter().map(|t|t.frame()).reduce(|a,b|a.union(&b))
 	}
 }
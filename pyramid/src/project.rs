@@ -0,0 +1,136 @@
+//! Projections of a stack of planes into one plane.
+use ndarray::{Array2, Array3};
+use num_complex::Complex;
+use iobase::{Error, Result};
+use crate::compose::Combine;
+use crate::{Element, Pixel};
+
+/// How the values of all planes are combined.
+#[derive(Debug,Clone,Copy,Default,PartialEq,Eq)]
+pub enum Projection{
+	/// the highest value (per component, complex values by their magnitude)
+	#[default]
+	Max,
+	/// the lowest value (per component, complex values by their magnitude)
+	Min,
+	Mean,
+	Sum,
+	/// the standard deviation of the population
+	StdDev
+}
+
+/// The axis a stack of planes is taken along.
+#[derive(Debug,Clone,Copy,PartialEq,Eq)]
+pub enum Axis{Z, T}
+
+/// Samples that can be projected.
+trait Project:Element+Combine{
+	/// number of components, alpha is left out
+	const COMPONENTS:usize;
+	const COMPLEX:bool;
+	fn components(&self) -> [Complex<f64>;3];
+}
+
+macro_rules! real_project {
+	($($t:ty => $components:expr, |$v:ident| $values:expr);*) => {$(
+		impl Project for $t{
+			const COMPONENTS:usize = $components;
+			const COMPLEX:bool = false;
+			fn components(&self) -> [Complex<f64>;3]{let $v = self; $values.map(|v:f64|Complex::new(v,0.0))}
+		}
+	)*}
+}
+real_project!(
+	u8 => 1, |v| [*v as f64,0.0,0.0];
+	u16 => 1, |v| [*v as f64,0.0,0.0];
+	u32 => 1, |v| [*v as f64,0.0,0.0];
+	u64 => 1, |v| [*v as f64,0.0,0.0];
+	f32 => 1, |v| [*v as f64,0.0,0.0];
+	(u8,u8,u8) => 3, |v| [v.0 as f64,v.1 as f64,v.2 as f64];
+	(u16,u16,u16) => 3, |v| [v.0 as f64,v.1 as f64,v.2 as f64];
+	(u8,u8,u8,u8) => 3, |v| [v.0 as f64,v.1 as f64,v.2 as f64];
+	(f32,f32,f32) => 3, |v| [v.0 as f64,v.1 as f64,v.2 as f64]
+);
+
+fn to_f64(c:Complex<f32>) -> Complex<f64>{Complex::new(c.re as f64,c.im as f64)}
+
+impl Project for Complex<f32>{
+	const COMPONENTS:usize = 1;
+	const COMPLEX:bool = true;
+	fn components(&self) -> [Complex<f64>;3]{[to_f64(*self),Complex::default(),Complex::default()]}
+}
+
+impl Project for (Complex<f32>,Complex<f32>,Complex<f32>){
+	const COMPONENTS:usize = 3;
+	const COMPLEX:bool = true;
+	fn components(&self) -> [Complex<f64>;3]{[to_f64(self.0),to_f64(self.1),to_f64(self.2)]}
+}
+
+/// float pixels of the given size from the values of their components
+fn float_pixel(size:(usize,usize), components:usize, complex:bool, value:impl Fn(usize,usize,usize)->Complex<f64>) -> Pixel{
+	let c = |y,x,k|{let v:Complex<f64> = value(y,x,k); Complex::new(v.re as f32,v.im as f32)};
+	let r = |y,x,k|value(y,x,k).re as f32;
+	match (components,complex) {
+		(1,false) => Pixel::Gray32Float(Array2::from_shape_fn(size,|(y,x)|r(y,x,0))),
+		(_,false) => Pixel::Bgr96Float(Array2::from_shape_fn(size,|(y,x)|(r(y,x,0),r(y,x,1),r(y,x,2)))),
+		(1,true) => Pixel::Gray64ComplexFloat(Array2::from_shape_fn(size,|(y,x)|c(y,x,0))),
+		(_,true) => Pixel::Bgr192ComplexFloat(Array2::from_shape_fn(size,|(y,x)|(c(y,x,0),c(y,x,1),c(y,x,2))))
+	}
+}
+
+fn project_typed<T:Project>(first:&Array2<T>, rest:&mut dyn Iterator<Item=Result<Pixel>>, projection:Projection) -> Result<Pixel>{
+	let dim = first.dim();
+	let mut next = ||rest.next().map(|pixel|{
+		let pixel = pixel?;
+		match T::array(&pixel) {
+			Some(a) if a.dim() == dim => Ok(a.clone()),
+			Some(a) => Err(Error::InvalidData(format!("Planes of {:?} and {:?} pixels can't be projected together",dim,a.dim()))),
+			None => Err(Error::InvalidData("Planes of different pixel types can't be projected together".to_string()))
+		}
+	}).transpose();
+	match projection {
+		Projection::Max | Projection::Min => {
+			let mut out = first.clone();
+			while let Some(a) = next()? {
+				out.zip_mut_with(&a,|o,v|*o = if projection == Projection::Max {o.maximum(*v)} else {o.minimum(*v)});
+			}
+			Ok(T::into_pixel(out))
+		}
+		Projection::Mean | Projection::Sum | Projection::StdDev => {
+			let mut sum:Array3<Complex<f64>> = Array3::zeros((dim.0,dim.1,T::COMPONENTS));
+			let mut squares:Array3<f64> = Array3::zeros((dim.0,dim.1,T::COMPONENTS));
+			let mut add = |a:&Array2<T>|for ((y,x),v) in a.indexed_iter() {
+				for (k,c) in v.components().into_iter().take(T::COMPONENTS).enumerate() {
+					sum[(y,x,k)] += c;
+					squares[(y,x,k)] += c.norm_sqr();
+				}
+			};
+			add(first);
+			let mut n = 1.0;
+			while let Some(a) = next()? {
+				add(&a);
+				n += 1.0;
+			}
+			Ok(match projection {
+				Projection::Sum => float_pixel(dim,T::COMPONENTS,T::COMPLEX,|y,x,k|sum[(y,x,k)]),
+				Projection::Mean => float_pixel(dim,T::COMPONENTS,T::COMPLEX,|y,x,k|sum[(y,x,k)]/n),
+				_ => float_pixel(dim,T::COMPONENTS,false,|y,x,k|{
+					let variance = squares[(y,x,k)]/n-(sum[(y,x,k)]/n).norm_sqr();
+					Complex::new(variance.max(0.0).sqrt(),0.0)
+				})
+			})
+		}
+	}
+}
+
+/// Project planes into one, taking them one after another so only one of them is kept in memory.
+///
+/// - Max and Min keep the pixel type
+/// - Mean, Sum and StdDev result in float pixels (Gray32Float, Bgr96Float, or their complex counterparts for Mean and Sum of complex pixels),
+///   the alpha of Bgra32 is left out
+/// - returns None if there are no planes, all planes must have the same type and size
+pub fn project(planes:impl IntoIterator<Item=Result<Pixel>>, projection:Projection) -> Result<Option<Pixel>>{
+	let mut planes = planes.into_iter();
+	let Some(first) = planes.next().transpose()? else {return Ok(None)};
+	with_pixel!(&first, a => project_typed(a,&mut planes,projection)).map(Some)
+}
@@ -0,0 +1,27 @@
+//! Placing pixels in real space.
+//!
+//! Real space is measured in micrometers, e.g. the coordinate system of the microscope stage.
+//! If all images use the same real space, tiles of different scenes or files can be put in relation to each other.
+use euclid::{Point2D, Rect, Size2D, Transform2D};
+use crate::{PixelSpace, RealSpace};
+
+/// Transform from full resolution pixels to real space.
+pub type PixelToReal = Transform2D<f64, PixelSpace, RealSpace>;
+
+/// The transform for pixels of the given size (in micrometers), where the corner of pixel 0/0 is at origin.
+pub fn pixel_to_real(pixel_size:Size2D<f64, RealSpace>, origin:Point2D<f64, RealSpace>) -> PixelToReal{
+	Transform2D::scale(pixel_size.width,pixel_size.height).then_translate(origin.to_vector())
+}
+
+/// The area a pixel frame covers in real space.
+pub fn to_real(transform:&PixelToReal, frame:Rect<i32, PixelSpace>) -> Rect<f64, RealSpace>{
+	transform.outer_transformed_rect(&frame.to_f64())
+}
+
+/// The smallest pixel frame covering an area in real space (None if the transform can't be inverted).
+pub fn to_pixels(transform:&PixelToReal, area:Rect<f64, RealSpace>) -> Option<Rect<i32, PixelSpace>>{
+	let frame = transform.inverse()?.outer_transformed_rect(&area);
+	// don't let rounding errors grow the frame by a whole pixel
+	let frame = frame.inflate(-1e-6,-1e-6).round_out();
+	frame.try_cast()
+}
@@ -0,0 +1,170 @@
+//! Rendering pixels for display.
+//!
+//! Every [Pixel] is rendered as a layer with its own [Channel] settings, layers are added up.
+use std::f64::consts::PI;
+use ndarray::{Array2, Array3};
+use num_complex::Complex;
+use iobase::{Error, Result};
+use crate::{Element, Pixel};
+
+/// Which value of complex pixels is displayed.
+#[derive(Debug,Clone,Copy,Default,PartialEq,Eq)]
+pub enum ComplexPart{
+	#[default]
+	Magnitude,
+	/// the angle, from -π to π
+	Phase,
+	Real,
+	Imaginary
+}
+
+/// Display settings of a layer.
+#[derive(Debug,Clone,Copy,PartialEq)]
+pub struct Channel{
+	/// values up to min are black
+	pub min:f64,
+	/// values from max on have the full color
+	pub max:f64,
+	/// exponent applied to the windowed value (0..1), values above 1 darken, below 1 brighten
+	pub gamma:f64,
+	/// color of the full value, for color pixels each component is multiplied with it
+	pub color:[u8;3],
+	pub complex:ComplexPart
+}
+
+impl Default for Channel {
+	fn default() -> Self{
+		Channel{min:0.0, max:255.0, gamma:1.0, color:[255,255,255], complex:ComplexPart::default()}
+	}
+}
+
+impl Channel {
+	/// A white channel with the given window.
+	pub fn new(min:f64, max:f64) -> Self{Channel{min, max, ..Default::default()}}
+	pub fn gamma(self, gamma:f64) -> Self{Channel{gamma, ..self}}
+	pub fn color(self, r:u8, g:u8, b:u8) -> Self{Channel{color:[r,g,b], ..self}}
+	pub fn complex(self, complex:ComplexPart) -> Self{Channel{complex, ..self}}
+	/// Set the window to the full range of the pixels.
+	///
+	/// - integer pixels use the range of their type
+	/// - float pixels use their smallest and largest value (NaN is ignored)
+	/// - complex pixels use the range of the displayed part, the phase is always -π..π
+	pub fn full_range(self, pixel:&Pixel) -> Self{
+		let (min,max) = match pixel {
+			Pixel::Gray8(_) | Pixel::Bgr24(_) | Pixel::Bgra32(_) => (0.0,u8::MAX as f64),
+			Pixel::Gray16(_) | Pixel::Bgr48(_) => (0.0,u16::MAX as f64),
+			Pixel::Gray32(_) => (0.0,u32::MAX as f64),
+			Pixel::Gray64(_) => (0.0,u64::MAX as f64),
+			_ if self.complex == ComplexPart::Phase && matches!(pixel,Pixel::Gray64ComplexFloat(_) | Pixel::Bgr192ComplexFloat(_)) => (-PI,PI),
+			_ => {
+				let mut range = (f64::INFINITY,f64::NEG_INFINITY);
+				with_pixel!(pixel, a => for v in a {
+					for s in v.rgb(self.complex).into_iter().filter(|s|!s.is_nan()) {
+						range = (range.0.min(s),range.1.max(s));
+					}
+				});
+				if range.0 > range.1 {(0.0,1.0)} else {range}
+			}
+		};
+		Channel{min, max, ..self}
+	}
+	/// the displayed intensity of a value, from 0 to 1
+	fn intensity(&self, value:f64) -> f64{
+		let windowed = if self.max > self.min {
+			((value-self.min)/(self.max-self.min)).clamp(0.0,1.0)
+		} else if value >= self.max {1.0} else {0.0};
+		if windowed.is_nan() {0.0} else {windowed.powf(self.gamma)}
+	}
+}
+
+/// A value that can be displayed.
+trait Sample:Element{
+	/// the value as red, green and blue (the same for gray pixels)
+	fn rgb(&self, part:ComplexPart) -> [f64;3];
+	/// opacity from 0 to 1
+	fn alpha(&self) -> f64{1.0}
+}
+
+macro_rules! gray_sample {
+	($($t:ty),*) => {$(
+		impl Sample for $t{
+			fn rgb(&self, _:ComplexPart) -> [f64;3]{[*self as f64;3]}
+		}
+	)*}
+}
+gray_sample!(u8,u16,u32,u64,f32);
+
+fn complex_value(c:Complex<f32>, part:ComplexPart) -> f64{
+	match part {
+		ComplexPart::Magnitude => c.norm() as f64,
+		ComplexPart::Phase => c.arg() as f64,
+		ComplexPart::Real => c.re as f64,
+		ComplexPart::Imaginary => c.im as f64
+	}
+}
+
+impl Sample for Complex<f32>{
+	fn rgb(&self, part:ComplexPart) -> [f64;3]{[complex_value(*self,part);3]}
+}
+
+// color pixels are stored in BGR order
+impl Sample for (u8,u8,u8){
+	fn rgb(&self, _:ComplexPart) -> [f64;3]{[self.2 as f64,self.1 as f64,self.0 as f64]}
+}
+impl Sample for (u16,u16,u16){
+	fn rgb(&self, _:ComplexPart) -> [f64;3]{[self.2 as f64,self.1 as f64,self.0 as f64]}
+}
+impl Sample for (u8,u8,u8,u8){
+	fn rgb(&self, _:ComplexPart) -> [f64;3]{[self.2 as f64,self.1 as f64,self.0 as f64]}
+	fn alpha(&self) -> f64{self.3 as f64/u8::MAX as f64}
+}
+impl Sample for (f32,f32,f32){
+	fn rgb(&self, _:ComplexPart) -> [f64;3]{[self.2 as f64,self.1 as f64,self.0 as f64]}
+}
+impl Sample for (Complex<f32>,Complex<f32>,Complex<f32>){
+	fn rgb(&self, part:ComplexPart) -> [f64;3]{
+		[complex_value(self.2,part),complex_value(self.1,part),complex_value(self.0,part)]
+	}
+}
+
+/// add a layer to the sum of red, green, blue and the largest alpha
+fn add_layer<T:Sample>(pixels:&Array2<T>, channel:&Channel, sum:&mut Array3<f64>){
+	let color = channel.color.map(|c|c as f64/u8::MAX as f64);
+	for ((y,x),v) in pixels.indexed_iter() {
+		let rgb = v.rgb(channel.complex);
+		for k in 0..3 {
+			sum[(y,x,k)] += channel.intensity(rgb[k])*color[k];
+		}
+		sum[(y,x,3)] = sum[(y,x,3)].max(v.alpha());
+	}
+}
+
+fn render(layers:&[(&Pixel,Channel)], components:usize) -> Result<Array3<u8>>{
+	let (first,_) = layers.first().ok_or(Error::InvalidData("Nothing to render".to_string()))?;
+	let size = first.size();
+	let mut sum = Array3::zeros((size.height,size.width,4));
+	for (pixel,channel) in layers {
+		if pixel.size() != size {
+			return Err(Error::InvalidData(format!("Layers of {:?} and {:?} can't be rendered together",size,pixel.size())));
+		}
+		with_pixel!(pixel, a => add_layer(a,channel,&mut sum));
+	}
+	Ok(Array3::from_shape_fn((size.height,size.width,components),|(y,x,k)|{
+		(sum[(y,x,k)].clamp(0.0,1.0)*u8::MAX as f64).round() as u8
+	}))
+}
+
+/// Render layers into RGB, with the shape (height, width, 3).
+///
+/// - the layers are added up and clipped, so e.g. a red and a green channel result in yellow
+/// - all layers must have the same size
+pub fn rgb(layers:&[(&Pixel,Channel)]) -> Result<Array3<u8>>{
+	render(layers,3)
+}
+
+/// Render layers into RGBA, with the shape (height, width, 4).
+///
+/// - like [rgb], the alpha is taken from Bgra32 layers (the most opaque wins), everything else is opaque
+pub fn rgba(layers:&[(&Pixel,Channel)]) -> Result<Array3<u8>>{
+	render(layers,4)
+}
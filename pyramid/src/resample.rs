@@ -0,0 +1,69 @@
+//! Resampling pixels to arbitrary sizes.
+use euclid::Size2D;
+use ndarray::Array2;
+use crate::compose::Combine;
+use crate::{Pixel, PixelSpace};
+
+/// How pixels are interpolated when resampling.
+#[derive(Debug,Clone,Copy,Default,PartialEq,Eq)]
+pub enum Filter{
+	/// the source pixel at the center of each pixel
+	Nearest,
+	/// linear interpolation between the four source pixels around the center of each pixel
+	#[default]
+	Bilinear,
+	/// the mean of all source pixels covered by each pixel, weighted by their coverage
+	Area
+}
+
+/// source pixels and their weights for each output pixel along one axis
+fn weights(source:usize, size:usize, filter:Filter) -> Vec<Vec<(usize,f64)>>{
+	let ratio = source as f64/size as f64;
+	let last = source.saturating_sub(1);
+	(0..size).map(|i|{
+		let center = (i as f64+0.5)*ratio;
+		match filter {
+			Filter::Nearest => vec![((center as usize).min(last),1.0)],
+			Filter::Bilinear => {
+				let position = (center-0.5).max(0.0);
+				let (first,fraction) = (position as usize,position.fract());
+				if fraction == 0.0 || first >= last {vec![(first.min(last),1.0)]}
+				else {vec![(first,1.0-fraction),(first+1,fraction)]}
+			}
+			Filter::Area => {
+				let (start,end) = (i as f64*ratio,(i+1) as f64*ratio);
+				(start as usize..(end.ceil() as usize).min(source))
+					.map(|s|(s,(end.min(s as f64+1.0)-start.max(s as f64)).max(0.0)))
+					.filter(|(_,w)|*w > 0.0)
+					.collect()
+			}
+		}
+	}).collect()
+}
+
+/// Resample an array to width x height.
+///
+/// - the pixel grids are aligned at their outer borders, so each axis is scaled independently
+/// - pixels taken from a single source pixel are copied unchanged, everything else is a weighted mean (see [Combine])
+/// - empty arrays stay empty
+pub fn resample<T:Combine>(array:&Array2<T>, size:Size2D<usize, PixelSpace>, filter:Filter) -> Array2<T>{
+	let (height,width) = array.dim();
+	if width == 0 || height == 0 {
+		return Array2::from_shape_vec((0,0),vec![]).expect("an empty array has no shape errors");
+	}
+	let (columns,rows) = (weights(width,size.width,filter),weights(height,size.height,filter));
+	Array2::from_shape_fn((size.height,size.width),|(j,i)|{
+		match (rows[j].as_slice(),columns[i].as_slice()) {
+			([(y,_)],[(x,_)]) => array[(*y,*x)],
+			(rows,columns) => T::weighted_mean(rows.iter()
+				.flat_map(|(y,wy)|columns.iter().map(move |(x,wx)|(array[(*y,*x)],wy*wx))))
+		}
+	})
+}
+
+impl Pixel {
+	/// Resample the pixels to the given size (see [resample]).
+	pub fn resample(&self, size:Size2D<usize, PixelSpace>, filter:Filter) -> Pixel{
+		map_pixel!(self, a => resample(a,size,filter))
+	}
+}
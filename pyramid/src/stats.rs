@@ -0,0 +1,334 @@
+//! Histograms and intensity statistics.
+//!
+//! Statistics are collected per component in the order they are stored, e.g. blue, green, red for Bgr24.
+//! Complex values are measured by their magnitude, NaN is ignored.
+use std::iter::repeat;
+use std::mem::{discriminant, Discriminant};
+use std::ops::Range;
+use euclid::Rect;
+use ndarray::{s, Array2};
+use num_complex::Complex;
+use iobase::{Error, Result};
+use crate::{Element, Pixel, PixelSpace};
+
+/// number of histogram bins for values that don't get a bin each
+pub const BINS:usize = 4096;
+
+/// Equally wide bins, starting at start.
+#[derive(Debug,Clone,PartialEq)]
+pub struct Histogram{
+	pub start:f64,
+	pub width:f64,
+	pub bins:Vec<u64>
+}
+
+impl Histogram {
+	/// the lowest value that goes into a bin
+	pub fn bin_start(&self, bin:usize) -> f64{self.start+bin as f64*self.width}
+}
+
+/// Statistics of one component.
+#[derive(Debug,Clone,PartialEq)]
+pub struct Statistics{
+	pub count:u64,
+	/// NaN if there are no values, as well as max and mean
+	pub min:f64,
+	pub max:f64,
+	pub mean:f64,
+	/// values at the largest value of their type, infinite for floats
+	pub saturated:u64,
+	/// Gray8, Gray16 and their color types have one bin for each value, everything else [BINS] bins covering
+	/// the finite values (exactly from the smallest to the largest if measured by [crate::Pyramid::statistics] or in one
+	/// pixel, else up to twice as wide)
+	pub histogram:Histogram
+}
+
+impl Statistics {
+	/// The value below which percent of all values are, by the nearest rank.
+	///
+	/// - exact if each value has its own bin, else the start of the bin (but at least min)
+	pub fn percentile(&self, percent:f64) -> f64{
+		if self.count == 0 {
+			return f64::NAN;
+		}
+		let rank = ((percent/100.0*self.count as f64).ceil() as u64).clamp(1,self.count);
+		let mut seen = 0;
+		for (n,count) in self.histogram.bins.iter().enumerate() {
+			seen += count;
+			if seen >= rank {
+				return self.histogram.bin_start(n).clamp(self.min,self.max);
+			}
+		}
+		self.max
+	}
+}
+
+/// Samples that can be measured.
+trait Measure:Element{
+	const COMPONENTS:usize;
+	/// the largest value if every value gets its own bin
+	const BINNED:Option<u32>;
+	/// values from here on are saturated
+	const SATURATED:f64;
+	fn values(&self) -> [f64;4];
+}
+
+macro_rules! gray_measure {
+	($($t:ty => $binned:expr, $saturated:expr);*) => {$(
+		impl Measure for $t{
+			const COMPONENTS:usize = 1;
+			const BINNED:Option<u32> = $binned;
+			const SATURATED:f64 = $saturated;
+			fn values(&self) -> [f64;4]{[*self as f64,0.0,0.0,0.0]}
+		}
+	)*}
+}
+gray_measure!(
+	u8 => Some(u8::MAX as u32), u8::MAX as f64;
+	u16 => Some(u16::MAX as u32), u16::MAX as f64;
+	u32 => None, u32::MAX as f64;
+	u64 => None, u64::MAX as f64;
+	f32 => None, f64::INFINITY
+);
+
+impl Measure for Complex<f32>{
+	const COMPONENTS:usize = 1;
+	const BINNED:Option<u32> = None;
+	const SATURATED:f64 = f64::INFINITY;
+	fn values(&self) -> [f64;4]{[self.norm() as f64,0.0,0.0,0.0]}
+}
+
+macro_rules! color_measure {
+	($($t:ty => $components:expr, $binned:expr, $saturated:expr, |$v:ident| $values:expr);*) => {$(
+		impl Measure for $t{
+			const COMPONENTS:usize = $components;
+			const BINNED:Option<u32> = $binned;
+			const SATURATED:f64 = $saturated;
+			fn values(&self) -> [f64;4]{let $v = self; $values}
+		}
+	)*}
+}
+color_measure!(
+	(u8,u8,u8) => 3, Some(u8::MAX as u32), u8::MAX as f64, |v| [v.0 as f64,v.1 as f64,v.2 as f64,0.0];
+	(u16,u16,u16) => 3, Some(u16::MAX as u32), u16::MAX as f64, |v| [v.0 as f64,v.1 as f64,v.2 as f64,0.0];
+	(u8,u8,u8,u8) => 4, Some(u8::MAX as u32), u8::MAX as f64, |v| [v.0 as f64,v.1 as f64,v.2 as f64,v.3 as f64];
+	(f32,f32,f32) => 3, None, f64::INFINITY, |v| [v.0 as f64,v.1 as f64,v.2 as f64,0.0];
+	(Complex<f32>,Complex<f32>,Complex<f32>) => 3, None, f64::INFINITY, |v| [v.0.norm() as f64,v.1.norm() as f64,v.2.norm() as f64,0.0]
+);
+
+/// running sums of one component
+#[derive(Clone)]
+struct Accumulator{
+	count:u64,
+	min:f64,
+	max:f64,
+	sum:f64,
+	saturated:u64,
+	/// the smallest and largest finite value
+	finite:(f64,f64),
+	/// count of -inf and +inf, they go into the first and last bin
+	infinite:(u64,u64),
+	/// the range of the histogram was given up front (one bin per value or a known range), so it never changes
+	fixed:bool,
+	histogram:Histogram
+}
+
+impl Default for Accumulator {
+	fn default() -> Self{
+		Accumulator{
+			count:0, min:f64::INFINITY, max:f64::NEG_INFINITY, sum:0.0, saturated:0,
+			finite:(f64::INFINITY,f64::NEG_INFINITY), infinite:(0,0), fixed:false,
+			histogram:Histogram{start:0.0, width:1.0, bins:vec![]}
+		}
+	}
+}
+
+impl Accumulator {
+	/// [BINS] bins from min to max, the finite range of all values to come
+	fn with_range(min:f64, max:f64) -> Self{
+		let mut accumulator = Accumulator{finite:(min,max), fixed:true, ..Default::default()};
+		if min < max {
+			accumulator.histogram = Histogram{start:min, width:(max-min)/BINS as f64, bins:vec![0;BINS]};
+		}
+		accumulator
+	}
+	/// if the histogram covers exactly the finite values (or has a bin for each value)
+	fn exact(&self) -> bool{
+		let histogram = &self.histogram;
+		self.fixed || histogram.bins.is_empty() ||
+			(histogram.start == self.finite.0 && histogram.bin_start(histogram.bins.len()) == self.finite.1)
+	}
+	fn bin(&mut self, value:f64, count:u64){
+		let histogram = &mut self.histogram;
+		let bin = (((value-histogram.start)/histogram.width) as usize).min(histogram.bins.len()-1);
+		histogram.bins[bin] += count;
+	}
+	/// Make the histogram of values without their own bin cover the finite values so far and min..max.
+	///
+	/// - the first time there is more than one value, the range gets [BINS] bins (all values before were the same)
+	/// - later the bins are merged pairwise, doubling the range until it covers everything
+	fn cover(&mut self, min:f64, max:f64){
+		let before = self.finite;
+		self.finite = (before.0.min(min),before.1.max(max));
+		let (min,max) = self.finite;
+		if self.histogram.bins.is_empty() {
+			if min < max {
+				self.histogram = Histogram{start:min, width:(max-min)/BINS as f64, bins:vec![0;BINS]};
+				let pending = self.count-self.infinite.0-self.infinite.1;
+				if pending > 0 {self.bin(before.0,pending)}
+			}
+			return;
+		}
+		let histogram = &mut self.histogram;
+		while min < histogram.start || max > histogram.start+histogram.width*BINS as f64 {
+			let merged:Vec<u64> = histogram.bins.chunks(2).map(|pair|pair.iter().sum()).collect();
+			// the old range becomes the lower half if there are values above it, else the upper half
+			histogram.bins = if max > histogram.start+histogram.width*BINS as f64 {
+				[merged,vec![0;BINS/2]].concat()
+			} else {
+				histogram.start -= histogram.width*BINS as f64;
+				[vec![0;BINS/2],merged].concat()
+			};
+			histogram.width *= 2.0;
+		}
+	}
+	fn finish(mut self) -> Statistics{
+		let empty = self.count == 0;
+		let finite = self.count-self.infinite.0-self.infinite.1;
+		if self.histogram.bins.is_empty() && !empty {
+			// at most one finite value
+			let start = if finite > 0 {self.finite.0} else {0.0};
+			self.histogram = Histogram{start, width:1.0, bins:vec![finite]};
+		}
+		if let Some(last) = self.histogram.bins.len().checked_sub(1) {
+			self.histogram.bins[0] += self.infinite.0;
+			self.histogram.bins[last] += self.infinite.1;
+		}
+		Statistics{
+			count:self.count,
+			min:if empty {f64::NAN} else {self.min},
+			max:if empty {f64::NAN} else {self.max},
+			mean:if empty {f64::NAN} else {self.sum/self.count as f64},
+			saturated:self.saturated,
+			histogram:self.histogram
+		}
+	}
+}
+
+/// Count the values of array that are shown (everything if there is no mask).
+///
+/// - the histogram of values without their own bin covers ranges if given, else it grows as needed
+fn measure<T:Measure>(array:&Array2<T>, shown:Option<&Array2<bool>>, accumulators:&mut Vec<Accumulator>, ranges:Option<&[(f64,f64)]>){
+	let values = ||array.iter().zip(shown.into_iter().flatten().copied().chain(repeat(true))).filter(|(_,s)|*s).map(|(v,_)|v);
+	if accumulators.is_empty() {
+		*accumulators = match (T::BINNED,ranges) {
+			(Some(max),_) => {
+				let histogram = Histogram{start:0.0, width:1.0, bins:vec![0;max as usize+1]};
+				vec![Accumulator{fixed:true, histogram, ..Default::default()};T::COMPONENTS]
+			}
+			(None,Some(ranges)) => ranges.iter().map(|&(min,max)|Accumulator::with_range(min,max)).collect(),
+			(None,None) => vec![Accumulator::default();T::COMPONENTS]
+		};
+	}
+	if T::BINNED.is_none() && ranges.is_none() {
+		// the finite range of this array, so all of its values fit into the bins
+		let mut range = vec![(f64::INFINITY,f64::NEG_INFINITY);T::COMPONENTS];
+		for v in values() {
+			for (r,value) in range.iter_mut().zip(v.values()).filter(|(_,v)|v.is_finite()) {
+				*r = (r.0.min(value),r.1.max(value));
+			}
+		}
+		for (a,(min,max)) in accumulators.iter_mut().zip(range) {
+			if min <= max {a.cover(min,max)}
+		}
+	}
+	for v in values() {
+		for (a,value) in accumulators.iter_mut().zip(v.values()) {
+			if value.is_nan() {continue}
+			a.count += 1;
+			a.min = a.min.min(value);
+			a.max = a.max.max(value);
+			a.sum += value;
+			if value >= T::SATURATED {a.saturated += 1}
+			if value == f64::NEG_INFINITY {a.infinite.0 += 1}
+			else if value == f64::INFINITY {a.infinite.1 += 1}
+			else if !a.histogram.bins.is_empty() {a.bin(value,1)}
+		}
+	}
+}
+
+/// Statistics of pixels of the same type, added one after another.
+#[derive(Default)]
+struct Collector{
+	accumulators:Vec<Accumulator>,
+	kind:Option<Discriminant<Pixel>>,
+	/// the finite range of each component, if known up front
+	ranges:Option<Vec<(f64,f64)>>
+}
+
+impl Collector {
+	fn add(&mut self, pixel:&Pixel, shown:Option<&Array2<bool>>) -> Result<()>{
+		let kind = self.kind.get_or_insert(discriminant(pixel));
+		if *kind != discriminant(pixel) {
+			return Err(Error::InvalidData("Statistics of different pixel types can't be collected together".to_string()));
+		}
+		with_pixel!(pixel, a => measure(a,shown,&mut self.accumulators,self.ranges.as_deref()));
+		Ok(())
+	}
+	fn finish(self) -> Vec<Statistics>{
+		self.accumulators.into_iter().map(Accumulator::finish).collect()
+	}
+}
+
+/// Collect statistics of the parts of the pixels shown by their mask, as visited by visit.
+///
+/// - if the histogram had to grow, the pixels are visited a second time with bins covering exactly the finite values
+/// - all pixels must have the same type
+pub(crate) fn collect<F>(visit:F) -> Result<Vec<Statistics>> where F:Fn(&mut dyn FnMut(&Pixel,&Array2<bool>) -> Result<()>) -> Result<()>{
+	let mut first = Collector::default();
+	visit(&mut |pixel,shown|first.add(pixel,Some(shown)))?;
+	if first.accumulators.iter().all(Accumulator::exact) {
+		return Ok(first.finish());
+	}
+	let ranges = first.accumulators.iter().map(|a|a.finite).collect();
+	let mut second = Collector{ranges:Some(ranges), ..Default::default()};
+	visit(&mut |pixel,shown|second.add(pixel,Some(shown)))?;
+	Ok(second.finish())
+}
+
+/// Statistics of each component of all pixels.
+///
+/// - pixels are gone through once, the histogram of values without their own bin starts at the range of the first pixels
+///   and its bins are merged as later values go beyond that (see [Statistics::histogram])
+/// - infinite values go into the first or last bin
+/// - all pixels must have the same type
+pub fn statistics<'a>(pixels:impl IntoIterator<Item=&'a Pixel>) -> Result<Vec<Statistics>>{
+	let mut collector = Collector::default();
+	for pixel in pixels {
+		collector.add(pixel,None)?;
+	}
+	Ok(collector.finish())
+}
+
+/// rows and columns of the stored pixels (of the given height and width) of a frame covering area (in full resolution pixels)
+fn stored_range(frame:Rect<i32, PixelSpace>, (height,width):(usize,usize), area:Rect<i32, PixelSpace>) -> (Range<usize>,Range<usize>){
+	let range = |start:i32,end:i32,origin:i32,length:i32,stored:usize|{
+		let to_stored = |p:i32|((p-origin) as f64*stored as f64/length.max(1) as f64).clamp(0.0,stored as f64);
+		to_stored(start).floor() as usize..to_stored(end).ceil() as usize
+	};
+	(range(area.min_y(),area.max_y(),frame.min_y(),frame.height(),height),range(area.min_x(),area.max_x(),frame.min_x(),frame.width(),width))
+}
+
+/// The part of the stored pixels of a tile covering area, with a mask of those not hidden by the frames on top of it
+/// (all given in full resolution pixels).
+pub(crate) fn visible(pixel:&Pixel, frame:Rect<i32, PixelSpace>, area:Rect<i32, PixelSpace>, on_top:&[Rect<i32, PixelSpace>]) -> (Pixel,Array2<bool>){
+	let dim = with_pixel!(pixel, a => a.dim());
+	let mut shown = Array2::from_elem(dim,true);
+	for &top in on_top {
+		let (rows,columns) = stored_range(frame,dim,top);
+		shown.slice_mut(s![rows,columns]).fill(false);
+	}
+	let (rows,columns) = stored_range(frame,dim,area);
+	let pixel = map_pixel!(pixel, a => a.slice(s![rows.clone(),columns.clone()]).to_owned());
+	(pixel,shown.slice(s![rows,columns]).to_owned())
+}
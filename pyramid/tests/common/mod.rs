@@ -0,0 +1,76 @@
+//! Synthetic tiles shared by the tests (and the benchmarks).
+#![allow(dead_code)]
+use std::sync::atomic::{AtomicUsize, Ordering};
+use std::sync::Arc;
+use euclid::{Rect, Size2D};
+use iobase::Error;
+use ndarray::Array2;
+use pyramid::{Pixel, PixelSpace, Plane, Tile};
+
+/// pixel types a [Synthetic] tile can have
+#[derive(Debug,Clone,Copy)]
+pub enum Kind{
+	Gray8,
+	Gray16,
+	Gray32Float
+}
+
+/// the value of the full resolution pixel x/y
+pub type Values = Arc<dyn Fn(i32, i32) -> f64+Send+Sync>;
+
+/// A tile whose pixels are computed from their full resolution position, counting how often they were read.
+///
+/// - stored pixel i/j takes the value of the full resolution pixel at its upper left corner
+/// - without values, reading the pixels fails with [Error::NotFound]
+#[derive(Clone)]
+pub struct Synthetic{
+	pub frame:Rect<i32, PixelSpace>,
+	pub stored:Size2D<u32, PixelSpace>,
+	pub plane:Plane,
+	pub id:i32,
+	pub values:Option<(Kind, Values)>,
+	pub reads:Arc<AtomicUsize>
+}
+
+impl Synthetic {
+	/// a tile of the default plane stored at full resolution, with ordering id 0 and no pixels
+	pub fn new(x:i32, y:i32, width:i32, height:i32) -> Self{
+		let frame = euclid::rect(x, y, width, height);
+		Synthetic{frame, stored:frame.size.cast(), plane:Plane::default(), id:0, values:None, reads:Arc::default()}
+	}
+	pub fn stored(self, width:u32, height:u32) -> Self{Synthetic{stored:euclid::size2(width, height), ..self}}
+	/// stored size downscaled by scale
+	pub fn scale(self, scale:u32) -> Self{Synthetic{stored:self.frame.size.cast::<u32>()/scale, ..self}}
+	pub fn plane(self, plane:Plane) -> Self{Synthetic{plane, ..self}}
+	pub fn id(self, id:i32) -> Self{Synthetic{id, ..self}}
+	pub fn values(self, kind:Kind, values:impl Fn(i32, i32) -> f64+Send+Sync+'static) -> Self{
+		Synthetic{values:Some((kind, Arc::new(values))), ..self}
+	}
+	/// the same value everywhere
+	pub fn constant(self, kind:Kind, value:f64) -> Self{self.values(kind, move |_, _|value)}
+	/// count the reads of the pixels in reads, e.g. shared with other tiles
+	pub fn counting(self, reads:&Arc<AtomicUsize>) -> Self{Synthetic{reads:reads.clone(), ..self}}
+	pub fn boxed(self) -> Box<dyn Tile>{Box::new(self)}
+}
+
+impl Tile for Synthetic{
+	fn frame(&self) -> Rect<i32, PixelSpace> {self.frame}
+	fn stored_size(&self) -> Size2D<u32, PixelSpace> {self.stored}
+	fn plane(&self) -> Plane {self.plane}
+	fn pixel(&self) -> iobase::Result<Pixel> {
+		let Some((kind, values)) = &self.values else {return Err(Error::NotFound("Synthetic pixels".to_string()))};
+		self.reads.fetch_add(1, Ordering::SeqCst);
+		let (frame, stored) = (self.frame, self.stored.cast::<i32>());
+		let value = |(j, i):(usize, usize)|values(
+			frame.origin.x + i as i32*frame.width()/stored.width.max(1),
+			frame.origin.y + j as i32*frame.height()/stored.height.max(1)
+		);
+		let shape = (stored.height as usize, stored.width as usize);
+		Ok(match kind {
+			Kind::Gray8 => Pixel::Gray8(Array2::from_shape_fn(shape, |p|value(p) as u8)),
+			Kind::Gray16 => Pixel::Gray16(Array2::from_shape_fn(shape, |p|value(p) as u16)),
+			Kind::Gray32Float => Pixel::Gray32Float(Array2::from_shape_fn(shape, |p|value(p) as f32))
+		})
+	}
+	fn ordering_id(&self) -> i32 {self.id}
+}
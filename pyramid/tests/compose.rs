@@ -0,0 +1,51 @@
+mod common;
+
+use ndarray::s;
+use pyramid::{Blend, Pixel, Plane, Pyramid, Tile};
+use common::{Kind, Synthetic};
+
+/// two tiles overlapping at 10..20, the second one having the higher ordering id
+fn overlapping(scale:u32) -> Pyramid {
+	let tiles:Vec<Box<dyn Tile>> = vec![
+		Synthetic::new(10, 0, 20, 100).scale(scale).id(1).constant(Kind::Gray16, 20.0).boxed(),
+		Synthetic::new(0, 0, 20, 100).scale(scale).id(0).constant(Kind::Gray16, 10.0).boxed(),
+	];
+	Pyramid::new(tiles, 2)
+}
+
+fn row(pyramid:&Pyramid, level:usize, blend:Blend) -> Vec<u16> {
+	let Some(Pixel::Gray16(pixels)) = pyramid.compose(level, Plane::default(), euclid::rect(0, 0, 40, 100), blend).unwrap()
+		else {panic!("expected Gray16")};
+	pixels.slice(s![pixels.nrows()/2, ..]).to_vec()
+}
+
+#[test]
+fn blend_modes() {
+	let pyramid = overlapping(1);
+	let expect = |overlap:u16|[[10; 10], [overlap; 10], [20; 10], [0; 10]].concat();
+	assert_eq!(row(&pyramid, 0, Blend::LastWins), expect(20));
+	assert_eq!(row(&pyramid, 0, Blend::FirstWins), expect(10));
+	assert_eq!(row(&pyramid, 0, Blend::Max), expect(20));
+	assert_eq!(row(&pyramid, 0, Blend::Mean), expect(15));
+
+	let feathered = row(&pyramid, 0, Blend::Feather);
+	assert_eq!(feathered[..10], [10; 10]);
+	assert_eq!(feathered[20..], expect(0)[20..]);
+	let overlap = &feathered[10..20];
+	assert!(overlap.windows(2).all(|w|w[0] <= w[1]), "{overlap:?}");
+	assert!(overlap[0] <= 11 && overlap[9] >= 19, "{overlap:?}");
+}
+
+#[test]
+fn composed_levels() {
+	let pyramid = overlapping(2);
+	assert_eq!(pyramid.levels(), 2);
+	assert!(pyramid.compose(0, Plane::default(), euclid::rect(0, 0, 40, 100), Blend::Mean).unwrap().is_none());
+	let composed = row(&pyramid, 1, Blend::Mean);
+	assert_eq!(composed, [[10; 5], [15; 5], [20; 5], [0; 5]].concat());
+	// areas not starting at a multiple of the scale
+	let Some(Pixel::Gray16(pixels)) = pyramid.compose(1, Plane::default(), euclid::rect(5, 0, 10, 2), Blend::LastWins).unwrap()
+		else {panic!("expected Gray16")};
+	assert_eq!(pixels.dim(), (1, 5));
+	assert_eq!(pixels.row(0).to_vec(), [10, 10, 20, 20, 20]);
+}
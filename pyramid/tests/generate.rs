@@ -0,0 +1,51 @@
+mod common;
+
+use std::sync::atomic::{AtomicUsize, Ordering};
+use std::sync::Arc;
+use ndarray::array;
+use pyramid::downsample::box_average;
+use pyramid::{Pixel, Pyramid, Tile};
+use common::{Kind, Synthetic};
+
+/// a full resolution tile counting how often its pixels were read
+#[test]
+fn averaging() {
+	let a = array![[1u8, 2, 3], [3, 4, 5], [10, 20, 30]];
+	assert_eq!(box_average(&a, 2), array![[3u8, 4], [15, 30]]);
+	let big = array![[u64::MAX, u64::MAX-2]];
+	assert_eq!(box_average(&big, 2), array![[u64::MAX-1]]);
+	let bgr = array![[(0u8, 10u8, 255u8), (2, 20, 255)]];
+	assert_eq!(box_average(&bgr, 2), array![[(1u8, 15u8, 255u8)]]);
+	let float = Pixel::Gray32Float(array![[0.5f32, 1.0], [2.0, 4.5]]);
+	assert_eq!(float.downsample(2), Pixel::Gray32Float(array![[2.0f32]]));
+}
+
+#[test]
+fn generated_levels() {
+	let reads = Arc::new(AtomicUsize::new(0));
+	let tiles:Vec<Box<dyn Tile>> = [0, 20].into_iter()
+		.map(|x|Synthetic::new(x, 0, 20, 10).id(x).values(Kind::Gray16, |x, y|(x + y*100) as f64).counting(&reads).boxed())
+		.collect();
+	let mut pyramid = Pyramid::new(tiles, 2);
+	assert_eq!(pyramid.levels(), 1);
+
+	pyramid.generate(2);
+	assert_eq!(pyramid.levels(), 3);
+	assert_eq!(pyramid.generated_levels(), [1, 2]);
+	assert_eq!(pyramid.scale(2), 4.0);
+	assert_eq!(reads.load(Ordering::SeqCst), 0); // nothing computed yet
+
+	let top = &pyramid.level(2)[1];
+	assert_eq!(top.frame(), euclid::rect(20, 0, 20, 10));
+	assert_eq!(top.stored_size(), euclid::size2(5, 3));
+	let Pixel::Gray16(pixels) = top.pixel().unwrap() else {panic!("expected Gray16")};
+	assert_eq!(pixels.dim(), (3, 5));
+	// mean of the 4x4 block at 24/4 in full resolution is 25.5 + 550
+	assert_eq!(pixels[(1, 1)], 576);
+	assert_eq!(reads.load(Ordering::SeqCst), 1);
+
+	// the level in between was computed on the way and is cached as well as the top
+	pyramid.level(1)[1].pixel().unwrap();
+	top.pixel().unwrap();
+	assert_eq!(reads.load(Ordering::SeqCst), 1);
+}
@@ -0,0 +1,101 @@
+mod common;
+
+use pyramid::{Plane, Pyramid, Scale, Tile};
+use common::Synthetic;
+
+/// tile with the given frame, its stored size is downscaled by scale and rounded with round
+fn tile(id:i32, frame:(i32,i32,i32,i32), scale:(f64,f64), round:fn(f64)->f64) -> Box<dyn Tile> {
+	let stored = (round(frame.2 as f64/scale.0) as u32, round(frame.3 as f64/scale.1) as u32);
+	Synthetic::new(frame.0, frame.1, frame.2, frame.3).stored(stored.0, stored.1).id(id).boxed()
+}
+
+/// id of the tiles in each level
+fn ids(pyramid:&Pyramid) -> Vec<Vec<i32>> {
+	(0..pyramid.levels()).map(|l|pyramid.level(l).iter().map(|t|t.ordering_id()).collect()).collect()
+}
+
+#[test]
+fn rounded_stored_sizes() {
+	for round in [f64::ceil, f64::floor, f64::round] {
+		let mut tiles = vec![];
+		for (level,scale) in [1.0, 3.0, 9.0].into_iter().enumerate() {
+			let id = level as i32*10;
+			tiles.push(tile(id, (0, 0, 100, 100), (scale, scale), round));
+			// thin edge tiles, their short axis can't tell the level
+			tiles.push(tile(id+1, (100, 0, 2, 100), (scale, scale), round));
+			tiles.push(tile(id+2, (0, 100, 100, 4), (scale, scale), round));
+		}
+		let pyramid = Pyramid::new(tiles, 3);
+		assert_eq!(ids(&pyramid), [vec![0, 1, 2], vec![10, 11, 12], vec![20, 21, 22]]);
+		assert_eq!(pyramid.scale(0), 1.0);
+		assert!((pyramid.scale(1) - 3.0).abs() < 0.1, "{}", pyramid.scale(1));
+		assert!((pyramid.scale(2) - 9.0).abs() < 1.1, "{}", pyramid.scale(2)); // 100 pixels stored in 11 or 12
+	}
+}
+
+#[test]
+fn irregular_scales() {
+	// not quite a factor of 2 and different for both axes
+	let tiles = vec![
+		tile(0, (0, 0, 512, 256), (1.0, 1.0), f64::round),
+		tile(1, (0, 0, 512, 256), (2.2, 1.9), f64::round),
+		tile(2, (0, 0, 512, 256), (4.4, 3.8), f64::round),
+	];
+	let pyramid = Pyramid::new(tiles, 2);
+	assert_eq!(ids(&pyramid), [vec![0], vec![1], vec![2]]);
+	assert!((pyramid.scale(2) - 4.2).abs() < 0.1, "{}", pyramid.scale(2));
+	let Scale{x, y} = pyramid.level(1)[0].scale();
+	assert!((x - 2.2).abs() < 0.01 && (y - 1.9).abs() < 0.01, "{x} {y}");
+}
+
+#[test]
+fn missing_levels() {
+	let tiles = vec![
+		tile(0, (0, 0, 256, 256), (1.0, 1.0), f64::ceil),
+		tile(3, (0, 0, 256, 256), (8.0, 8.0), f64::ceil),
+		tile(1, (0, 0, 256, 256), (2.0, 2.0), f64::ceil),
+	];
+	let pyramid = Pyramid::new(tiles, 2);
+	assert_eq!(ids(&pyramid), [vec![0], vec![1], vec![], vec![3]]);
+	assert_eq!(pyramid.missing_levels(), [2]);
+	assert_eq!(pyramid.scale(2), 4.0);
+	assert_eq!(pyramid.scale(5), 32.0);
+}
+
+#[test]
+fn guessed_factor() {
+	let tiles = vec![
+		tile(0, (0, 0, 300, 300), (1.0, 1.0), f64::ceil),
+		tile(1, (0, 0, 300, 300), (4.0, 4.0), f64::ceil),
+		tile(2, (0, 0, 300, 300), (16.0, 16.0), f64::ceil),
+		tile(3, (0, 0, 3, 3), (4.0, 4.0), f64::ceil), // too small to be trusted
+	];
+	let pyramid = Pyramid::new(tiles, 0);
+	assert_eq!(pyramid.scaling_factor(), 4);
+	assert_eq!(ids(&pyramid), [vec![0], vec![1, 3], vec![2]]);
+}
+
+#[test]
+fn indexed_lookup() {
+	// overlapping tiles of varying size, some of them empty
+	let tiles:Vec<Box<dyn Tile>> = (0..400).map(|n|{
+		let (x, y) = ((n%20)*90, (n/20)*90);
+		let size = 100 - (n*7)%30 - if n%37 == 0 {100} else {0};
+		tile(n, (x, y, size.max(0), size.max(0)), (1.0, 1.0), f64::round)
+	}).collect();
+	let pyramid = Pyramid::new(tiles, 2);
+	let areas = [
+		euclid::rect(0, 0, 1, 1), euclid::rect(95, 95, 1, 1), euclid::rect(-50, -50, 50, 50),
+		euclid::rect(100, 0, 0, 500), euclid::rect(333, 777, 250, 120), euclid::rect(-10, -10, 3000, 3000),
+		euclid::rect(1700, 1700, 1000, 1000), euclid::rect(90, 0, 1, 1800),
+	];
+	for area in areas {
+		let indexed:Vec<i32> = pyramid.intersecting(0, Plane::default(), area).map(|t|t.ordering_id()).collect();
+		let linear:Vec<i32> = pyramid.tiles(0, Plane::default()).filter(|t|t.frame().intersects(&area)).map(|t|t.ordering_id()).collect();
+		assert_eq!(indexed, linear, "{area:?}");
+	}
+	// touching frames don't intersect
+	let touching:Vec<i32> = pyramid.intersecting(0, Plane::default(), euclid::rect(-10, 0, 10, 10)).map(|t|t.ordering_id()).collect();
+	assert!(touching.is_empty());
+	assert_eq!(pyramid.intersecting(0, Plane{c:1, z:0, t:0}, euclid::rect(0, 0, 100, 100)).count(), 0);
+}
@@ -0,0 +1,53 @@
+use ndarray::array;
+use num_complex::Complex;
+use pyramid::project::project;
+use pyramid::{Pixel, Projection};
+
+fn stack() -> Vec<iobase::Result<Pixel>> {
+	vec![
+		Ok(Pixel::Gray16(array![[1u16, 8], [3, 0]])),
+		Ok(Pixel::Gray16(array![[5u16, 2], [3, 0]])),
+		Ok(Pixel::Gray16(array![[3u16, 5], [3, 3]])),
+	]
+}
+
+#[test]
+fn projections() {
+	let projected = |projection|project(stack(), projection).unwrap().unwrap();
+	assert_eq!(projected(Projection::Max), Pixel::Gray16(array![[5u16, 8], [3, 3]]));
+	assert_eq!(projected(Projection::Min), Pixel::Gray16(array![[1u16, 2], [3, 0]]));
+	assert_eq!(projected(Projection::Sum), Pixel::Gray32Float(array![[9.0f32, 15.0], [9.0, 3.0]]));
+	assert_eq!(projected(Projection::Mean), Pixel::Gray32Float(array![[3.0f32, 5.0], [3.0, 1.0]]));
+	let Pixel::Gray32Float(deviation) = projected(Projection::StdDev) else {panic!("expected Gray32Float")};
+	assert_eq!(deviation[(1, 0)], 0.0);
+	assert!((deviation[(0, 0)] - (8.0f32/3.0).sqrt()).abs() < 1e-6);
+	assert!((deviation[(1, 1)] - 2f32.sqrt()).abs() < 1e-6);
+
+	assert_eq!(project([], Projection::Max).unwrap(), None);
+	let mut mixed = stack();
+	mixed.push(Ok(Pixel::Gray8(array![[1u8, 2], [3, 4]])));
+	assert!(project(mixed, Projection::Mean).is_err());
+	let mut resized = stack();
+	resized.push(Ok(Pixel::Gray16(array![[1u16]])));
+	assert!(project(resized, Projection::Max).is_err());
+}
+
+#[test]
+fn color_and_complex() {
+	let bgra = [
+		Pixel::Bgra32(array![[(10u8, 0u8, 200u8, 255u8)]]),
+		Pixel::Bgra32(array![[(20u8, 4u8, 100u8, 0u8)]]),
+	];
+	assert_eq!(project(bgra.clone().map(Ok), Projection::Max).unwrap(), Some(Pixel::Bgra32(array![[(20u8, 4u8, 200u8, 255u8)]])));
+	assert_eq!(project(bgra.map(Ok), Projection::Mean).unwrap(), Some(Pixel::Bgr96Float(array![[(15.0f32, 2.0f32, 150.0f32)]])));
+
+	let complex = [
+		Pixel::Gray64ComplexFloat(array![[Complex::new(0.0f32, 2.0)]]),
+		Pixel::Gray64ComplexFloat(array![[Complex::new(1.0f32, 0.0)]]),
+	];
+	assert_eq!(project(complex.clone().map(Ok), Projection::Min).unwrap(), Some(Pixel::Gray64ComplexFloat(array![[Complex::new(1.0f32, 0.0)]])));
+	assert_eq!(project(complex.clone().map(Ok), Projection::Sum).unwrap(), Some(Pixel::Gray64ComplexFloat(array![[Complex::new(1.0f32, 2.0)]])));
+	// the distance of both values to their mean is sqrt(1.25)
+	let Some(Pixel::Gray32Float(deviation)) = project(complex.map(Ok), Projection::StdDev).unwrap() else {panic!("expected Gray32Float")};
+	assert!((deviation[(0, 0)] - 1.25f32.sqrt()).abs() < 1e-6);
+}
@@ -0,0 +1,30 @@
+mod common;
+
+use pyramid::real::{pixel_to_real, to_pixels, to_real};
+use pyramid::{Blend, Pixel, Plane, Pyramid, Tile};
+use common::{Kind, Synthetic};
+
+#[test]
+fn pixels_and_micrometers() {
+	let transform = pixel_to_real(euclid::size2(0.5, 0.25), euclid::point2(1000.0, -20.0));
+	assert_eq!(to_real(&transform, euclid::rect(10, 40, 20, 8)), euclid::rect(1005.0, -10.0, 10.0, 2.0));
+	assert_eq!(to_pixels(&transform, euclid::rect(1005.0, -10.0, 10.0, 2.0)), Some(euclid::rect(10, 40, 20, 8)));
+	// partially covered pixels are included
+	assert_eq!(to_pixels(&transform, euclid::rect(1005.1, -10.0, 0.5, 0.3)), Some(euclid::rect(10, 40, 2, 2)));
+	assert_eq!(to_pixels(&pixel_to_real(euclid::size2(0.0, 1.0), euclid::point2(0.0, 0.0)), euclid::rect(0.0, 0.0, 1.0, 1.0)), None);
+}
+
+#[test]
+fn composed_in_micrometers() {
+	let tiles:Vec<Box<dyn Tile>> = vec![Synthetic::new(0, 0, 100, 100).values(Kind::Gray16, |x, y|(x + y*100) as f64).boxed()];
+	let pyramid = Pyramid::new(tiles, 2);
+	let area = euclid::rect(52.0, 51.0, 2.0, 1.0);
+	assert!(pyramid.compose_real(0, Plane::default(), area, Blend::LastWins).is_err());
+
+	let pyramid = pyramid.with_transform(pixel_to_real(euclid::size2(2.0, 2.0), euclid::point2(50.0, 50.0)));
+	assert_eq!(pyramid.to_real(euclid::rect(0, 0, 100, 100)), Some(euclid::rect(50.0, 50.0, 200.0, 200.0)));
+	let Some(Pixel::Gray16(pixels)) = pyramid.compose_real(0, Plane::default(), area, Blend::LastWins).unwrap()
+		else {panic!("expected Gray16")};
+	assert_eq!(pixels.dim(), (1, 1));
+	assert_eq!(pixels[(0, 0)], 1);
+}
@@ -0,0 +1,66 @@
+use std::f32::consts::PI;
+use ndarray::{array, s, Array3};
+use num_complex::Complex;
+use pyramid::render::{rgb, rgba, Channel, ComplexPart};
+use pyramid::Pixel;
+
+/// the red component of the first row
+fn red(rendered:&Array3<u8>) -> Vec<u8> {
+	rendered.slice(s![0, .., 0]).to_vec()
+}
+
+#[test]
+fn windowing_and_gamma() {
+	let gray = Pixel::Gray16(array![[100u16, 200, 300, 400]]);
+	let rendered = rgb(&[(&gray, Channel::new(200.0, 400.0))]).unwrap();
+	assert_eq!(rendered.dim(), (1, 4, 3));
+	assert_eq!(red(&rendered), [0, 0, 128, 255]);
+	assert_eq!(rendered[(0, 2, 1)], rendered[(0, 2, 2)]);
+	let darker = rgb(&[(&gray, Channel::new(200.0, 400.0).gamma(2.0))]).unwrap();
+	assert_eq!(darker[(0, 2, 0)], 64);
+
+	let full = Channel::default().full_range(&gray);
+	assert_eq!((full.min, full.max), (0.0, 65535.0));
+}
+
+#[test]
+fn additive_channels() {
+	let red = Pixel::Gray8(array![[255u8, 0]]);
+	let green = Pixel::Gray8(array![[255u8, 128]]);
+	let rendered = rgba(&[
+		(&red, Channel::default().color(255, 0, 0)),
+		(&green, Channel::default().color(0, 255, 0)),
+	]).unwrap();
+	assert_eq!(rendered.as_slice().unwrap(), [255, 255, 0, 255, 0, 128, 0, 255]);
+
+	let other_size = Pixel::Gray8(array![[1u8]]);
+	assert!(rgb(&[(&red, Channel::default()), (&other_size, Channel::default())]).is_err());
+	assert!(rgb(&[]).is_err());
+}
+
+#[test]
+fn color_pixels() {
+	// stored in BGR order
+	let bgra = Pixel::Bgra32(array![[(10u8, 20u8, 30u8, 51u8)]]);
+	assert_eq!(rgba(&[(&bgra, Channel::default())]).unwrap().as_slice().unwrap(), [30, 20, 10, 51]);
+	assert_eq!(rgb(&[(&bgra, Channel::default().color(255, 0, 255))]).unwrap().as_slice().unwrap(), [30, 0, 10]);
+
+	let float = Pixel::Bgr96Float(array![[(0.0f32, 0.5f32, 1.0f32), (f32::NAN, 2.0, -1.0)]]);
+	let channel = Channel::default().full_range(&float);
+	assert_eq!((channel.min, channel.max), (-1.0, 2.0));
+	assert_eq!(rgb(&[(&float, channel)]).unwrap().as_slice().unwrap(), [170, 128, 85, 0, 255, 0]);
+}
+
+#[test]
+fn complex_pixels() {
+	let complex = Pixel::Gray64ComplexFloat(array![[Complex::new(3.0f32, 4.0), Complex::new(0.0, -1.0), Complex::new(-1.0, 0.0)]]);
+	let magnitude = Channel::default().full_range(&complex);
+	assert_eq!((magnitude.min, magnitude.max), (1.0, 5.0));
+	assert_eq!(red(&rgb(&[(&complex, magnitude)]).unwrap()), [255, 0, 0]);
+
+	let phase = Channel::default().complex(ComplexPart::Phase).full_range(&complex);
+	assert_eq!(phase.max, std::f64::consts::PI);
+	let rendered = rgb(&[(&complex, phase)]).unwrap();
+	let expected = |angle:f32|((angle + PI)/(2.0*PI)*255.0).round() as u8;
+	assert_eq!(red(&rendered), [expected(4f32.atan2(3.0)), expected(-PI/2.0), 255]);
+}
@@ -0,0 +1,59 @@
+mod common;
+
+use std::sync::atomic::{AtomicUsize, Ordering};
+use std::sync::Arc;
+use ndarray::{array, Array2};
+use num_complex::Complex;
+use pyramid::resample::resample;
+use pyramid::{Blend, Filter, Pixel, Plane, Pyramid, Tile};
+use common::{Kind, Synthetic};
+
+#[test]
+fn filters() {
+	let a = array![[0u8, 100], [200, 40]];
+	assert_eq!(resample(&a, euclid::size2(4, 4), Filter::Nearest), array![
+		[0u8, 0, 100, 100], [0, 0, 100, 100], [200, 200, 40, 40], [200, 200, 40, 40]
+	]);
+	assert_eq!(resample(&a, euclid::size2(4, 1), Filter::Bilinear), array![[100u8, 93, 78, 70]]);
+	assert_eq!(resample(&a, euclid::size2(1, 1), Filter::Area), array![[85u8]]);
+	assert_eq!(resample(&a, euclid::size2(2, 2), Filter::Bilinear), a);
+
+	let row = array![[0.0f32, 1.0, 2.0, 3.0, 4.0, 5.0]];
+	assert_eq!(resample(&row, euclid::size2(2, 1), Filter::Area), array![[1.0f32, 4.0]]);
+	// partially covered source pixels count by their coverage
+	let partial = resample(&row, euclid::size2(4, 1), Filter::Area);
+	assert!((partial[(0, 0)] - 0.5/1.5).abs() < 1e-6, "{partial}");
+	assert_eq!(resample(&row, euclid::size2(3, 1), Filter::Bilinear), array![[0.5f32, 2.5, 4.5]]);
+
+	// all variants can be resampled
+	let bgr = Pixel::Bgr24(array![[(0u8, 10u8, 20u8), (100, 110, 120)]]);
+	assert_eq!(bgr.resample(euclid::size2(1, 1), Filter::Area), Pixel::Bgr24(array![[(50u8, 60u8, 70u8)]]));
+	let complex = Pixel::Gray64ComplexFloat(array![[Complex::new(1.0f32, -1.0), Complex::new(3.0, 1.0)]]);
+	assert_eq!(complex.resample(euclid::size2(1, 1), Filter::Bilinear), Pixel::Gray64ComplexFloat(array![[Complex::new(2.0f32, 0.0)]]));
+	let big = Pixel::Gray64(array![[u64::MAX - 1]]);
+	assert_eq!(big.resample(euclid::size2(3, 2), Filter::Bilinear), Pixel::Gray64(Array2::from_elem((2, 3), u64::MAX - 1)));
+}
+
+#[test]
+fn closest_level() {
+	let reads = Arc::new(AtomicUsize::new(0));
+	// tiles of 100x100 full resolution pixels, whose value is their scale
+	let tiles:Vec<Box<dyn Tile>> = [1, 4, 16].into_iter()
+		.map(|scale|Synthetic::new(0, 0, 100, 100).scale(scale).constant(Kind::Gray8, scale as f64).counting(&reads).boxed())
+		.collect();
+	let pyramid = Pyramid::new(tiles, 4);
+	assert_eq!(pyramid.level_for(1.0), 0);
+	assert_eq!(pyramid.level_for(3.9), 0);
+	assert_eq!(pyramid.level_for(4.0), 1);
+	assert_eq!(pyramid.level_for(15.0), 1);
+	assert_eq!(pyramid.level_for(100.0), 2);
+	assert_eq!(pyramid.level_for(0.5), 0);
+
+	let area = euclid::rect(0, 0, 100, 100);
+	let resampled = |size:usize|pyramid.resampled(Plane::default(), area, euclid::size2(size, size), Filter::Area, Blend::LastWins).unwrap();
+	assert_eq!(resampled(10), Some(Pixel::Gray8(Array2::from_elem((10, 10), 4))));
+	assert_eq!(resampled(5), Some(Pixel::Gray8(Array2::from_elem((5, 5), 16))));
+	assert_eq!(resampled(150), Some(Pixel::Gray8(Array2::from_elem((150, 150), 1))));
+	assert_eq!(reads.load(Ordering::SeqCst), 3);
+	assert_eq!(pyramid.resampled(Plane{c:1, z:0, t:0}, area, euclid::size2(10, 10), Filter::Area, Blend::LastWins).unwrap(), None);
+}
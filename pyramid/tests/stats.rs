@@ -0,0 +1,125 @@
+mod common;
+
+use std::sync::atomic::{AtomicUsize, Ordering};
+use std::sync::Arc;
+use ndarray::{array, Array2};
+use pyramid::stats::{statistics, BINS};
+use pyramid::{Pixel, Plane, Pyramid, Tile};
+use common::{Kind, Synthetic};
+
+/// a float tile with the value x+y at full resolution pixel x/y
+fn ramp(x:i32, width:i32, height:i32) -> Synthetic {
+	Synthetic::new(x, 0, width, height).id(x).values(Kind::Gray32Float, |x, y|(x + y) as f64)
+}
+
+#[test]
+fn gray_and_color() {
+	let gray = Pixel::Gray8(array![[0u8, 10, 10, 255], [20, 30, 40, 255]]);
+	let [stats] = statistics([&gray]).unwrap().try_into().unwrap();
+	assert_eq!((stats.count, stats.min, stats.max, stats.saturated), (8, 0.0, 255.0, 2));
+	assert_eq!(stats.mean, 620.0/8.0);
+	assert_eq!(stats.histogram.bins.len(), 256);
+	assert_eq!(stats.histogram.bins[10], 2);
+	assert_eq!([0.0, 25.0, 50.0, 75.0, 100.0].map(|p|stats.percentile(p)), [0.0, 10.0, 20.0, 40.0, 255.0]);
+
+	let more = Pixel::Gray8(array![[5u8]]);
+	assert_eq!(statistics([&gray, &more]).unwrap()[0].count, 9);
+	assert!(statistics([&gray, &Pixel::Gray16(array![[5u16]])]).is_err());
+
+	let bgra = Pixel::Bgra32(array![[(1u8, 2u8, 3u8, 255u8), (3, 4, 5, 255)]]);
+	let stats = statistics([&bgra]).unwrap();
+	assert_eq!(stats.len(), 4);
+	assert_eq!(stats.iter().map(|s|s.mean).collect::<Vec<_>>(), [2.0, 3.0, 4.0, 255.0]);
+	assert_eq!(stats[3].saturated, 2);
+}
+
+#[test]
+fn float_histogram() {
+	let values:Vec<f32> = (0..1000).map(|v|v as f32/10.0).chain([f32::NAN, f32::INFINITY, f32::NEG_INFINITY]).collect();
+	let float = Pixel::Gray32Float(Array2::from_shape_vec((1, values.len()), values).unwrap());
+	let [stats] = statistics([&float]).unwrap().try_into().unwrap();
+	assert_eq!((stats.count, stats.min, stats.max, stats.saturated), (1002, f64::NEG_INFINITY, f64::INFINITY, 1));
+	// the bins cover the finite values, infinities go into the edge bins
+	let histogram = &stats.histogram;
+	assert_eq!((histogram.start, histogram.bins.len()), (0.0, BINS));
+	assert_eq!(histogram.width, 99.9f32 as f64/BINS as f64);
+	assert_eq!((histogram.bins[0], histogram.bins[BINS - 1]), (2, 2));
+	assert_eq!(histogram.bins.iter().filter(|&&b|b > 0).count(), 1000);
+	assert_eq!(histogram.bins.iter().sum::<u64>(), 1002);
+	assert_eq!(histogram.bins[(50.0/histogram.width) as usize], 1);
+
+	// later values beyond the range of the first ones merge the bins
+	let low = Pixel::Gray32Float(array![[0.0f32, 1.0, f32::INFINITY]]);
+	let high = Pixel::Gray32Float(array![[3.5f32, -1.5]]);
+	let [stats] = statistics([&low, &high]).unwrap().try_into().unwrap();
+	let histogram = &stats.histogram;
+	assert_eq!((histogram.start, histogram.width*BINS as f64, histogram.bins.len()), (-4.0, 8.0, BINS));
+	let bin = |v:f64|((v - histogram.start)/histogram.width) as usize;
+	assert_eq!([0.0, 3.5, -1.5].map(|v|histogram.bins[bin(v)]), [1, 1, 1]);
+	// the largest value of the first range was in its last bin, which ends at 1.0
+	assert_eq!(histogram.bins[bin(1.0) - 1], 1);
+	assert_eq!(histogram.bins[BINS - 1], 1);
+	assert_eq!(histogram.bins.iter().sum::<u64>(), 5);
+
+	// a single value gets a single bin
+	let [stats] = statistics([&Pixel::Gray32Float(array![[2.5f32, 2.5]])]).unwrap().try_into().unwrap();
+	assert_eq!((stats.histogram.start, stats.histogram.bins.clone()), (2.5, vec![2]));
+
+	let finite = Pixel::Gray32Float(Array2::from_shape_fn((10, 100), |(y, x)|(x + y*100) as f32/10.0));
+	let [stats] = statistics([&finite]).unwrap().try_into().unwrap();
+	assert_eq!(stats.max, 99.9f32 as f64);
+	let median = stats.percentile(50.0);
+	assert!((median - 49.9).abs() < 0.1, "{median}");
+	assert_eq!(stats.percentile(100.0), stats.histogram.bin_start(BINS - 1));
+}
+
+#[test]
+fn levels_and_areas() {
+	let reads = Arc::new(AtomicUsize::new(0));
+	let mut tiles:Vec<Box<dyn Tile>> = vec![];
+	for scale in [1, 4] {
+		for x in [0, 100] {
+			tiles.push(ramp(x, 100, 100).scale(scale).counting(&reads).boxed());
+		}
+	}
+	let pyramid = Pyramid::new(tiles, 4);
+	let exact = &pyramid.statistics(0, Plane::default(), None).unwrap()[0];
+	// the second tile goes beyond the range of the first one, so both are read again for the exact histogram
+	assert_eq!(reads.load(Ordering::Relaxed), 4);
+	assert_eq!((exact.count, exact.min, exact.max, exact.mean), (20000, 0.0, 298.0, 149.0));
+	assert_eq!((exact.histogram.start, exact.histogram.bin_start(BINS)), (0.0, 298.0));
+	assert_eq!(exact.histogram.bins.iter().sum::<u64>(), 20000);
+	let approximate = &pyramid.statistics(1, Plane::default(), None).unwrap()[0];
+	assert_eq!((approximate.count, approximate.min, approximate.max), (1250, 0.0, 292.0));
+	// the level samples the top left pixel of each 4x4 block
+	assert_eq!(approximate.mean, exact.mean - 3.0);
+
+	let area = euclid::rect(90, 10, 20, 5);
+	let cropped = &pyramid.statistics(0, Plane::default(), Some(area)).unwrap()[0];
+	assert_eq!((cropped.count, cropped.min, cropped.max), (100, 100.0, 123.0));
+	let cropped = &pyramid.statistics(1, Plane::default(), Some(area)).unwrap()[0];
+	assert_eq!((cropped.count, cropped.min, cropped.max), (12, 96.0, 120.0));
+	assert!(pyramid.statistics(0, Plane{c:1, z:0, t:0}, None).unwrap().is_empty());
+}
+
+#[test]
+fn overlapping_tiles() {
+	let tiles:Vec<Box<dyn Tile>> = [0, 60, 30].into_iter()
+		.map(|x|ramp(x, 100, 50).boxed())
+		.collect();
+	let pyramid = Pyramid::new(tiles, 4);
+	// each of the 160x50 pixels counts once
+	let [stats] = pyramid.statistics(0, Plane::default(), None).unwrap().try_into().unwrap();
+	assert_eq!((stats.count, stats.min, stats.max, stats.mean), (8000, 0.0, 208.0, 104.0));
+	let histogram = &stats.histogram;
+	assert_eq!((histogram.start, histogram.bin_start(BINS)), (0.0, 208.0));
+	assert_eq!(histogram.bins.iter().sum::<u64>(), 8000);
+	// the value 0 only occurs once, at the upper left corner
+	assert_eq!(histogram.bins[0], 1);
+	assert_eq!(stats.percentile(0.0), 0.0);
+
+	// only the area where all three overlap
+	let area = euclid::rect(60, 0, 40, 10);
+	let [stats] = pyramid.statistics(0, Plane::default(), Some(area)).unwrap().try_into().unwrap();
+	assert_eq!((stats.count, stats.min, stats.max), (400, 60.0, 108.0));
+}
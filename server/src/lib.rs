@@ -0,0 +1,172 @@
+use axum::{
+	http::StatusCode,
+	response::IntoResponse,
+	routing::{get, post},
+	Json, Router,
+	extract::Extension
+};
+use serde::Deserialize;
+use std::collections::HashMap;
+use std::path::PathBuf;
+use std::sync::{Arc, Mutex};
+use axum::extract::Path;
+use axum::response::{Redirect, Response};
+use uuid::Uuid;
+use db::{DB, Error, ImageInfo, RegisterSuccess};
+use iobase::basic::Cached;
+use iobase::source::Source;
+use zisraw::compression::decode;
+use zisraw::nonblocking;
+use zisraw::structs::{DimensionEntryDV1, DirectoryEntryDV, Directory};
+use zisraw::ZisrawInterface;
+
+/// Error of a handler, responded with a status code that matches its cause and the error message as body.
+pub struct ApiError(StatusCode,String);
+
+impl From<StatusCode> for ApiError{
+	fn from(status: StatusCode) -> Self {
+		ApiError(status,status.canonical_reason().unwrap_or_default().to_string())
+	}
+}
+
+impl From<Error> for ApiError{
+	fn from(e: Error) -> Self {
+		let status = match e.cause() {
+			Error::NotFound(_) => StatusCode::NOT_FOUND,
+			Error::Io(io) => match io.kind() {
+				std::io::ErrorKind::NotFound => StatusCode::NOT_FOUND,
+				std::io::ErrorKind::PermissionDenied => StatusCode::FORBIDDEN,
+				_ => StatusCode::INTERNAL_SERVER_ERROR
+			},
+			// the file is there but is not a (valid) zisraw file
+			Error::Truncated{..} | Error::BadMagic{..} | Error::InvalidData(_) | Error::Xml(_) | Error::LimitExceeded{..} =>
+				StatusCode::UNPROCESSABLE_ENTITY,
+			Error::UnsupportedCompression(_) => StatusCode::UNSUPPORTED_MEDIA_TYPE,
+			Error::Sql(_) => StatusCode::INTERNAL_SERVER_ERROR,
+			// cause() already looked through the segment the error occurred in
+			Error::Segment{..} => unreachable!("cause() never returns a segment error")
+		};
+		ApiError(status,e.to_string())
+	}
+}
+
+impl IntoResponse for ApiError{
+	fn into_response(self) -> Response {
+		(self.0,self.1).into_response()
+	}
+}
+
+/// An opened image file with its parsed directory.
+struct OpenImage{
+	file:Arc<dyn Source>,
+	directory:Directory
+}
+
+fn open_image(path:&PathBuf) -> db::Result<OpenImage>{
+	let file:Arc<dyn Source> = Arc::new(std::fs::File::open(path)?);
+	let directory = zisraw::get_file_header(&file)?.get_directory(&file)?;
+	Ok(OpenImage{file, directory})
+}
+
+/// memory used by the directory, estimated by the size of its entries
+fn image_weight(_:&PathBuf, image:&OpenImage) -> usize{
+	image.directory.Entries.iter()
+		.map(|e|size_of::<DirectoryEntryDV>()+e.dimension_map.len()*(size_of::<(String,DimensionEntryDV1)>()+4))
+		.sum()
+}
+
+/// The opened images by their file name, so tile requests don't parse header and directory each time.
+///
+/// The directories are managed by the global [iobase::cache::CacheManager] and read again after they were dropped.
+#[derive(Default)]
+struct Images(Mutex<HashMap<PathBuf,Arc<Cached<PathBuf,OpenImage>>>>);
+
+impl Images {
+	fn get(&self, path:PathBuf) -> Arc<Cached<PathBuf,OpenImage>>{
+		let mut images = self.0.lock().unwrap_or_else(|e|e.into_inner());
+		images.entry(path.clone()).or_insert_with(||Arc::new(Cached::with_weight(path,open_image,image_weight))).clone()
+	}
+}
+
+/// build the application with all routes serving the given database
+pub fn app(db:DB) -> Router {
+	let state = Arc::new(Mutex::new(db));
+	let images = Arc::new(Images::default());
+	Router::new()
+		// `GET / redirects to `images`
+		.route("/", get(|| async { Redirect::permanent("/images") }))
+		.route("/images", get(get_images))
+		.route("/images", post(register_image))
+		.route("/images/:uuid", get(get_image))
+		.route("/images/:uuid/xml", get(get_image_xml))
+		.route("/images/:uuid/thumbnail", get(get_image_thumbnail))
+		.route("/images/:uuid/subblocks/:index", get(get_subblock))
+		.layer(Extension(state))
+		.layer(Extension(images))
+}
+
+/// Run f with the database on the blocking thread pool.
+///
+/// So neither waiting for the lock nor the queries (or the files read by them) stall the runtime.
+async fn with_db<T:Send+'static>(db:Arc<Mutex<DB>>, f:impl FnOnce(&DB)->db::Result<T>+Send+'static) -> Result<T,ApiError> {
+	let ret = nonblocking::unblock(move ||f(&db.lock().unwrap_or_else(|e|e.into_inner()))).await?;
+	Ok(ret)
+}
+
+async fn get_images(Extension(db): Extension<Arc<Mutex<DB>>>) -> Result<Json<Vec<ImageInfo>>,ApiError> {
+	let images = with_db(db, |db|db.query_images(None)).await?;
+	Ok(Json(images))
+}
+
+async fn get_image(Path(id):Path<Uuid>, Extension(db): Extension<Arc<Mutex<DB>>>) -> Result<Json<ImageInfo>,ApiError> {
+	let image = with_db(db, move |db|db.get_image(id)).await?
+		.ok_or(StatusCode::NOT_FOUND)?;
+	Ok(Json(image))
+}
+
+#[derive(Deserialize)]
+struct RegisterImagePayload{filename:PathBuf}
+async fn register_image(Json(payload):Json<RegisterImagePayload>, Extension(db): Extension<Arc<Mutex<DB>>>) -> Result<Response,ApiError> {
+	let filename = payload.filename;
+	match tokio::fs::metadata(&filename).await {
+		Err(_) => return Err(StatusCode::NOT_FOUND.into()),
+		Ok(m) if !m.is_file() => return Err(StatusCode::NOT_ACCEPTABLE.into()),
+		Ok(_) => {}
+	}
+	match with_db(db, move |db|db.register_file(&filename)).await? {
+		RegisterSuccess::Inserted => Ok(StatusCode::CREATED.into_response()),
+		RegisterSuccess::ImageExists(e) => Ok((StatusCode::ACCEPTED,Json(e)).into_response()),
+		RegisterSuccess::FileExists => Ok(StatusCode::ALREADY_REPORTED.into_response())
+	}
+}
+
+
+async fn get_image_xml(Path(id):Path<Uuid>,Extension(db): Extension<Arc<Mutex<DB>>>) -> Result<Response,ApiError> {
+	let xml=with_db(db, move |db|db.get_image_xml(id)).await?
+		.ok_or(StatusCode::NOT_FOUND)?;
+	Ok((axum::TypedHeader(axum::headers::ContentType::xml()),xml).into_response())
+}
+
+async fn get_image_thumbnail(Path(id):Path<Uuid>,Extension(db): Extension<Arc<Mutex<DB>>>) -> Result<Response,ApiError> {
+	let image=with_db(db, move |db|db.get_image_thumbnail(id)).await?
+		.ok_or(StatusCode::NOT_FOUND)?;
+	Ok((axum::TypedHeader(axum::headers::ContentType::jpeg()),image).into_response())
+}
+
+/// the decoded pixel data of a subblock, the index refers to the directory of the image
+///
+/// - the file is kept open together with its parsed directory for the following requests (see [Images])
+async fn get_subblock(
+	Path((id,index)):Path<(Uuid,usize)>, Extension(db): Extension<Arc<Mutex<DB>>>, Extension(images): Extension<Arc<Images>>
+) -> Result<Response,ApiError> {
+	let filename = with_db(db, move |db|db.lookup_filenames(&id)).await?
+		.into_iter().next()
+		.ok_or(StatusCode::NOT_FOUND)?;
+	let image = images.get(filename);
+	let image = nonblocking::unblock(move ||image.get()).await?;
+	let entry = image.directory.Entries.get(index).ok_or(StatusCode::NOT_FOUND)?;
+	let subblock = nonblocking::read_subblock(&image.file,entry).await?;
+	let compression = entry.Compression.into();
+	let pixels = nonblocking::unblock(move ||decode(compression,&subblock.Data.get()?)).await?;
+	Ok((axum::TypedHeader(axum::headers::ContentType::octet_stream()),pixels).into_response())
+}
@@ -1,19 +1,7 @@
-use axum::{
-	http::StatusCode,
-	response::IntoResponse,
-	routing::{get, post},
-	Json, Router,
-	extract::Extension
-};
-use serde::Deserialize;
 use std::net::SocketAddr;
 use std::path::PathBuf;
-use std::sync::{Arc, Mutex};
 use argh::FromArgs;
-use axum::extract::Path;
-use axum::response::{Redirect, Response};
-use uuid::Uuid;
-use db::{DB, ImageInfo, RegisterSuccess};
+use db::DB;
 
 #[derive(FromArgs, PartialEq, Debug)]
 #[argh(description = "sqlite backed registry for czi files")]
@@ -35,19 +23,9 @@ async fn main() {
 	let cli: Cli = argh::from_env();
 	println!("opening database {}",cli.dbfile.to_string_lossy());
 	let db=DB::new(&cli.dbfile).unwrap();
-	let state = Arc::new(Mutex::new(db));
 
 	// build our application with a route
-	let app = Router::new()
-		// `GET / redirects to `images`
-		.route("/", get(|| async { Redirect::permanent("/images") }))
-		.route("/images", get(get_images))
-		.route("/images", post(register_image))
-		.route("/images/:uuid", get(get_image))
-		.route("/images/:uuid/xml", get(get_image_xml))
-		.route("/images/:uuid/thumbnail", get(get_image_thumbnail))
-		.layer(Extension(state))
-		;
+	let app = server::app(db);
 
 	// run our app with hyper
 	// `axum::Server` is a re-export of `hyper::Server`
@@ -58,55 +36,3 @@ async fn main() {
 		.await
 		.unwrap();
 }
-
-async fn get_images(Extension(db): Extension<Arc<Mutex<DB>>>) -> Result<Json<Vec<ImageInfo>>,StatusCode> {
-	match db.lock().unwrap().query_images(None){
-		Ok(images) => Ok(Json(images)),
-		Err(e) => Err(StatusCode::INTERNAL_SERVER_ERROR)
-	}
-}
-
-async fn get_image(Path(id):Path<Uuid>, Extension(db): Extension<Arc<Mutex<DB>>>) -> Result<Json<ImageInfo>,StatusCode> {
-	let image = db.lock().unwrap().get_image(id)
-		.or(Err(StatusCode::INTERNAL_SERVER_ERROR))?
-		.ok_or(StatusCode::NOT_FOUND)?;
-	Ok(Json(image))
-}
-
-#[derive(Deserialize)]
-struct RegisterImagePayload{filename:PathBuf}
-async fn register_image(Json(payload):Json<RegisterImagePayload>, Extension(db): Extension<Arc<Mutex<DB>>>) -> Result<Response,StatusCode> {
-	if ! payload.filename.exists(){
-		return Err(StatusCode::NOT_FOUND);
-	}
-	if ! payload.filename.is_file(){
-		return Err(StatusCode::NOT_ACCEPTABLE)
-	}
-	// todo handle missing read access
-	match db.lock().unwrap().register_file(&payload.filename){
-		Ok(r) => {
-			match r {
-				RegisterSuccess::Inserted => Ok(StatusCode::CREATED.into_response()),
-				RegisterSuccess::ImageExists(e) => Ok((StatusCode::ACCEPTED,Json(e)).into_response()),
-				RegisterSuccess::FileExists => Ok(StatusCode::ALREADY_REPORTED.into_response())
-			}
-
-		}
-		Err(e) => Err(StatusCode::INTERNAL_SERVER_ERROR)
-	}
-}
-
-
-async fn get_image_xml(Path(id):Path<Uuid>,Extension(db): Extension<Arc<Mutex<DB>>>) -> Result<Response,StatusCode> {
-	let xml=db.lock().unwrap()
-		.get_image_xml(id).or(Err(StatusCode::INTERNAL_SERVER_ERROR))?
-		.ok_or(StatusCode::NOT_FOUND)?;
-	Ok((axum::TypedHeader(axum::headers::ContentType::xml()),xml).into_response())
-}
-
-async fn get_image_thumbnail(Path(id):Path<Uuid>,Extension(db): Extension<Arc<Mutex<DB>>>) -> Result<Response,StatusCode> {
-	let image=db.lock().unwrap()
-		.get_image_thumbnail(id).or(Err(StatusCode::INTERNAL_SERVER_ERROR))?
-		.ok_or(StatusCode::NOT_FOUND)?;
-	Ok((axum::TypedHeader(axum::headers::ContentType::jpeg()),image).into_response())
-}
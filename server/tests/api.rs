@@ -0,0 +1,103 @@
+use std::path::Path;
+use axum::body::Body;
+use axum::http::{Request, StatusCode};
+use axum::Router;
+use fixture::{temp_path, Fixture};
+use tower::ServiceExt;
+use uuid::Uuid;
+use zisraw::compression::Compression;
+use db::DB;
+
+fn app(name:&str) -> Router {
+	let dbfile = temp_path!(name);
+	std::fs::remove_file(&dbfile).ok();
+	server::app(DB::new(&dbfile).unwrap())
+}
+
+async fn get(app:&Router, uri:&str) -> (StatusCode, Vec<u8>) {
+	let response = app.clone()
+		.oneshot(Request::get(uri).body(Body::empty()).unwrap())
+		.await.unwrap();
+	let status = response.status();
+	(status, hyper::body::to_bytes(response.into_body()).await.unwrap().to_vec())
+}
+
+async fn register(app:&Router, filename:&Path) -> StatusCode {
+	let body = serde_json::json!({"filename":filename}).to_string();
+	let request = Request::post("/images")
+		.header("content-type", "application/json")
+		.body(Body::from(body)).unwrap();
+	app.clone().oneshot(request).await.unwrap().status()
+}
+
+#[tokio::test]
+async fn register_and_get() {
+	let app = app("server_register.db");
+	let guid = Uuid::from_u128(10);
+	let path = temp_path!("server_register.czi");
+	Fixture::new(16, 16).guid(guid).thumbnail(b"thumb").write(&path).unwrap();
+
+	assert_eq!(register(&app, &path).await, StatusCode::CREATED);
+	assert_eq!(register(&app, &path).await, StatusCode::ALREADY_REPORTED);
+	assert_eq!(register(&app, &temp_path!("does_not_exist.czi")).await, StatusCode::NOT_FOUND);
+
+	let (status, body) = get(&app, "/images").await;
+	assert_eq!(status, StatusCode::OK);
+	let images: serde_json::Value = serde_json::from_slice(&body).unwrap();
+	assert_eq!(images[0]["guid"], guid.to_string());
+
+	let (status, body) = get(&app, &format!("/images/{guid}")).await;
+	assert_eq!(status, StatusCode::OK);
+	let image: serde_json::Value = serde_json::from_slice(&body).unwrap();
+	assert_eq!(image["orig_path"], "fixture.czi");
+
+	let (status, body) = get(&app, &format!("/images/{guid}/thumbnail")).await;
+	assert_eq!((status, body.as_slice()), (StatusCode::OK, b"thumb".as_slice()));
+
+	let (status, body) = get(&app, &format!("/images/{guid}/xml")).await;
+	assert_eq!(status, StatusCode::OK);
+	assert!(String::from_utf8(body).unwrap().contains("<SizeX>16</SizeX>"));
+}
+
+#[tokio::test]
+async fn unknown_images() {
+	let app = app("server_unknown.db");
+	let guid = Uuid::from_u128(11);
+
+	assert_eq!(get(&app, &format!("/images/{guid}")).await.0, StatusCode::NOT_FOUND);
+	assert_eq!(get(&app, &format!("/images/{guid}/thumbnail")).await.0, StatusCode::NOT_FOUND);
+	assert_eq!(get(&app, &format!("/images/{guid}/xml")).await.0, StatusCode::NOT_FOUND);
+	assert_eq!(get(&app, "/").await.0, StatusCode::PERMANENT_REDIRECT);
+}
+
+#[tokio::test]
+async fn invalid_files() {
+	let app = app("server_invalid.db");
+	let path = temp_path!("server_invalid.czi");
+	std::fs::write(&path, b"definitely not a czi file").unwrap();
+
+	assert_eq!(register(&app, &path).await, StatusCode::UNPROCESSABLE_ENTITY);
+	assert_eq!(register(&app, Path::new(env!("CARGO_TARGET_TMPDIR"))).await, StatusCode::NOT_ACCEPTABLE);
+}
+
+#[tokio::test]
+async fn subblocks() {
+	let app = app("server_subblocks.db");
+	let guid = Uuid::from_u128(12);
+	let path = temp_path!("server_subblocks.czi");
+	let fixture = Fixture::new(8, 4).guid(guid).compression(Compression::Zstd1);
+	fixture.write(&path).unwrap();
+	assert_eq!(register(&app, &path).await, StatusCode::CREATED);
+
+	let (status, body) = get(&app, &format!("/images/{guid}/subblocks/0")).await;
+	assert_eq!(status, StatusCode::OK);
+	let fixture = &fixture;
+	let expected:Vec<u8> = (0..4).flat_map(|y|(0..8).flat_map(move |x|fixture.pixel_bytes(x, y, 0, 0, 0))).collect();
+	assert_eq!(body, expected);
+	// the opened file and its directory are kept for the next request
+	std::fs::remove_file(&path).unwrap();
+	assert_eq!(get(&app, &format!("/images/{guid}/subblocks/0")).await, (StatusCode::OK, expected));
+
+	assert_eq!(get(&app, &format!("/images/{guid}/subblocks/1")).await.0, StatusCode::NOT_FOUND);
+	assert_eq!(get(&app, &format!("/images/{}/subblocks/0", Uuid::from_u128(13))).await.0, StatusCode::NOT_FOUND);
+}
@@ -0,0 +1,105 @@
+use std::sync::Arc;
+use iobase::blockbuf::Limits;
+use iobase::source::{Prefetched, Source};
+use crate::Result;
+use crate::segment::{Reader, SegmentBlock};
+use crate::structs::{DirectoryEntryDV, SubBlock};
+
+/// Options for reading many subblocks at once with [read_subblocks].
+#[derive(Debug,Clone)]
+pub struct BatchOptions{
+	/// subblocks starting at most this many bytes after the previous one are read together
+	pub max_distance:u64,
+	/// maximum size of a single read in bytes
+	pub max_read:u64,
+	/// bytes read after the start of the last subblock of a read, before its actual size is known
+	pub tail:u64,
+	/// limits for reading each subblock
+	pub limits:Limits
+}
+
+impl Default for BatchOptions{
+	fn default() -> Self {
+		BatchOptions{max_distance:4<<20, max_read:64<<20, tail:1<<20, limits:Limits::default()}
+	}
+}
+
+/// Read the subblocks of many directory entries with as few reads as possible.
+///
+/// - the entries are sorted by their position and nearby subblocks are read together
+/// - all entries must belong to the given file (part)
+/// - the subblocks are returned in the order of the entries
+/// - the subblocks keep the data that was read together with them in memory until all of them are dropped
+pub fn read_subblocks(file:&Arc<dyn Source>, entries:&[DirectoryEntryDV], options:&BatchOptions) -> Result<Vec<SubBlock>>{
+	let mut order:Vec<usize> = (0..entries.len()).collect();
+	order.sort_by_key(|&i|entries[i].FilePosition);
+
+	let reader = Reader::new(file)?.with_limits(options.limits.clone());
+	let mut subblocks:Vec<Option<SubBlock>> = std::iter::repeat_with(||None).take(entries.len()).collect();
+	for group in groups(&order,entries,options) {
+		let positions:Vec<u64> = group.iter().map(|&i|entries[i].FilePosition).collect();
+		let window = reader.through(Arc::new(prefetch(&reader,&positions,options)?));
+		for &i in group {
+			let s = window.segment(entries[i].FilePosition)?;
+			subblocks[i] = match s.block {
+				SegmentBlock::ImageSubBlock(s) => Some(s),
+				_ => return Err(s.unexpected("ZISRAWSUBBLOCK"))
+			};
+		}
+	}
+	Ok(subblocks.into_iter().flatten().collect())
+}
+
+/// split the sorted indices into groups that are read together
+fn groups<'a>(order:&'a [usize], entries:&[DirectoryEntryDV], options:&BatchOptions) -> Vec<&'a [usize]>{
+	let mut ret = vec![];
+	let mut first = 0;
+	for i in 1..order.len() {
+		let pos = entries[order[i]].FilePosition;
+		if pos - entries[order[i-1]].FilePosition > options.max_distance
+			|| pos - entries[order[first]].FilePosition > options.max_read {
+			ret.push(&order[first..i]);
+			first = i;
+		}
+	}
+	if first < order.len() {
+		ret.push(&order[first..]);
+	}
+	ret
+}
+
+/// Read everything from the first to the end of the last segment at the sorted positions.
+///
+/// The size of the segments is only known after reading their header.
+/// So the read goes [BatchOptions::tail] beyond the last position and is extended if that was not enough.
+fn prefetch(reader:&Reader, positions:&[u64], options:&BatchOptions) -> Result<Prefetched>{
+	let (file,file_size) = (reader.source(),reader.len());
+	let start = positions[0].min(file_size);
+	let last = positions[positions.len()-1].min(file_size);
+	let mut end = last.saturating_add(options.tail).min(file_size);
+	let mut data = read(file,start,end)?;
+
+	let needed = positions.iter()
+		.filter_map(|&pos|segment_end(data.get(usize::try_from(pos-start).ok()?..)?).and_then(|size|pos.checked_add(size)))
+		.max().unwrap_or(end)
+		.min(file_size);
+	// corrupt sizes are left to the segment reader
+	if needed > end && needed - start <= options.max_read {
+		data.extend(read(file,end,needed)?);
+		end = needed;
+	}
+	debug_assert_eq!(data.len() as u64, end-start);
+	Ok(Prefetched::new(file.clone(),start,data))
+}
+
+fn read(file:&Arc<dyn Source>, start:u64, end:u64) -> Result<Vec<u8>>{
+	let mut data = vec![0;(end-start) as usize];
+	file.read_exact_at(&mut data,start)?;
+	Ok(data)
+}
+
+/// size of the segment (including its header) whose header is at the beginning of data
+fn segment_end(data:&[u8]) -> Option<u64>{
+	let allocated = u64::from_le_bytes(data.get(16..24)?.try_into().ok()?);
+	allocated.checked_add(32)
+}
@@ -0,0 +1,123 @@
+use iobase::Error;
+use crate::Result;
+
+/// Compression schemes as stored in DirectoryEntryDV::Compression
+#[derive(Debug,Clone,Copy,PartialEq,Eq)]
+pub enum Compression{
+	Uncompressed,
+	JpgFile,
+	Lzw,
+	JpgXrFile,
+	/// plain zstd stream
+	Zstd0,
+	/// zstd stream with a small header, optionally using hi/lo byte packing
+	Zstd1,
+	Other(i32)
+}
+
+impl From<i32> for Compression{
+	fn from(value: i32) -> Self {
+		match value {
+			0 => Compression::Uncompressed,
+			1 => Compression::JpgFile,
+			2 => Compression::Lzw,
+			4 => Compression::JpgXrFile,
+			5 => Compression::Zstd0,
+			6 => Compression::Zstd1,
+			v => Compression::Other(v)
+		}
+	}
+}
+
+impl From<Compression> for i32{
+	fn from(value: Compression) -> Self {
+		match value {
+			Compression::Uncompressed => 0,
+			Compression::JpgFile => 1,
+			Compression::Lzw => 2,
+			Compression::JpgXrFile => 4,
+			Compression::Zstd0 => 5,
+			Compression::Zstd1 => 6,
+			Compression::Other(v) => v
+		}
+	}
+}
+
+/// chunk type of the only chunk currently defined for the Zstd1 header
+const ZSTD1_CHUNK_HILO:u8 = 1;
+
+/// Decode a subblock payload into raw pixel data.
+///
+/// Only lossless compressions are supported, everything else returns an error.
+pub fn decode(compression:Compression, data:&[u8]) -> Result<Vec<u8>>{
+	match compression {
+		Compression::Uncompressed => Ok(data.to_vec()),
+		Compression::Lzw => weezl::decode::Decoder::with_tiff_size_switch(weezl::BitOrder::Msb, 8)
+			.decode(data)
+			.map_err(|e|Error::InvalidData(format!("Failed to decode lzw data: {e}"))),
+		Compression::Zstd0 => Ok(zstd::decode_all(data)?),
+		Compression::Zstd1 => {
+			let (hilo,payload) = parse_zstd1_header(data)?;
+			let decoded = zstd::decode_all(payload)?;
+			if hilo {unpack_hilo(&decoded)} else {Ok(decoded)}
+		}
+		c => Err(Error::UnsupportedCompression(format!("{c:?}")))
+	}
+}
+
+/// Compress raw pixel data with zstd.
+///
+/// - uses Zstd1 (with hi/lo byte packing of 16bit words) if hilo is set, plain Zstd0 otherwise
+/// - returns the compression scheme that was used together with the encoded data
+pub fn encode_zstd(data:&[u8], level:i32, hilo:bool) -> Result<(Compression,Vec<u8>)>{
+	if hilo{
+		let mut ret = vec![3,ZSTD1_CHUNK_HILO,1];
+		zstd::stream::copy_encode(pack_hilo(data)?.as_slice(),&mut ret,level)?;
+		Ok((Compression::Zstd1,ret))
+	} else {
+		Ok((Compression::Zstd0,zstd::encode_all(data,level)?))
+	}
+}
+
+/// Split the header off a Zstd1 payload.
+///
+/// Returns whether hi/lo packing is used and the remaining zstd stream.
+fn parse_zstd1_header(data:&[u8]) -> Result<(bool,&[u8])>{
+	let header_size = *data.first().ok_or(Error::InvalidData("Empty Zstd1 payload".to_string()))? as usize;
+	if header_size == 0 || header_size > data.len() {
+		return Err(Error::InvalidData(format!("Invalid Zstd1 header size {header_size}")));
+	}
+	let mut hilo = false;
+	let mut chunks = &data[1..header_size];
+	while let Some((&chunk_type,rest)) = chunks.split_first(){
+		match chunk_type {
+			ZSTD1_CHUNK_HILO => {
+				let (&flags,rest) = rest.split_first()
+					.ok_or(Error::InvalidData("Truncated Zstd1 header".to_string()))?;
+				hilo = flags & 1 != 0;
+				chunks = rest;
+			}
+			t => return Err(Error::InvalidData(format!("Unknown Zstd1 header chunk {t}")))
+		}
+	}
+	Ok((hilo,&data[header_size..]))
+}
+
+/// Pack 16bit words so that all low bytes come first, followed by all high bytes.
+fn pack_hilo(data:&[u8]) -> Result<Vec<u8>>{
+	if !data.len().is_multiple_of(2) {
+		return Err(Error::InvalidData("Hi/lo packing needs an even amount of bytes".to_string()));
+	}
+	let lo = data.iter().step_by(2);
+	let hi = data.iter().skip(1).step_by(2);
+	Ok(lo.chain(hi).cloned().collect())
+}
+
+/// Reverse of [pack_hilo]
+fn unpack_hilo(data:&[u8]) -> Result<Vec<u8>>{
+	if !data.len().is_multiple_of(2) {
+		return Err(Error::InvalidData("Hi/lo packed data must have an even amount of bytes".to_string()));
+	}
+	let (lo,hi) = data.split_at(data.len()/2);
+	Ok(lo.iter().zip(hi).flat_map(|(l,h)|[*l,*h]).collect())
+}
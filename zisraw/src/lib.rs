@@ -3,30 +3,45 @@
 use std::borrow::Borrow;
 use std::collections::HashMap;
 use std::iter::Iterator;
-use std::os::unix::fs::FileExt;
+use iobase::source::Source;
 use std::sync::Arc;
 use uom::si::{f64::Length,length::meter};
 use iobase::Result;
-use std::io::{ErrorKind::InvalidData};
+use iobase::Error;
 use std::str::FromStr;
-use chrono::{DateTime, Local, TimeZone};
+use chrono::{DateTime, Local, NaiveDateTime, TimeZone};
 
 pub mod structs;
 pub mod utils;
-mod segment;
+pub mod segment;
+pub mod compression;
+pub mod writer;
+pub mod transcode;
+pub mod parts;
+pub mod batch;
+pub mod tiles;
+#[cfg(feature = "tokio")]
+pub mod nonblocking;
 
 use utils::XmlUtil;
 
-pub fn get_file_header(file:&Arc<dyn FileExt>) -> Result<structs::FileHeader>{
-	let s = segment::Segment::new(file, 0)?;
+pub fn get_file_header(file:&Arc<dyn Source>) -> Result<structs::FileHeader>{
+	read_file_header(&segment::Reader::new(file)?)
+}
+
+/// [get_file_header] with the limits of reader
+pub fn read_file_header(reader:&segment::Reader) -> Result<structs::FileHeader>{
+	let s = reader.segment(0)?;
 	match s.block {
 		segment::SegmentBlock::FileHeader(hd) => Ok(hd),
-		_ => Err(std::io::Error::new(InvalidData,"Unexpected block when looking for header").into())
+		_ => Err(s.unexpected("ZISRAWFILE"))
 	}
 }
 
 #[derive(Debug)]
 pub struct Scene{
+	/// the scene index as used by the S dimension of directory entries
+	pub Index:i32,
 	pub RegionId:String,
 	pub PyramidLayersCount:usize,
 	pub MinificationFactor:i32
@@ -44,31 +59,35 @@ pub struct ImageInfo{
 }
 
 pub trait ZisrawInterface{
-	fn get_metadata(&self,file:&Arc<dyn FileExt>) -> Result<structs::Metadata>;
-	fn get_directory(&self,file:&Arc<dyn FileExt>) -> Result<structs::Directory>;
-	fn get_attachments(&self,file:&Arc<dyn FileExt>)-> Result<Vec<structs::AttachmentEntryA1>>;
+	fn get_metadata(&self,file:&Arc<dyn Source>) -> Result<structs::Metadata>;
+	fn get_directory(&self,file:&Arc<dyn Source>) -> Result<structs::Directory>;
+	fn get_attachments(&self,file:&Arc<dyn Source>)-> Result<Vec<structs::AttachmentEntryA1>>;
 
-	fn get_metadata_xml(&self,file:&Arc<dyn FileExt>) -> Result<String>{
+	fn get_metadata_xml(&self,file:&Arc<dyn Source>) -> Result<String>{
 		let e = self.get_metadata(file)?;
 		Ok(e.cache.source.clone())
 	}
-	fn get_timestamp(&self,file:&Arc<dyn FileExt>) -> Result<DateTime<Local>>{
+	fn get_timestamp(&self,file:&Arc<dyn Source>) -> Result<DateTime<Local>>{
 		let meta = self.get_metadata(file)?.as_tree()?;
 		let timestamp = meta //first find an entry with a timestamp and use it as string
 			.drill_down(["Information","Image","AcquisitionDateAndTime"].borrow())
 			.or_else(|_|meta.drill_down(["Information","Document","CreationDate"].borrow()))?
 			.get_text()
-			.ok_or(std::io::Error::new(InvalidData,"No text"))?;
-		let timestamp = DateTime::<Local>::from_str(timestamp.as_ref())
-				.or_else(|_|Local.datetime_from_str(timestamp.as_ref(),"%FT%T"))?;
+			.ok_or(Error::Xml("AcquisitionDateAndTime has no text".to_string()))?;
+		let timestamp = DateTime::<Local>::from_str(timestamp.as_ref()).map(Some)
+				.or_else(|_|NaiveDateTime::parse_from_str(timestamp.as_ref(),"%FT%T")
+					.map(|t|Local.from_local_datetime(&t).earliest()))
+				.map_err(|e|Error::Xml(format!("Failed to parse timestamp {timestamp}: {e}")))?
+				.ok_or(Error::Xml(format!("Timestamp {timestamp} does not exist in the local timezone")))?;
 		Ok(timestamp)
 	}
-	fn get_image_info(&self,file:&Arc<dyn FileExt>) -> Result<ImageInfo>{
+	fn get_image_info(&self,file:&Arc<dyn Source>) -> Result<ImageInfo>{
 		let scaling_path=["Scaling","Items"];
 		let mut meta = self.get_metadata(file)?.as_tree()?;
 		let image_props = meta
-			.take_child("Information").unwrap()
-			.take_child("Image").unwrap();
+			.take_child("Information")
+			.and_then(|mut e|e.take_child("Image"))
+			.ok_or(Error::Xml("Information/Image missing in metadata".to_string()))?;
 		let scaling_el = meta
 			.drill_down(&scaling_path)
 			.or(image_props.drill_down(&scaling_path));
@@ -85,24 +104,25 @@ pub trait ZisrawInterface{
 			pixel_type: image_props.child_into("PixelType")?,
 			timestamp: self.get_timestamp(file)?,
 			acquisition_duration: image_props.child_into("AcquisitionDuration")
-				.and_then(|d|Ok(std::time::Duration::from_secs_f32(d))).ok(),
+				.map(std::time::Duration::from_secs_f32).ok(),
 			mosaic_tiles: image_props.child_into("SizeM").ok(),
 			scenes:vec![]
 		};
 
-		if scaling_el.is_ok(){
-			let scaling_el= scaling_el.unwrap()
+		if let Ok(scaling_el) = scaling_el{
+			let scaling_el= scaling_el
 				.collect_attributed_values("Distance","Id")
 				.unwrap_or_default()
 				.into_iter().map(|(k,v)|(k.to_ascii_lowercase(),Length::new::<meter>(v)));
 			info.pixel_size=scaling_el.collect();
 		}
 
-		if scenes.is_some() { // no scenes => no pyramid => flat image
-			let scenes = scenes.unwrap().children.iter().filter_map(|n|n.as_element());
-			for e in scenes{
+		if let Some(scenes) = scenes { // no scenes => no pyramid => flat image
+			let scenes = scenes.children.iter().filter_map(|n|n.as_element());
+			for (i,e) in scenes.enumerate(){
 				let pinfo=e.drill_down(["PyramidInfo"].borrow())?;
 				info.scenes.push(Scene{
+					Index: e.attributes.get("Index").and_then(|i|i.parse().ok()).unwrap_or(i as i32),
 					RegionId: e.child_into("RegionId")?,
 					PyramidLayersCount: pinfo.child_into("PyramidLayersCount")?,
 					MinificationFactor: pinfo.child_into("MinificationFactor")?
@@ -111,17 +131,16 @@ pub trait ZisrawInterface{
 		}
 		Ok(info)
 	}
-	fn get_thumbnail(&self, file:&Arc<dyn FileExt>) -> Result<Option<structs::Attachment>>{
+	fn get_thumbnail(&self, file:&Arc<dyn Source>) -> Result<Option<structs::Attachment>>{
 		let thumbnail = self.get_attachments(file)?
 			.into_iter()
-			.filter(|a|a.Name=="Thumbnail")
-			.next();
+			.find(|a|a.Name=="Thumbnail");
 
-		if thumbnail.is_some(){
-			let att = segment::Segment::new(file,thumbnail.unwrap().FilePosition)?;
+		if let Some(thumbnail) = thumbnail{
+			let att = segment::Segment::new(file,thumbnail.FilePosition)?;
 			let att= match att.block{
 				segment::SegmentBlock::Attachment(a) => a,
-				_ => return Err(std::io::Error::new(InvalidData,"Unexpected block when looking for attachment").into())
+				_ => return Err(att.unexpected("ZISRAWATTACH"))
 			};
 			Ok(Some(att))
 		} else {Ok(None)}
@@ -0,0 +1,64 @@
+//! Async versions of the readers for use in async runtimes (e.g. the server).
+//!
+//! All reading happens on tokio's blocking thread pool, so it doesn't stall the runtime.
+//! Subblocks are returned with their data already read, so [iobase::DataFromFile::get] won't block on I/O.
+use std::path::Path;
+use std::fs::File;
+use std::sync::Arc;
+use iobase::source::Source;
+use iobase::Error;
+use crate::{Result, ZisrawInterface};
+use crate::batch::BatchOptions;
+use crate::segment::Segment;
+use crate::structs::{DirectoryEntryDV, Directory, FileHeader, Metadata, SubBlock};
+
+/// run a blocking read on the blocking thread pool
+pub async fn unblock<T:Send+'static>(read:impl FnOnce()->Result<T>+Send+'static) -> Result<T>{
+	tokio::task::spawn_blocking(read).await
+		.map_err(|e|Error::Io(std::io::Error::other(e)))?
+}
+
+/// open a file for reading
+pub async fn open(path:impl AsRef<Path>) -> Result<Arc<dyn Source>>{
+	let path = path.as_ref().to_path_buf();
+	unblock(move ||{
+		let file:Arc<dyn Source> = Arc::new(File::open(path)?);
+		Ok(file)
+	}).await
+}
+
+/// async version of [Segment::new]
+pub async fn segment(file:&Arc<dyn Source>, pos:u64) -> Result<Segment>{
+	let file = file.clone();
+	unblock(move ||Segment::new(&file,pos)).await
+}
+
+/// async version of [crate::get_file_header]
+pub async fn get_file_header(file:&Arc<dyn Source>) -> Result<FileHeader>{
+	let file = file.clone();
+	unblock(move ||crate::get_file_header(&file)).await
+}
+
+/// async version of [ZisrawInterface::get_directory]
+pub async fn get_directory(file:&Arc<dyn Source>, hd:&FileHeader) -> Result<Directory>{
+	let (file,hd) = (file.clone(),hd.clone());
+	unblock(move ||hd.get_directory(&file)).await
+}
+
+/// async version of [ZisrawInterface::get_metadata]
+pub async fn get_metadata(file:&Arc<dyn Source>, hd:&FileHeader) -> Result<Metadata>{
+	let (file,hd) = (file.clone(),hd.clone());
+	unblock(move ||hd.get_metadata(&file)).await
+}
+
+/// read the subblock of a directory entry including its data
+pub async fn read_subblock(file:&Arc<dyn Source>, entry:&DirectoryEntryDV) -> Result<SubBlock>{
+	let mut subblocks = read_subblocks(file,std::slice::from_ref(entry),&BatchOptions::default()).await?;
+	subblocks.pop().ok_or(Error::NotFound("Subblock".to_string()))
+}
+
+/// async version of [crate::batch::read_subblocks]
+pub async fn read_subblocks(file:&Arc<dyn Source>, entries:&[DirectoryEntryDV], options:&BatchOptions) -> Result<Vec<SubBlock>>{
+	let (file,entries,options) = (file.clone(),entries.to_vec(),options.clone());
+	unblock(move ||crate::batch::read_subblocks(&file,&entries,&options)).await
+}
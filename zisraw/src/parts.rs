@@ -0,0 +1,135 @@
+use std::fs::File;
+use iobase::Error;
+use iobase::source::Source;
+use std::path::{Path, PathBuf};
+use std::sync::Arc;
+use uuid::Uuid;
+use crate::Result;
+use crate::structs::*;
+use crate::transcode::{finish_copy, read_subblock, readers, verify_pixels};
+use crate::writer::FileWriter;
+
+/// File names for the parts of a multi part image, following the "name.czi", "name(1).czi", ... convention.
+pub fn part_file_names(primary:&Path, parts:usize) -> Vec<PathBuf>{
+	let stem = primary.file_stem().unwrap_or_default().to_string_lossy();
+	let extension = primary.extension().map(|e|format!(".{}",e.to_string_lossy())).unwrap_or_default();
+	(0..parts).map(|i|
+		if i == 0 {primary.to_path_buf()}
+		else {primary.with_file_name(format!("{stem}({i}){extension}"))}
+	).collect()
+}
+
+/// Open all parts of a multi part image.
+///
+/// - the given files may be in any order, the returned files are ordered by their FilePart
+/// - fails if the files don't belong to the same image or if any part is missing or given twice
+pub fn open_parts(files:&[PathBuf]) -> Result<Vec<Arc<dyn Source>>>{
+	if files.is_empty() {
+		return Err(Error::InvalidData("No file parts given".to_string()));
+	}
+	let mut parts:Vec<Option<(FileHeader,Arc<dyn Source>)>> = std::iter::repeat_with(||None).take(files.len()).collect();
+	for name in files{
+		let file:Arc<dyn Source> = Arc::new(File::open(name)?);
+		let hd = crate::get_file_header(&file)?;
+		let slot = usize::try_from(hd.FilePart).ok()
+			.and_then(|i|parts.get_mut(i))
+			.ok_or(Error::InvalidData(format!("Unexpected file part {} in {}",hd.FilePart,name.to_string_lossy())))?;
+		if slot.is_some() {
+			return Err(Error::InvalidData(format!("File part {} was given twice",hd.FilePart)));
+		}
+		*slot = Some((hd,file));
+	}
+	let parts:Vec<(FileHeader,Arc<dyn Source>)> = parts.into_iter().flatten().collect();
+	// no slot can be empty, as there are as many slots as files and no slot was filled twice
+	let primary = parts[0].0.FileGuid;
+	if let Some((hd,_)) = parts.iter().find(|(hd,_)|hd.PrimaryFileGuid != primary){
+		return Err(Error::InvalidData(format!("File part {} belongs to a different image",hd.FilePart)));
+	}
+	Ok(parts.into_iter().map(|(_,f)|f).collect())
+}
+
+/// Split an image into as many parts as there are targets.
+///
+/// - subblocks are distributed in directory order, so that all parts get about the same amount of data
+/// - metadata, attachments and the directory go into the first (primary) part
+/// - the written parts are compared against the source afterwards
+pub fn split(source:&Path, targets:&[PathBuf]) -> Result<()>{
+	if targets.is_empty() {
+		return Err(Error::InvalidData("Need at least one target to split into".to_string()));
+	}
+	let file:[Arc<dyn Source>;1] = [Arc::new(File::open(source)?)];
+	let parts = readers(&file)?;
+	let hd = crate::read_file_header(&parts[0])?;
+	let directory = hd.read_directory(&parts[0])?;
+	if hd.FilePart != 0 || hd.PrimaryFileGuid != hd.FileGuid {
+		return Err(Error::InvalidData("Source is already part of a multi part image, merge it first".to_string()));
+	}
+
+	let mut subblocks = Vec::with_capacity(directory.Entries.len());
+	for entry in &directory.Entries{
+		subblocks.push(read_subblock(&parts,entry)?);
+	}
+	let total:u64 = subblocks.iter().map(|s|s.Data.size() as u64).sum();
+	let per_part = total.div_ceil(targets.len() as u64).max(1);
+
+	let mut writers = targets.iter().map(|t|FileWriter::create(t)).collect::<Result<Vec<_>>>()?;
+	let mut entries = Vec::with_capacity(subblocks.len());
+	let mut written = 0;
+	for (entry,subblock) in directory.Entries.iter().zip(subblocks){
+		let part = ((written/per_part) as usize).min(targets.len()-1);
+		written += subblock.Data.size() as u64;
+		let attachment = match subblock.Attachment.as_ref() {
+			Some(a) => a.get()?.to_vec(),
+			None => vec![]
+		};
+		let entry = DirectoryEntryDV{FilePart:part as i32, ..entry.clone()};
+		entries.push(writers[part].write_subblock(&entry,&subblock.Metadata.source,&subblock.Data.get()?,&attachment)?);
+	}
+
+	let mut writers = writers.into_iter();
+	let mut primary = writers.next().unwrap();// there is at least one target
+	let header = finish_copy(&file,&hd,&mut primary,entries)?;
+	primary.finish(&header)?;
+	for (i,writer) in writers.enumerate(){
+		writer.finish(&FileHeader{
+			FileGuid:Uuid::new_v4(),
+			FilePart:i as i32+1,
+			DirectoryPosition:0,
+			MetadataPosition:0,
+			AttachmentDirectoryPosition:0,
+			..header.clone()
+		})?;
+	}
+
+	verify_pixels(&file,&open_parts(targets)?)
+}
+
+/// Merge all parts of a multi part image into a single file.
+///
+/// - the parts may be given in any order
+/// - the merged file keeps the guid of the primary part
+/// - the merged file is compared against the parts afterwards
+pub fn merge(parts:&[PathBuf], target:&Path) -> Result<()>{
+	let files = open_parts(parts)?;
+	let readers = readers(&files)?;
+	let hd = crate::read_file_header(&readers[0])?;
+	let directory = hd.read_directory(&readers[0])?;
+
+	let mut writer = FileWriter::create(target)?;
+	let mut entries = Vec::with_capacity(directory.Entries.len());
+	for entry in &directory.Entries{
+		let subblock = read_subblock(&readers,entry)?;
+		let attachment = match subblock.Attachment.as_ref() {
+			Some(a) => a.get()?.to_vec(),
+			None => vec![]
+		};
+		let entry = DirectoryEntryDV{FilePart:0, ..entry.clone()};
+		entries.push(writer.write_subblock(&entry,&subblock.Metadata.source,&subblock.Data.get()?,&attachment)?);
+	}
+	let hd = FileHeader{FilePart:0, PrimaryFileGuid:hd.FileGuid, ..hd};
+	let header = finish_copy(&files,&hd,&mut writer,entries)?;
+	writer.finish(&header)?;
+
+	let merged:Arc<dyn Source> = Arc::new(File::open(target)?);
+	verify_pixels(&files,&[merged])
+}
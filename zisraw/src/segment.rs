@@ -1,18 +1,32 @@
-use std::io::{Error, ErrorKind};
-use iobase::blockbuf::{BlockBuf, BlockRead};
+use iobase::blockbuf::{BlockBuf, BlockRead, Limits};
 use std::sync::Arc;
-use std::os::unix::fs::FileExt;
+use iobase::source::Source;
 use iobase::Endian::Little;
 use crate::Result;
 use super::structs::*;
-use uuid::Uuid;
 use xmltree::Element;
-use iobase::{basic::Cached,DataFromFile};
+use iobase::{basic::Cached,DataFromFile,Error};
 
 pub fn parse_xml(source:&String) ->Result<Element>{
-	Element::parse(source.as_bytes()).or_else(|e|Err(e.into()))
+	Element::parse(source.as_bytes()).map_err(|e|Error::Xml(e.to_string()))
 }
 
+/// memory used by parsed xml, estimated by the size of its text
+#[allow(clippy::ptr_arg)] // has to match the signature expected by Cached
+fn xml_weight(source:&String, _:&Element) -> usize{
+	source.len()*2
+}
+
+/// Convert a size or count read from the file, failing on negative or oversized values.
+pub(crate) fn size_from<T>(value:T, what:&str) -> Result<usize> where T:Copy+std::fmt::Display, usize:TryFrom<T>{
+	usize::try_from(value).map_err(|_|Error::InvalidData(format!("Invalid {what} {value}")))
+}
+
+/// ids of all segments known to [Segment::new]
+const SEGMENT_IDS:[&str;7] = [
+	"ZISRAWFILE", "ZISRAWDIRECTORY", "ZISRAWSUBBLOCK", "ZISRAWMETADATA", "ZISRAWATTACH", "ZISRAWATTDIR", "DELETED"
+];
+
 #[derive(Debug)]
 pub struct Segment{
 	pub allocated_size:u64,
@@ -21,37 +35,98 @@ pub struct Segment{
 	pub block:SegmentBlock,
 }
 
-impl Segment{
-	pub fn new(file:&Arc<dyn FileExt>,pos:u64) -> std::io::Result<Self>{
+/// A source to read segments from, together with the [Limits] to apply.
+///
+/// - the length of the source is queried once, when the reader is created
+#[derive(Debug,Clone)]
+pub struct Reader{
+	source:Arc<dyn Source>,
+	limits:Limits,
+	len:u64
+}
+
+impl Reader{
+	/// A reader with the default [Limits].
+	pub fn new(source:&Arc<dyn Source>) -> Result<Self>{
+		Ok(Reader{source:source.clone(), limits:Limits::default(), len:source.len()?})
+	}
+	pub fn with_limits(self, limits:Limits) -> Self{Reader{limits, ..self}}
+	/// the same reader going through source, which must show the same bytes (e.g. a prefetched part of it)
+	pub(crate) fn through(&self, source:Arc<dyn Source>) -> Self{Reader{source, ..self.clone()}}
+	pub fn source(&self) -> &Arc<dyn Source>{&self.source}
+	pub fn limits(&self) -> &Limits{&self.limits}
+	/// the length of the source when the reader was created
+	pub fn len(&self) -> u64{self.len}
+	pub fn is_empty(&self) -> bool{self.len == 0}
+	/// Read the segment at pos (see [Segment::with_limits]).
+	pub fn segment(&self, pos:u64) -> Result<Segment>{
 		//create buffer block beginning with the segment
-		let mut buffer=BlockBuf::new(file.clone(),pos,Little)?;// prepare and read 1k for now
+		let mut buffer=BlockBuf::new(self.source.clone(),pos,Little)?.with_limits(self.limits.clone());
 		// get header from there
-		let id= buffer.get_ascii::<16>()?;
-		let allocated_size = buffer.get_scalar()?;
-		let used_size = buffer.get_scalar()?;
+		let id= buffer.get_ascii::<16>().map_err(|e|e.in_segment("unknown",pos))?;
+		Segment::read_block(buffer,&id,pos,self.len).map_err(|e|e.in_segment(&id,pos))
+	}
+}
 
-		// now that we know the segments size we go back an resize the buffer which might read more data
-		buffer.resize(32+allocated_size as usize)?;
+impl Segment{
+	/// Read the segment at pos with the default [Limits].
+	///
+	/// - all errors are wrapped into [Error::Segment] with the segment id (if it could be read) and pos
+	/// - unknown segment ids result in [Error::BadMagic]
+	/// - use a [Reader] to read more than one segment
+	pub fn new(file:&Arc<dyn Source>,pos:u64) -> Result<Self>{
+		Reader::new(file)?.segment(pos)
+	}
+	/// Read the segment at pos.
+	///
+	/// - the used size of the segment must be within the file
+	/// - reading is restricted to the allocated size of the segment, which must not exceed limits.max_block_size
+	/// - see [Segment::new]
+	pub fn with_limits(file:&Arc<dyn Source>,pos:u64,limits:&Limits) -> Result<Self>{
+		Reader::new(file)?.with_limits(limits.clone()).segment(pos)
+	}
+	fn read_block(mut buffer:BlockBuf,id:&str,pos:u64,file_size:u64) -> Result<Self>{
+		if !SEGMENT_IDS.contains(&id) {
+			return Err(Error::BadMagic{expected:"a segment id".to_string(), found:id.to_string()});
+		}
+		let allocated_size:u64 = buffer.get_scalar()?;
+		let used_size:u64 = buffer.get_scalar()?;
+		if used_size > allocated_size {
+			return Err(Error::InvalidData(format!("Used size {used_size} exceeds the allocated size {allocated_size}")));
+		}
+		if used_size.saturating_add(32) > file_size.saturating_sub(pos) {
+			return Err(Error::Truncated{offset:pos, needed:used_size.saturating_add(32), available:file_size.saturating_sub(pos)});
+		}
+		// now that we know the segments size, make sure we never read beyond it
+		let segment_size = allocated_size.checked_add(32)
+			.ok_or(Error::InvalidData(format!("Invalid allocated size {allocated_size}")))?;
+		buffer.limit_to(segment_size)?;
 
 		let s = Segment{
 			pos,
 			allocated_size,
 			used_size: {if used_size==0 {allocated_size} else {used_size}},
-			block: match id.as_str() {
+			block: match id {
 				"ZISRAWFILE" => SegmentBlock::FileHeader(buffer.read()?),
 				"ZISRAWATTDIR" => SegmentBlock::AttachmentDirectory(buffer.read()?),
 				"ZISRAWMETADATA" => SegmentBlock::Metadata(buffer.read()?),
 				"ZISRAWSUBBLOCK" => SegmentBlock::ImageSubBlock(buffer.read()?),
 				"ZISRAWDIRECTORY" => SegmentBlock::Directory(buffer.read()?),
 				"ZISRAWATTACH" => SegmentBlock::Attachment(buffer.read()?),
-				_ => SegmentBlock::DELETED
+				_ => SegmentBlock::Deleted
 			}
 		};
 		Ok(s)
 	}
+	/// The error to return if this segment is not the expected one.
+	pub fn unexpected(&self, expected:&str) -> Error{
+		Error::BadMagic{expected:expected.to_string(), found:self.block.id().to_string()}
+			.in_segment(self.block.id(),self.pos)
+	}
 }
 
 #[derive(Debug)]
+#[allow(clippy::large_enum_variant)]
 pub enum SegmentBlock{
 	// File Header segment, occurs only once per file. The segment is always located at position 0.
 	FileHeader(FileHeader),
@@ -68,54 +143,55 @@ pub enum SegmentBlock{
 	// Attachments directory.
 	AttachmentDirectory(AttachmentDirectory),
 	// Indicates that the segment has been deleted (dropped) and should be skipped or ignored by readers.
-	DELETED
+	Deleted
 }
 
-impl BlockRead for FileHeader{
-	fn read(buffer: &mut BlockBuf) -> std::io::Result<Self> {
-		let version = buffer.get_array()?;
-		buffer.skip_to(16).unwrap();
-		let PrimaryFileGuid = Uuid::from_slice(buffer.drain(16)?.as_slice()).unwrap();//impossible to fail
-		let FileGuid = Uuid::from_slice(buffer.drain(16)?.as_slice()).unwrap();
-		Ok(FileHeader{
-			version, PrimaryFileGuid, FileGuid,
-			FilePart: buffer.get_scalar()?,
-			DirectoryPosition: buffer.get_scalar()?,
-			MetadataPosition: buffer.get_scalar()?,
-			UpdatePending: buffer.get_scalar::<i32>()?!=0,
-			AttachmentDirectoryPosition: buffer.get_scalar()?
-		})
+impl SegmentBlock {
+	/// the id of the segment the block was read from
+	pub fn id(&self) -> &'static str{
+		match self {
+			SegmentBlock::FileHeader(_) => "ZISRAWFILE",
+			SegmentBlock::Directory(_) => "ZISRAWDIRECTORY",
+			SegmentBlock::ImageSubBlock(_) => "ZISRAWSUBBLOCK",
+			SegmentBlock::Metadata(_) => "ZISRAWMETADATA",
+			SegmentBlock::Attachment(_) => "ZISRAWATTACH",
+			SegmentBlock::AttachmentDirectory(_) => "ZISRAWATTDIR",
+			SegmentBlock::Deleted => "DELETED"
+		}
 	}
 }
 
+/// Fail unless the entry at the current position has the given schema, without draining anything.
+fn expect_schema(buffer:&BlockBuf, schema:&str) -> Result<()>{
+	let found = buffer.peek(|b|b.get_ascii::<2>())?;
+	if found == schema {Ok(())}
+	else {Err(Error::BadMagic{expected:format!("{schema} entry"), found})}
+}
+
 impl BlockRead for Metadata{
-	fn read(buffer: &mut BlockBuf) -> std::io::Result<Self> {
+	fn read(buffer: &mut BlockBuf) -> Result<Self> {
 		let xml_size:i32= buffer.get_scalar()?;
-		match buffer.skip_to(256)?.get_utf8(xml_size as usize){
-			Ok(s) => Ok(Metadata{cache: Cached::new(s, parse_xml)}),
-			Err(e) => Err(Error::new(ErrorKind::InvalidData,"Failed to read xml string"))
-		}
+		let xml = buffer.skip_to(256)?.get_utf8(size_from(xml_size,"xml size")?)?;
+		Ok(Metadata{cache: Cached::with_weight(xml, parse_xml, xml_weight)})
 	}
 }
 
 impl BlockRead for SubBlock{
-	fn read(buffer:&mut BlockBuf) -> std::io::Result<Self> {
+	fn read(buffer:&mut BlockBuf) -> Result<Self> {
 		let metadata_size:u32 = buffer.get_scalar()?;
 		let attachment_size:u32= buffer.get_scalar()?;
 		let data_size:u64 = buffer.get_scalar()?;
+		expect_schema(buffer,"DV")?;
 		let Entry = buffer.read()?;
 
-		buffer.skip_to(256).ok();
-		let Metadata = match buffer.get_utf8(metadata_size as usize){
-			Ok(s) => Cached::new(s, parse_xml),
-			Err(e) => return Err(Error::new(ErrorKind::InvalidData,"Failed to read xml string"))
-		};
+		buffer.skip_to(256).ok(); // the entry might be longer than 256 bytes, then the metadata follows directly
+		let Metadata = Cached::with_weight(buffer.get_utf8(size_from(metadata_size,"metadata size")?)?, parse_xml, xml_weight);
 
-		let Data = buffer.get_cached_data(data_size as usize);
+		let Data = buffer.get_cached_data(size_from(data_size,"data size")?)?;
 
 		let Attachment:Option<DataFromFile> =
 			if attachment_size>0 {
-				Some(buffer.get_cached_data(attachment_size as usize))
+				Some(buffer.get_cached_data(size_from(attachment_size,"attachment size")?)?)
 			} else {
 				None
 			};
@@ -123,75 +199,13 @@ impl BlockRead for SubBlock{
 	}
 }
 
-impl BlockRead for DimensionEntryDV1{
-	fn read(buffer: &mut BlockBuf) -> std::io::Result<Self> {
-		Ok(DimensionEntryDV1{
-			Dimension: buffer.get_ascii::<4>()?,
-			Start: buffer.get_scalar()?,
-			Size: buffer.get_scalar()?,
-			StartCoordinate: buffer.get_scalar()?,
-			StoredSize: buffer.get_scalar()?
-		})
-	}
-}
-
-impl BlockRead for DirectoryEntryDV{
-	fn read(buffer: &mut BlockBuf) -> std::io::Result<Self>{
-		let SchemaType= buffer.get_ascii::<2>()?;
-		let PixelType = buffer.get_scalar()?;
-		let FilePosition = buffer.get_scalar()?;
-		let FilePart = buffer.get_scalar()?;
-		let Compression = buffer.get_scalar()?;
-		let PyramidType = buffer.get_scalar()?;//PyramidType, and 5 reserved bytes
-		let dimension_count:u32 = buffer.skip_to(28)?.get_scalar()?;
-		let dimension_map = buffer
-			.read_vec(dimension_count as usize)?
-			.into_iter()
-			.map(|de:DimensionEntryDV1|(de.Dimension.clone(),de))
-			.collect();
-		Ok(DirectoryEntryDV{SchemaType, PixelType, FilePosition, FilePart, Compression, PyramidType, dimension_map})
-	}
-}
-
-impl BlockRead for Directory{
-	fn read(buffer: &mut BlockBuf) -> std::io::Result<Self> where Self: Sized {
-		let EntryCount:i32 = buffer.get_scalar()?;
-		Ok(Directory{
-			Entries:buffer.skip_to(128)?.read_vec(EntryCount as usize)?
-		})
-	}
-}
-
 impl BlockRead for Attachment{
-	fn read(buffer: &mut BlockBuf) -> std::io::Result<Self> {
+	fn read(buffer: &mut BlockBuf) -> Result<Self> {
 		let data_size:u32 = buffer.get_scalar()?;
+		expect_schema(buffer.skip_to(16)?,"A1")?;
 		Ok(Attachment{
-			Entry:buffer.skip_to(16)?.read()?,
-			Data:buffer.skip_to(256)?.get_cached_data(data_size as usize)
-		})
-	}
-}
-
-impl BlockRead for AttachmentEntryA1{
-	fn read(buffer: &mut BlockBuf) -> std::io::Result<Self> {
-		let SchemaType = buffer.get_ascii::<2>()?;
-		buffer.skip_to(12).expect("Unexpected backward skip");
-		let FilePosition=buffer.get_scalar()?;
-		let FilePart=buffer.get_scalar()?;
-		let ContentGuid=Uuid::from_slice(buffer.drain(16)?.as_slice()).unwrap(); // impossible to fail
-		Ok(AttachmentEntryA1{
-			SchemaType, // todo implement support for attachments other than thumbnails
-			FilePosition, FilePart,	ContentGuid,
-			ContentFileType: buffer.get_ascii::<8>()?,
-			Name: buffer.get_ascii::<80>()?
+			Entry:buffer.read()?,
+			Data:buffer.skip_to(256)?.get_cached_data(size_from(data_size,"data size")?)?
 		})
 	}
 }
-
-impl BlockRead for AttachmentDirectory{
-	fn read(buffer: &mut BlockBuf) -> std::io::Result<Self> where Self: Sized {
-		let count:u32 = buffer.get_scalar()?;
-		buffer.skip_to(256).expect("Unexpected backward skip");
-		Ok(AttachmentDirectory {Entries:buffer.read_vec(count as usize)?})
-	}
-}
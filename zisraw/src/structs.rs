@@ -1,30 +1,39 @@
 #![allow(non_snake_case)]
 
 use uuid::Uuid;
-use iobase::DataFromFile;
+use std::collections::HashMap;
+use std::ops::Range;
+use iobase::blockbuf::BlockRead;
+use iobase::blockwrite::BlockWrite;
+use iobase::{DataFromFile, Payload};
 use xmltree;
 use crate::Result;
 use super::ZisrawInterface;
-use std::os::unix::fs::FileExt;
+use iobase::source::Source;
 use std::sync::Arc;
-use super::segment::{Segment,SegmentBlock};
-use std::io::{Error,ErrorKind::InvalidData};
+use super::segment::{Reader,Segment,SegmentBlock};
+use super::compression::Compression;
+use iobase::Error;
 
 
-#[derive(Debug)]
+#[derive(Debug,Clone,BlockRead,BlockWrite)]
 pub struct FileHeader{
 	pub version:[u32;2],
+	#[block(offset = 16, guid)]
 	pub PrimaryFileGuid:Uuid,
+	#[block(guid)]
 	pub FileGuid:Uuid,
 	pub FilePart:i32,
 	pub DirectoryPosition:u64,
 	pub MetadataPosition:u64,
+	#[block(with = "flag")]
 	pub UpdatePending:bool,
 	pub AttachmentDirectoryPosition:u64
 }
 
-#[derive(Debug)]
+#[derive(Debug,BlockRead,BlockWrite)]
 pub struct Directory{
+	#[block(count = "i32", elements_at = 128)]
 	pub Entries:Vec<DirectoryEntryDV>
 }
 
@@ -34,8 +43,9 @@ pub struct Metadata{
 	pub cache:iobase::basic::Cached<String,xmltree::Element>
 }
 
-#[derive(Debug)]
+#[derive(Debug,BlockRead,BlockWrite)]
 pub struct AttachmentDirectory{
+	#[block(count = "u32", elements_at = 256)]
 	pub Entries:Vec<AttachmentEntryA1>
 }
 
@@ -45,14 +55,19 @@ pub struct Attachment{
 	pub Data:DataFromFile
 }
 
-#[derive(Debug)]
+#[derive(Debug,Clone,BlockRead,BlockWrite)]
 pub struct AttachmentEntryA1{
-	pub SchemaType:String, //4 bytes
+	#[block(ascii = 2)]
+	pub SchemaType:String, // todo implement support for attachments other than thumbnails
+	#[block(offset = 12)]
 	pub FilePosition:u64,
 	pub FilePart:i32,
+	#[block(guid)]
 	pub ContentGuid:Uuid,
-	pub ContentFileType:String, //8 bytes
-	pub Name:String //80 bytes
+	#[block(ascii = 8)]
+	pub ContentFileType:String,
+	#[block(ascii = 80)]
+	pub Name:String
 }
 
 #[derive(Debug)]
@@ -63,109 +78,230 @@ pub struct SubBlock{
 	pub Attachment:Option<DataFromFile>
 }
 
-#[derive(Debug)]
+#[derive(Debug,Clone,BlockRead,BlockWrite)]
 pub struct DirectoryEntryDV{
-	pub SchemaType:String,//4 bytes
+	#[block(ascii = 2)]
+	pub SchemaType:String,
 	pub PixelType:i32,
 	pub FilePosition:u64,
 	pub FilePart:i32,
 	pub Compression:i32,
-	pub PyramidType:u8,
-	pub dimension_map:std::collections::HashMap<String,DimensionEntryDV1>,
+	pub PyramidType:u8, // followed by 5 reserved bytes
+	#[block(offset = 28, with = "dimensions")]
+	pub dimension_map:HashMap<String,DimensionEntryDV1>,
 }
 
-#[derive(Debug)]
+#[derive(Debug,Clone,BlockRead,BlockWrite)]
 pub struct DimensionEntryDV1{
-	pub Dimension:String,//read as [char;4]
+	#[block(ascii = 4)]
+	pub Dimension:String,
 	pub Start:i32,
 	pub Size:u32,
 	pub StartCoordinate:f32,
 	pub StoredSize:u32
 }
 
-// impl pyramid::Tile for DirectoryEntryDV{
-// 	fn frame(&self) -> Rect<i32, pyramid::PixelSpace> {
-// 		euclid::rect(
-// 			self.dimension_map["X"].Start,
-// 			self.dimension_map["Y"].Start,
-// 			self.dimension_map["X"].Size as i32,
-// 			self.dimension_map["Y"].Size as i32
-// 		)
-// 	}
-//
-// 	fn pixel(&self) -> pyramid::Pixel {
-// 		todo!()
-// 	}
-//
-// 	fn level(&self, scaling: i32) -> usize {
-// 		if self.PyramidType > 0{
-// 			assert!(scaling >1);
-// 			let scale= self.dimension_map["X"].Size / self.dimension_map["X"].StoredSize;
-// 			// todo Use feature(int_log)
-// 			((scale as f32).log10() / (scaling as f32).log10()) as usize
-// 		} else {
-// 			0
-// 		}
-// 	}
-//
-// 	fn ordering_id(&self) -> i32 {
-// 		self.dimension_map["M"].Start
-// 	}
-// }
-
-// impl Directory {
-// 	pub fn take_tiles(&mut self, scene: i32) -> Vec<Box<dyn pyramid::Tile>>{
-// 		let mut ret:Vec<Box<dyn pyramid::Tile>>=Vec::with_capacity(self.Entries.len());
-// 		let mut i = 0;
-// 		while i < self.Entries.len() {
-// 			if self.Entries[i].dimension_map["S"].Start == scene {
-// 				ret.push(Box::new(self.Entries.remove(i)));
-// 			} else {
-// 				i += 1;
-// 			}
-// 		}
-// 		// todo Use drain_filter once available
-// 		// for t in self.Entries.drain_filter(|ed|ed.dimension_map["S"].Start == scene){
-// 		// 	ret.push(Box::new(t));
-// 		// }
-// 		ret
-// 	}
-// }
+/// Pixel types as stored in DirectoryEntryDV::PixelType
+#[derive(Debug,Clone,Copy,PartialEq,Eq)]
+pub enum PixelType{
+	Gray8,
+	Gray16,
+	Gray32Float,
+	Bgr24,
+	Bgr48,
+	Bgr96Float,
+	Bgra32,
+	Gray64ComplexFloat,
+	Bgr192ComplexFloat,
+	Gray32,
+	Gray64
+}
 
-impl ZisrawInterface for FileHeader{
-	fn get_metadata(&self,file:&Arc<dyn FileExt>) -> Result<Metadata>{
-		let s = Segment::new(file, self.MetadataPosition)?;
+impl TryFrom<i32> for PixelType{
+	type Error = Error;
+	fn try_from(value: i32) -> std::result::Result<Self, Self::Error> {
+		Ok(match value {
+			0 => PixelType::Gray8,
+			1 => PixelType::Gray16,
+			2 => PixelType::Gray32Float,
+			3 => PixelType::Bgr24,
+			4 => PixelType::Bgr48,
+			8 => PixelType::Bgr96Float,
+			9 => PixelType::Bgra32,
+			10 => PixelType::Gray64ComplexFloat,
+			11 => PixelType::Bgr192ComplexFloat,
+			12 => PixelType::Gray32,
+			13 => PixelType::Gray64,
+			_ => return Err(Error::InvalidData(format!("Unknown pixel type {value}")))
+		})
+	}
+}
+
+impl From<PixelType> for i32{
+	fn from(value: PixelType) -> Self {
+		match value {
+			PixelType::Gray8 => 0,
+			PixelType::Gray16 => 1,
+			PixelType::Gray32Float => 2,
+			PixelType::Bgr24 => 3,
+			PixelType::Bgr48 => 4,
+			PixelType::Bgr96Float => 8,
+			PixelType::Bgra32 => 9,
+			PixelType::Gray64ComplexFloat => 10,
+			PixelType::Bgr192ComplexFloat => 11,
+			PixelType::Gray32 => 12,
+			PixelType::Gray64 => 13
+		}
+	}
+}
+
+impl PixelType {
+	/// size of one pixel in bytes (all channels)
+	pub fn bytes_per_pixel(&self) -> usize{
+		match self {
+			PixelType::Gray8 => 1,
+			PixelType::Gray16 => 2,
+			PixelType::Gray32Float | PixelType::Bgra32 | PixelType::Gray32 => 4,
+			PixelType::Bgr24 => 3,
+			PixelType::Bgr48 => 6,
+			PixelType::Gray64ComplexFloat | PixelType::Gray64 => 8,
+			PixelType::Bgr96Float => 12,
+			PixelType::Bgr192ComplexFloat => 24
+		}
+	}
+}
+
+impl FileHeader{
+	/// [ZisrawInterface::get_metadata] with the limits of reader
+	pub fn read_metadata(&self,reader:&Reader) -> Result<Metadata>{
+		let s = reader.segment(self.MetadataPosition)?;
 		if let SegmentBlock::Metadata(d) = s.block {
 			Ok(d)
 		} else {
-			Err(std::io::Error::new(std::io::ErrorKind::InvalidData,"Unexpected block when looking for metadata").into())
+			Err(s.unexpected("ZISRAWMETADATA"))
 		}
 	}
-	fn get_directory(&self,file:&Arc<dyn FileExt>) -> Result<Directory>{
-		let s:Segment = Segment::new(file, self.DirectoryPosition)?;
+	/// [ZisrawInterface::get_directory] with the limits of reader
+	pub fn read_directory(&self,reader:&Reader) -> Result<Directory>{
+		let s:Segment = reader.segment(self.DirectoryPosition)?;
 		if let SegmentBlock::Directory(d) = s.block {
 			Ok(d)
 		} else {
-			Err(Error::new(InvalidData,"Unexpected block when looking for directory").into())
+			Err(s.unexpected("ZISRAWDIRECTORY"))
 		}
 	}
-	fn get_attachments(&self,file:&Arc<dyn FileExt>)-> Result<Vec<AttachmentEntryA1>>{
-		let s:Segment = Segment::new(file, self.AttachmentDirectoryPosition)?;
+	/// [ZisrawInterface::get_attachments] with the limits of reader
+	pub fn read_attachments(&self,reader:&Reader)-> Result<Vec<AttachmentEntryA1>>{
+		if self.AttachmentDirectoryPosition == 0 { // no attachment directory => no attachments
+			return Ok(vec![]);
+		}
+		let s:Segment = reader.segment(self.AttachmentDirectoryPosition)?;
 		match s.block {
 			SegmentBlock::AttachmentDirectory(d) => Ok(d.Entries),
-			_ => Err(Error::new(InvalidData,"Unexpected block when looking for attachments").into())
+			_ => Err(s.unexpected("ZISRAWATTDIR"))
 		}
 	}
 }
 
+impl ZisrawInterface for FileHeader{
+	fn get_metadata(&self,file:&Arc<dyn Source>) -> Result<Metadata>{
+		self.read_metadata(&Reader::new(file)?)
+	}
+	fn get_directory(&self,file:&Arc<dyn Source>) -> Result<Directory>{
+		self.read_directory(&Reader::new(file)?)
+	}
+	fn get_attachments(&self,file:&Arc<dyn Source>)-> Result<Vec<AttachmentEntryA1>>{
+		self.read_attachments(&Reader::new(file)?)
+	}
+}
+
 impl Metadata {
-	pub fn as_tree(&mut self) -> Result<xmltree::Element> {
+	pub fn as_tree(&self) -> Result<xmltree::Element> {
 		match self.cache.get(){
 			Ok(elm) => elm // if the producer produced the data
 				.get_child("Metadata").cloned()// get the child, maybe
-				.ok_or(std::io::Error::new(std::io::ErrorKind::InvalidData,"\"Metadata\" missing in xml stream").into()), //if not return error
-			Err(e) => Err(e.into())
+				.ok_or(Error::Xml("\"Metadata\" missing in xml stream".to_string())), //if not return error
+			Err(e) => Err(e)
+		}
+	}
+}
+
+
+impl SubBlock {
+	/// Read the given rows of an uncompressed subblock without reading the rest of it.
+	///
+	/// - a row has the stored size of the X dimension, rows of all other dimensions follow each other
+	/// - compressed subblocks can only be decoded as a whole, so they return [Error::UnsupportedCompression]
+	pub fn read_rows(&self, rows:Range<u64>) -> Result<Payload<'_>>{
+		let compression = Compression::from(self.Entry.Compression);
+		if compression != Compression::Uncompressed {
+			return Err(Error::UnsupportedCompression(format!("{compression:?} (rows can only be read from uncompressed data)")));
+		}
+		let width = self.Entry.dimension_map.get("X")
+			.ok_or(Error::InvalidData("Subblock has no X dimension".to_string()))?
+			.StoredSize as u64;
+		let stride = width*PixelType::try_from(self.Entry.PixelType)?.bytes_per_pixel() as u64;
+		match (rows.start.checked_mul(stride),rows.end.checked_mul(stride)) {
+			(Some(start),Some(end)) => self.Data.read_range(start..end),
+			_ => Err(Error::InvalidData(format!("Rows {rows:?} are out of range")))
+		}
+	}
+}
+
+/// booleans stored as i32
+mod flag{
+	use iobase::blockbuf::BlockBuf;
+	use iobase::blockwrite::BlockWriter;
+	use crate::Result;
+
+	pub fn read(buffer:&mut BlockBuf) -> Result<bool>{
+		Ok(buffer.get_scalar::<i32>()? != 0)
+	}
+	pub fn write(value:&bool, buffer:&mut BlockWriter) -> Result<()>{
+		buffer.put_scalar(*value as i32);
+		Ok(())
+	}
+}
+
+/// the dimensions of a DirectoryEntryDV, stored as a counted list
+mod dimensions{
+	use std::collections::HashMap;
+	use iobase::blockbuf::BlockBuf;
+	use iobase::blockwrite::BlockWriter;
+	use crate::Result;
+	use crate::segment::size_from;
+	use super::DimensionEntryDV1;
+
+	/// canonical order of dimensions when writing, unknown dimensions go last
+	const DIMENSION_ORDER:&str = "XYCZTRSIHVBM";
+
+	pub fn read(buffer:&mut BlockBuf) -> Result<HashMap<String,DimensionEntryDV1>>{
+		let dimension_count:u32 = buffer.get_scalar()?;
+		Ok(buffer
+			.read_vec(size_from(dimension_count,"dimension count")?)?
+			.into_iter()
+			.map(|de:DimensionEntryDV1|(de.Dimension.clone(),de))
+			.collect())
+	}
+	pub fn write(map:&HashMap<String,DimensionEntryDV1>, buffer:&mut BlockWriter) -> Result<()>{
+		let mut dimensions:Vec<&DimensionEntryDV1> = map.values().collect();
+		dimensions.sort_by_key(|d|(DIMENSION_ORDER.find(d.Dimension.as_str()).unwrap_or(usize::MAX),d.Dimension.clone()));
+		buffer.put_scalar(dimensions.len() as u32);
+		for d in dimensions{
+			buffer.write(d)?;
 		}
+		Ok(())
 	}
 }
 
+// parsed structures are meant to be shared between threads (e.g. by request handlers)
+const _:() = {
+	const fn shareable<T:Send+Sync>(){}
+	shareable::<FileHeader>();
+	shareable::<Directory>();
+	shareable::<Metadata>();
+	shareable::<AttachmentDirectory>();
+	shareable::<Attachment>();
+	shareable::<SubBlock>();
+	shareable::<Segment>();
+};
@@ -0,0 +1,275 @@
+use std::collections::{BTreeMap, HashMap};
+use std::fs::File;
+use std::path::Path;
+use std::sync::Arc;
+use euclid::{Rect, Size2D};
+use iobase::Error;
+use iobase::source::Source;
+use ndarray::Array2;
+use num_complex::Complex;
+use pyramid::{Pixel, PixelSpace, PixelToReal, Plane, Pyramid, Tile};
+use uom::si::length::micrometer;
+use crate::{ImageInfo, Result, Scene, ZisrawInterface};
+use crate::utils::XmlUtil;
+use crate::compression::{decode, Compression};
+use crate::structs::*;
+use crate::segment::Reader;
+use crate::transcode::{read_subblock, readers};
+use crate::writer::FileWriter;
+
+/// A subblock as tile of a [Pyramid], its pixels are read from the file when needed.
+#[derive(Debug,Clone)]
+pub struct SubBlockTile{
+	pub entry:DirectoryEntryDV,
+	/// all parts of the image (see [crate::parts::open_parts])
+	parts:Arc<[Reader]>
+}
+
+impl SubBlockTile {
+	pub fn new(entry:DirectoryEntryDV, parts:&Arc<[Reader]>) -> Self{
+		SubBlockTile{entry, parts:parts.clone()}
+	}
+	/// Start, Size and StoredSize of a dimension, missing dimensions are treated as (0,1,1)
+	fn dimension(&self, name:&str) -> (i32,u32,u32){
+		self.entry.dimension_map.get(name).map_or((0,1,1),|d|(d.Start,d.Size,d.StoredSize))
+	}
+}
+
+impl Tile for SubBlockTile{
+	fn frame(&self) -> Rect<i32, PixelSpace> {
+		let (x,width,_) = self.dimension("X");
+		let (y,height,_) = self.dimension("Y");
+		euclid::rect(x,y,width as i32,height as i32)
+	}
+	fn stored_size(&self) -> Size2D<u32, PixelSpace> {
+		Size2D::new(self.dimension("X").2,self.dimension("Y").2)
+	}
+	fn plane(&self) -> Plane {
+		plane_of(&self.entry)
+	}
+	fn pixel(&self) -> Result<Pixel> {
+		let subblock = read_subblock(&self.parts,&self.entry)?;
+		let data = decode(Compression::from(self.entry.Compression),&subblock.Data.get()?)?;
+		let size = self.stored_size();
+		to_pixel(PixelType::try_from(self.entry.PixelType)?,size.width as usize,size.height as usize,&data)
+	}
+	fn ordering_id(&self) -> i32 {
+		self.dimension("M").0
+	}
+}
+
+/// Interpret decoded pixel data of the given type and size.
+///
+/// - the data must contain at least width*height pixels, anything after that is ignored
+pub fn to_pixel(pixel_type:PixelType, width:usize, height:usize, data:&[u8]) -> Result<Pixel>{
+	fn array<T>(width:usize, height:usize, data:&[u8], pixel_size:usize, f:impl Fn(&[u8])->T) -> Result<Array2<T>>{
+		let needed = width.checked_mul(height).and_then(|n|n.checked_mul(pixel_size))
+			.ok_or(Error::InvalidData(format!("Invalid tile size {width}x{height}")))?;
+		if data.len() < needed {
+			return Err(Error::InvalidData(format!("{} bytes of pixel data are too few for {width}x{height} pixels",data.len())));
+		}
+		let pixels = data[..needed].chunks_exact(pixel_size).map(f).collect();
+		Array2::from_shape_vec((height,width),pixels).map_err(|e|Error::InvalidData(e.to_string()))
+	}
+	let u16_at = |b:&[u8],i:usize|u16::from_le_bytes([b[i],b[i+1]]);
+	let f32_at = |b:&[u8],i:usize|f32::from_le_bytes([b[i],b[i+1],b[i+2],b[i+3]]);
+	let complex_at = |b:&[u8],i:usize|Complex::new(f32_at(b,i),f32_at(b,i+4));
+	let bytes_per_pixel = pixel_type.bytes_per_pixel();
+	Ok(match pixel_type {
+		PixelType::Gray8 => Pixel::Gray8(array(width,height,data,bytes_per_pixel,|b|b[0])?),
+		PixelType::Gray16 => Pixel::Gray16(array(width,height,data,bytes_per_pixel,|b|u16_at(b,0))?),
+		PixelType::Gray32 => Pixel::Gray32(array(width,height,data,bytes_per_pixel,|b|u32::from_le_bytes([b[0],b[1],b[2],b[3]]))?),
+		PixelType::Gray64 => Pixel::Gray64(array(width,height,data,bytes_per_pixel,|b|u64::from_le_bytes(b.try_into().unwrap()))?),
+		PixelType::Bgr24 => Pixel::Bgr24(array(width,height,data,bytes_per_pixel,|b|(b[0],b[1],b[2]))?),
+		PixelType::Bgr48 => Pixel::Bgr48(array(width,height,data,bytes_per_pixel,|b|(u16_at(b,0),u16_at(b,2),u16_at(b,4)))?),
+		PixelType::Bgra32 => Pixel::Bgra32(array(width,height,data,bytes_per_pixel,|b|(b[0],b[1],b[2],b[3]))?),
+		PixelType::Bgr96Float => Pixel::Bgr96Float(array(width,height,data,bytes_per_pixel,|b|(f32_at(b,0),f32_at(b,4),f32_at(b,8)))?),
+		PixelType::Gray32Float => Pixel::Gray32Float(array(width,height,data,bytes_per_pixel,|b|f32_at(b,0))?),
+		PixelType::Gray64ComplexFloat => Pixel::Gray64ComplexFloat(array(width,height,data,bytes_per_pixel,|b|complex_at(b,0))?),
+		PixelType::Bgr192ComplexFloat => Pixel::Bgr192ComplexFloat(array(width,height,data,bytes_per_pixel,|b|(complex_at(b,0),complex_at(b,8),complex_at(b,16)))?)
+	})
+}
+
+/// The pixels as raw data, as stored in an uncompressed subblock (the reverse of [to_pixel]).
+pub fn pixel_data(pixel:&Pixel) -> (PixelType,Vec<u8>){
+	fn bytes<T,const N:usize>(array:&Array2<T>, f:impl Fn(&T)->[u8;N]) -> Vec<u8>{
+		array.iter().flat_map(f).collect()
+	}
+	let complex = |c:&Complex<f32>|{
+		let (re,im) = (c.re.to_le_bytes(),c.im.to_le_bytes());
+		[re[0],re[1],re[2],re[3],im[0],im[1],im[2],im[3]]
+	};
+	match pixel {
+		Pixel::Gray8(a) => (PixelType::Gray8,bytes(a,|v|[*v])),
+		Pixel::Gray16(a) => (PixelType::Gray16,bytes(a,|v|v.to_le_bytes())),
+		Pixel::Gray32(a) => (PixelType::Gray32,bytes(a,|v|v.to_le_bytes())),
+		Pixel::Gray64(a) => (PixelType::Gray64,bytes(a,|v|v.to_le_bytes())),
+		Pixel::Bgr24(a) => (PixelType::Bgr24,bytes(a,|v|[v.0,v.1,v.2])),
+		Pixel::Bgr48(a) => (PixelType::Bgr48,a.iter().flat_map(|v|[v.0,v.1,v.2]).flat_map(u16::to_le_bytes).collect()),
+		Pixel::Bgra32(a) => (PixelType::Bgra32,bytes(a,|v|[v.0,v.1,v.2,v.3])),
+		Pixel::Bgr96Float(a) => (PixelType::Bgr96Float,a.iter().flat_map(|v|[v.0,v.1,v.2]).flat_map(f32::to_le_bytes).collect()),
+		Pixel::Gray32Float(a) => (PixelType::Gray32Float,bytes(a,|v|v.to_le_bytes())),
+		Pixel::Gray64ComplexFloat(a) => (PixelType::Gray64ComplexFloat,bytes(a,complex)),
+		Pixel::Bgr192ComplexFloat(a) => (PixelType::Bgr192ComplexFloat,a.iter().flat_map(|v|[v.0,v.1,v.2]).flat_map(|c|complex(&c)).collect())
+	}
+}
+
+/// the scene of a directory entry, entries without scene dimension belong to scene 0
+fn scene_of(entry:&DirectoryEntryDV) -> i32{
+	entry.dimension_map.get("S").map_or(0,|s|s.Start)
+}
+
+/// the plane of a directory entry, missing dimensions are treated as 0
+fn plane_of(entry:&DirectoryEntryDV) -> Plane{
+	let start = |name:&str|entry.dimension_map.get(name).map_or(0,|d|d.Start);
+	Plane{c:start("C"), z:start("Z"), t:start("T")}
+}
+
+/// The dimensions of the full resolution entries besides X, Y and M, by their scene and plane.
+///
+/// - fails if entries of the same plane differ in them (e.g. in H or R), as their tiles would be mixed up in a pyramid
+fn plane_dimensions(directory:&Directory) -> Result<HashMap<(i32,Plane),HashMap<String,DimensionEntryDV1>>>{
+	let mut ret:HashMap<(i32,Plane),HashMap<String,DimensionEntryDV1>> = HashMap::new();
+	for entry in directory.Entries.iter().filter(|e|e.PyramidType == 0) {
+		let dimensions:HashMap<String,DimensionEntryDV1> = entry.dimension_map.iter()
+			.filter(|(name,_)|!matches!(name.as_str(),"X"|"Y"|"M"))
+			.map(|(name,d)|(name.clone(),d.clone()))
+			.collect();
+		let key = (scene_of(entry),plane_of(entry));
+		match ret.get(&key) {
+			None => {ret.insert(key,dimensions);}
+			Some(known) => {
+				let same = known.len() == dimensions.len() && known.iter()
+					.all(|(name,d)|dimensions.get(name).is_some_and(|o|(o.Start,o.Size) == (d.Start,d.Size)));
+				if !same {
+					return Err(Error::InvalidData(format!("Tiles of {:?} in scene {} differ in their other dimensions",key.1,key.0)));
+				}
+			}
+		}
+	}
+	Ok(ret)
+}
+
+impl Directory {
+	/// Remove the entries of the given scene from the directory and return them as tiles.
+	pub fn take_tiles(&mut self, scene:i32, parts:&Arc<[Reader]>) -> Vec<Box<dyn Tile>>{
+		let (taken,kept):(Vec<_>,Vec<_>) = std::mem::take(&mut self.Entries).into_iter().partition(|e|scene_of(e) == scene);
+		self.Entries = kept;
+		taken.into_iter().map(|e|Box::new(SubBlockTile::new(e,parts)) as Box<dyn Tile>).collect()
+	}
+	/// Build one pyramid for each scene in the directory.
+	///
+	/// - levels are assigned with the MinificationFactor of the scene (see [crate::ImageInfo::scenes])
+	/// - scenes without (valid) pyramid information get their factor guessed from the tiles (see [Pyramid::new])
+	pub fn pyramids(mut self, parts:&Arc<[Reader]>, scenes:&[Scene]) -> BTreeMap<i32,Pyramid>{
+		let mut ret = BTreeMap::new();
+		while let Some(scene) = self.Entries.first().map(scene_of) {
+			let factor = scenes.iter().find(|s|s.Index == scene).map_or(0,|s|s.MinificationFactor);
+			ret.insert(scene,Pyramid::new(self.take_tiles(scene,parts),factor));
+		}
+		ret
+	}
+}
+
+/// The transform from pixels into stage coordinates (see [pyramid::real]).
+///
+/// - the pixel size is taken from the scaling of the image
+/// - the stage position is taken from the StageXPosition/StageYPosition tags (the position of the tile center)
+///   of the first full resolution subblock, without them (or without valid subblock metadata) pixel 0/0 is at stage position 0/0
+/// - returns None if the image has no scaling in X and Y
+pub fn stage_transform(parts:&Arc<[Reader]>, info:&ImageInfo, directory:&Directory) -> Result<Option<PixelToReal>>{
+	let (Some(x),Some(y)) = (info.pixel_size.get("x"),info.pixel_size.get("y")) else {return Ok(None)};
+	let pixel_size = Size2D::new(x.get::<micrometer>(),y.get::<micrometer>());
+	if !(pixel_size.width > 0.0 && pixel_size.height > 0.0) {
+		return Ok(None);
+	}
+	let mut origin = euclid::point2(0.0,0.0);
+	if let Some(entry) = directory.Entries.iter().find(|e|e.PyramidType == 0) {
+		let subblock = read_subblock(parts,entry)?;
+		// empty or invalid metadata is treated like missing stage positions
+		if let Ok(metadata) = subblock.Metadata.get() {
+			let position = |name:&str|metadata.drill_down(&["Tags",name]).and_then(XmlUtil::into::<f64>);
+			if let (Ok(sx),Ok(sy)) = (position("StageXPosition"),position("StageYPosition")) {
+				let center = SubBlockTile::new(entry.clone(),parts).frame().to_f64().center();
+				origin = euclid::point2(sx-center.x*pixel_size.width,sy-center.y*pixel_size.height);
+			}
+		}
+	}
+	Ok(Some(pyramid::real::pixel_to_real(pixel_size,origin)))
+}
+
+/// Build one pyramid for each scene of an image (see [Directory::pyramids]).
+///
+/// - files are all parts of the image, the first one being the primary file
+/// - the pyramids are placed in stage coordinates if the image has a scaling (see [stage_transform])
+pub fn scene_pyramids(files:&Arc<[Arc<dyn Source>]>) -> Result<BTreeMap<i32,Pyramid>>{
+	let parts:Arc<[Reader]> = readers(files)?.into();
+	let primary = parts.first().ok_or(Error::InvalidData("No file parts given".to_string()))?;
+	let header = crate::read_file_header(primary)?;
+	let info = header.get_image_info(primary.source())?;
+	let directory = header.read_directory(primary)?;
+	let transform = stage_transform(&parts,&info,&directory)?;
+	let pyramids = directory.pyramids(&parts,&info.scenes);
+	Ok(match transform {
+		Some(transform) => pyramids.into_iter().map(|(s,p)|(s,p.with_transform(transform))).collect(),
+		None => pyramids
+	})
+}
+
+/// Write the generated levels of the pyramids (see [Pyramid::generate]) into an image as pyramid subblocks.
+///
+/// - pyramids are given by their scene, as returned by [scene_pyramids]
+/// - the subblocks are appended uncompressed together with a new directory, the old one is marked as deleted
+/// - the subblocks keep the dimensions of the full resolution tiles of their plane besides X, Y and M (e.g. H or R)
+/// - returns the directory entries of the new subblocks
+pub fn persist_generated(path:&Path, pyramids:&BTreeMap<i32,Pyramid>) -> Result<Vec<DirectoryEntryDV>>{
+	let source:Arc<dyn Source> = Arc::new(File::open(path)?);
+	let hd = crate::get_file_header(&source)?;
+	let mut directory = hd.get_directory(&source)?;
+
+	let mut writer = FileWriter::append(path)?;
+	// flag the file as being updated until the new header is written
+	writer.write_header(&FileHeader{UpdatePending:true, ..hd.clone()})?;
+
+	let planes = plane_dimensions(&directory)?;
+	let mut written = vec![];
+	for (&scene,pyramid) in pyramids {
+		for level in pyramid.generated_levels() {
+			for tile in pyramid.level(level) {
+				let (pixel_type,data) = pixel_data(&tile.pixel()?);
+				let (frame,stored,plane) = (tile.frame(),tile.stored_size(),tile.plane());
+				let dimension = |name:&str,Start:i32,Size:u32,StoredSize:u32|
+					(name.to_string(),DimensionEntryDV1{Dimension:name.to_string(), Start, Size, StartCoordinate:0.0, StoredSize});
+				// everything but X, Y and M is taken from the full resolution tiles of the plane
+				let mut dimension_map = planes.get(&(scene,plane)).cloned().unwrap_or_else(||HashMap::from([
+					dimension("C",plane.c,1,1),
+					dimension("Z",plane.z,1,1),
+					dimension("T",plane.t,1,1),
+					dimension("S",scene,1,1)
+				]));
+				dimension_map.extend([
+					dimension("X",frame.origin.x,frame.size.width as u32,stored.width),
+					dimension("Y",frame.origin.y,frame.size.height as u32,stored.height),
+					dimension("M",tile.ordering_id(),1,1)
+				]);
+				let entry = DirectoryEntryDV{
+					SchemaType:"DV".to_string(),
+					PixelType:pixel_type.into(),
+					FilePosition:0,
+					FilePart:hd.FilePart,
+					Compression:Compression::Uncompressed.into(),
+					PyramidType:2,
+					dimension_map
+				};
+				written.push(writer.write_subblock(&entry,"",&data,&[])?);
+			}
+		}
+	}
+	directory.Entries.extend(written.iter().cloned());
+	let DirectoryPosition = writer.write_directory(&directory)?;
+	if hd.DirectoryPosition > 0 {
+		writer.mark_deleted(hd.DirectoryPosition)?;
+	}
+	writer.finish(&FileHeader{UpdatePending:false, DirectoryPosition, ..hd})?;
+	Ok(written)
+}
@@ -0,0 +1,182 @@
+use std::fs::File;
+use iobase::Error;
+use iobase::source::Source;
+use std::path::Path;
+use std::sync::Arc;
+use crate::Result;
+use crate::compression::{decode, encode_zstd, Compression};
+use crate::segment::{Reader, SegmentBlock};
+use crate::structs::*;
+use crate::writer::FileWriter;
+
+/// Options for [recompress]
+#[derive(Debug,Clone)]
+pub struct RecompressOptions{
+	/// zstd compression level
+	pub level:i32,
+	/// use hi/lo byte packing for 16bit pixel types (Gray16 and Bgr48)
+	pub hilo_packing:bool,
+	/// also recompress lzw compressed subblocks
+	pub lzw:bool,
+	/// compare the pixel data of the written file against the source
+	pub verify:bool
+}
+
+impl Default for RecompressOptions{
+	fn default() -> Self {
+		RecompressOptions{level:zstd::DEFAULT_COMPRESSION_LEVEL, hilo_packing:false, lzw:false, verify:true}
+	}
+}
+
+#[derive(Debug,Default)]
+pub struct RecompressReport{
+	/// number of subblocks that were recompressed
+	pub recompressed:usize,
+	/// number of subblocks that were copied as they are
+	pub copied:usize,
+	/// payload size of the recompressed subblocks before recompression
+	pub bytes_before:u64,
+	/// payload size of the recompressed subblocks after recompression
+	pub bytes_after:u64
+}
+
+/// select the file of a multi part image the entry points to
+pub(crate) fn part_of<T>(files:&[T], FilePart:i32) -> Result<&T>{
+	usize::try_from(FilePart).ok()
+		.and_then(|i|files.get(i))
+		.ok_or(Error::NotFound(format!("File part {FilePart}")))
+}
+
+/// a reader for each part, so their length is only queried once
+pub(crate) fn readers(files:&[Arc<dyn Source>]) -> Result<Vec<Reader>>{
+	files.iter().map(Reader::new).collect()
+}
+
+pub(crate) fn read_subblock(parts:&[Reader], entry:&DirectoryEntryDV) -> Result<SubBlock>{
+	let s = part_of(parts,entry.FilePart)?.segment(entry.FilePosition)?;
+	match s.block {
+		SegmentBlock::ImageSubBlock(s) => Ok(s),
+		_ => Err(s.unexpected("ZISRAWSUBBLOCK"))
+	}
+}
+
+pub(crate) fn read_attachment(parts:&[Reader], entry:&AttachmentEntryA1) -> Result<Attachment>{
+	let s = part_of(parts,entry.FilePart)?.segment(entry.FilePosition)?;
+	match s.block {
+		SegmentBlock::Attachment(a) => Ok(a),
+		_ => Err(s.unexpected("ZISRAWATTACH"))
+	}
+}
+
+/// Copy metadata, attachments and the directories of the (primary) source into writer.
+///
+/// - sources are the parts of the source image, ordered by their FilePart
+/// - Entries must be the already written subblock entries.
+/// - all attachments end up in the written file
+/// - returns the header to finish the written file with
+pub(crate) fn finish_copy(
+	sources:&[Arc<dyn Source>], hd:&FileHeader, writer:&mut FileWriter, Entries:Vec<DirectoryEntryDV>
+) -> Result<FileHeader>{
+	let parts = readers(sources)?;
+	let source = part_of(&parts,0)?;
+	let mut header = FileHeader{UpdatePending:false, ..hd.clone()};
+	header.MetadataPosition = if hd.MetadataPosition > 0 {
+		writer.write_metadata(&hd.read_metadata(source)?.cache.source)?
+	} else {0};
+	header.AttachmentDirectoryPosition = if hd.AttachmentDirectoryPosition > 0 {
+		let mut attachments = AttachmentDirectory{Entries:vec![]};
+		for entry in hd.read_attachments(source)? {
+			let att = read_attachment(&parts,&entry)?;
+			let entry = AttachmentEntryA1{FilePart:hd.FilePart, ..entry};
+			attachments.Entries.push(writer.write_attachment(&entry,&att.Data.get()?)?);
+		}
+		writer.write_attachment_directory(&attachments)?
+	} else {0};
+	header.DirectoryPosition = writer.write_directory(&Directory{Entries})?;
+	Ok(header)
+}
+
+fn check_single_part(hd:&FileHeader, directory:&Directory) -> Result<()>{
+	if hd.FilePart != 0 || directory.Entries.iter().any(|e|e.FilePart != 0) {
+		Err(Error::InvalidData("Files with multiple parts are not supported".to_string()))
+	} else {Ok(())}
+}
+
+/// Losslessly recompress all uncompressed (and optionally lzw compressed) subblocks of source with zstd into target.
+///
+/// Subblocks with any other compression, metadata and attachments are copied as they are.
+pub fn recompress(source:&Path, target:&Path, options:&RecompressOptions) -> Result<RecompressReport>{
+	let file:[Arc<dyn Source>;1] = [Arc::new(File::open(source)?)];
+	let parts = readers(&file)?;
+	let hd = crate::read_file_header(&parts[0])?;
+	let directory = hd.read_directory(&parts[0])?;
+	check_single_part(&hd,&directory)?;
+
+	let mut report = RecompressReport::default();
+	let mut writer = FileWriter::create(target)?;
+	let mut entries = Vec::with_capacity(directory.Entries.len());
+	for entry in &directory.Entries{
+		let subblock = read_subblock(&parts,entry)?;
+		let attachment = match subblock.Attachment.as_ref() {
+			Some(a) => a.get()?.to_vec(),
+			None => vec![]
+		};
+		let data = subblock.Data.get()?;
+		let compression = Compression::from(entry.Compression);
+		let convert = match compression {
+			Compression::Uncompressed => true,
+			Compression::Lzw => options.lzw,
+			_ => false
+		};
+		let written = if convert {
+			let pixel_type = PixelType::try_from(entry.PixelType)?;
+			let hilo = options.hilo_packing && matches!(pixel_type,PixelType::Gray16|PixelType::Bgr48);
+			let (compression,encoded) = encode_zstd(&decode(compression,&data)?,options.level,hilo)?;
+			report.recompressed += 1;
+			report.bytes_before += data.len() as u64;
+			report.bytes_after += encoded.len() as u64;
+			let entry = DirectoryEntryDV{Compression:compression.into(), ..entry.clone()};
+			writer.write_subblock(&entry,&subblock.Metadata.source,&encoded,&attachment)?
+		} else {
+			report.copied += 1;
+			writer.write_subblock(entry,&subblock.Metadata.source,&data,&attachment)?
+		};
+		entries.push(written);
+	}
+	let header = finish_copy(&file,&hd,&mut writer,entries)?;
+	writer.finish(&header)?;
+
+	if options.verify {
+		let written:Arc<dyn Source> = Arc::new(File::open(target)?);
+		verify_pixels(&file,&[written])?;
+	}
+	Ok(report)
+}
+
+/// Compare the pixel data of all subblocks of two images.
+///
+/// - a and b are the parts of each image, ordered by their FilePart (so a single file for single part images)
+/// - subblocks are matched by their order in the directory.
+/// - payloads with identical compression are compared as they are, everything else is decoded first.
+pub fn verify_pixels(a:&[Arc<dyn Source>], b:&[Arc<dyn Source>]) -> Result<()>{
+	let (a,b) = (readers(a)?,readers(b)?);
+	let (a0,b0) = (part_of(&a,0)?,part_of(&b,0)?);
+	let entries_a = crate::read_file_header(a0)?.read_directory(a0)?.Entries;
+	let entries_b = crate::read_file_header(b0)?.read_directory(b0)?.Entries;
+	if entries_a.len() != entries_b.len() {
+		return Err(Error::InvalidData(format!("Subblock count differs ({} vs. {})",entries_a.len(),entries_b.len())));
+	}
+	for (i,(ea,eb)) in entries_a.iter().zip(&entries_b).enumerate(){
+		let sa = read_subblock(&a,ea)?;
+		let sb = read_subblock(&b,eb)?;
+		let equal = if ea.Compression == eb.Compression {
+			sa.Data.get()? == sb.Data.get()?
+		} else {
+			decode(ea.Compression.into(),&sa.Data.get()?)? == decode(eb.Compression.into(),&sb.Data.get()?)?
+		};
+		if !equal {
+			return Err(Error::InvalidData(format!("Pixel data of subblock {i} differs")));
+		}
+	}
+	Ok(())
+}
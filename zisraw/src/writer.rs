@@ -0,0 +1,178 @@
+use std::fs::{File, OpenOptions};
+use std::os::unix::fs::FileExt;
+use std::path::Path;
+use std::sync::Arc;
+use iobase::blockwrite::BlockWriter;
+use iobase::source::Source;
+use iobase::Endian::Little;
+use uuid::Uuid;
+use crate::{Result, ZisrawInterface};
+use super::structs::*;
+
+/// size of the segment header (id, allocated size and used size)
+const SEGMENT_HEADER_SIZE:u64 = 32;
+/// space reserved for the data of the file header segment
+const FILE_HEADER_SIZE:u64 = 512;
+
+/// Writes a new zisraw file segment by segment.
+///
+/// Segments are appended to the end of the file, the file header is written last by [FileWriter::finish].
+pub struct FileWriter{
+	file:File,
+	end:u64
+}
+
+impl FileWriter{
+	/// Create a new file (truncating any existing one) and reserve space for the file header.
+	pub fn create(path:&Path) -> Result<Self>{
+		let file = File::create(path)?;
+		Ok(FileWriter{file, end:SEGMENT_HEADER_SIZE+FILE_HEADER_SIZE})
+	}
+	/// Open an existing file to append segments to it.
+	///
+	/// New segments will start at the (32 byte aligned) end of the file.
+	pub fn append(path:&Path) -> Result<Self>{
+		let file = OpenOptions::new().read(true).write(true).open(path)?;
+		let end = file.metadata()?.len().next_multiple_of(32);
+		Ok(FileWriter{file, end})
+	}
+	/// Append a segment with the given id and data.
+	///
+	/// - the allocated size will be rounded up to a multiple of 32
+	/// - returns the position of the segment in the file
+	pub fn write_segment(&mut self, id:&str, data:&[u8]) -> Result<u64>{
+		let pos = self.end;
+		let allocated = (data.len() as u64).next_multiple_of(32);
+		self.write_segment_at(pos, id, data, allocated)?;
+		self.end = pos + SEGMENT_HEADER_SIZE + allocated;
+		Ok(pos)
+	}
+	fn write_segment_at(&self, pos:u64, id:&str, data:&[u8], allocated:u64) -> Result<()>{
+		let mut header = BlockWriter::new(Little);
+		header.put_ascii::<16>(id)?
+			.put_scalar(allocated)
+			.put_scalar(data.len() as u64);
+		let mut buffer = header.into_inner();
+		buffer.extend_from_slice(data);
+		buffer.resize((SEGMENT_HEADER_SIZE+allocated) as usize,0);
+		self.file.write_all_at(&buffer,pos)?;
+		Ok(())
+	}
+	/// Append an image subblock.
+	///
+	/// Returns a copy of the entry with FilePosition pointing to the new subblock, ready to be put into a Directory.
+	pub fn write_subblock(&mut self, entry:&DirectoryEntryDV, metadata:&str, data:&[u8], attachment:&[u8]) -> Result<DirectoryEntryDV>{
+		let mut entry = entry.clone();
+		entry.FilePosition = self.end;
+
+		let mut buffer = BlockWriter::new(Little);
+		buffer.put_scalar(metadata.len() as u32)
+			.put_scalar(attachment.len() as u32)
+			.put_scalar(data.len() as u64)
+			.write(&entry)?;
+		if buffer.len() < 256 {
+			buffer.pad_to(256)?;
+		}
+		buffer.put_bytes(metadata.as_bytes())
+			.put_bytes(data)
+			.put_bytes(attachment);
+		self.write_segment("ZISRAWSUBBLOCK",buffer.into_inner().as_slice())?;
+		Ok(entry)
+	}
+	/// Append a metadata segment and return its position.
+	pub fn write_metadata(&mut self, xml:&str) -> Result<u64>{
+		let mut buffer = BlockWriter::new(Little);
+		buffer.put_scalar(xml.len() as i32)
+			.put_scalar(0_i32) //AttachmentSize
+			.pad_to(256)?
+			.put_bytes(xml.as_bytes());
+		self.write_segment("ZISRAWMETADATA",buffer.into_inner().as_slice())
+	}
+	/// Append a named attachment.
+	///
+	/// Returns a copy of the entry with FilePosition pointing to the new attachment, ready to be put into an AttachmentDirectory.
+	pub fn write_attachment(&mut self, entry:&AttachmentEntryA1, data:&[u8]) -> Result<AttachmentEntryA1>{
+		let mut entry = entry.clone();
+		entry.FilePosition = self.end;
+
+		let mut buffer = BlockWriter::new(Little);
+		buffer.put_scalar(data.len() as u32)
+			.pad_to(16)?
+			.write(&entry)?
+			.pad_to(256)?
+			.put_bytes(data);
+		self.write_segment("ZISRAWATTACH",buffer.into_inner().as_slice())?;
+		Ok(entry)
+	}
+	/// Append the subblock directory and return its position.
+	pub fn write_directory(&mut self, directory:&Directory) -> Result<u64>{
+		let mut buffer = BlockWriter::new(Little);
+		buffer.write(directory)?;
+		self.write_segment("ZISRAWDIRECTORY",buffer.into_inner().as_slice())
+	}
+	/// Append the attachment directory and return its position.
+	pub fn write_attachment_directory(&mut self, directory:&AttachmentDirectory) -> Result<u64>{
+		let mut buffer = BlockWriter::new(Little);
+		buffer.write(directory)?;
+		self.write_segment("ZISRAWATTDIR",buffer.into_inner().as_slice())
+	}
+	/// Mark the segment at the given position as deleted, so readers will skip it.
+	pub fn mark_deleted(&self, pos:u64) -> Result<()>{
+		let mut id = BlockWriter::new(Little);
+		id.put_ascii::<16>("DELETED")?;
+		self.file.write_all_at(id.into_inner().as_slice(),pos)?;
+		Ok(())
+	}
+	/// Write the file header segment at position 0.
+	pub fn write_header(&self, header:&FileHeader) -> Result<()>{
+		let mut buffer = BlockWriter::new(Little);
+		buffer.write(header)?;
+		self.write_segment_at(0,"ZISRAWFILE",buffer.into_inner().as_slice(),FILE_HEADER_SIZE)
+	}
+	/// Write the file header segment at position 0 and close the file.
+	///
+	/// The header must point to the directories and metadata written before.
+	pub fn finish(self, header:&FileHeader) -> Result<()>{
+		self.write_header(header)?;
+		self.file.sync_all()?;
+		Ok(())
+	}
+}
+
+/// Add a named attachment to an existing file, or replace all attachments with that name.
+///
+/// The data is appended as a new attachment segment together with a new attachment directory.
+/// Replaced attachments and the old directory are marked as deleted.
+/// Returns the directory entry of the new attachment.
+pub fn put_attachment(path:&Path, name:&str, content_file_type:&str, data:&[u8]) -> Result<AttachmentEntryA1>{
+	let source:Arc<dyn Source> = Arc::new(File::open(path)?);
+	let hd = crate::get_file_header(&source)?;
+	let mut entries = hd.get_attachments(&source)?;
+
+	let mut writer = FileWriter::append(path)?;
+	// flag the file as being updated until the new header is written
+	writer.write_header(&FileHeader{UpdatePending:true, ..hd.clone()})?;
+
+	let replaced:Vec<AttachmentEntryA1> = entries.iter().filter(|e|e.Name == name).cloned().collect();
+	entries.retain(|e|e.Name != name);
+	let entry = AttachmentEntryA1{
+		SchemaType: "A1".to_string(),
+		FilePosition: 0,
+		FilePart: hd.FilePart,
+		ContentGuid: replaced.first().map_or_else(Uuid::new_v4,|e|e.ContentGuid),
+		ContentFileType: content_file_type.to_string(),
+		Name: name.to_string()
+	};
+	let entry = writer.write_attachment(&entry,data)?;
+	entries.push(entry.clone());
+	let AttachmentDirectoryPosition = writer.write_attachment_directory(&AttachmentDirectory{Entries:entries})?;
+
+	for old in replaced.iter().filter(|e|e.FilePart == hd.FilePart) {
+		writer.mark_deleted(old.FilePosition)?;
+	}
+	if hd.AttachmentDirectoryPosition > 0 {
+		writer.mark_deleted(hd.AttachmentDirectoryPosition)?;
+	}
+	writer.finish(&FileHeader{UpdatePending:false, AttachmentDirectoryPosition, ..hd})?;
+	Ok(entry)
+}
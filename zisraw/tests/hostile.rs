@@ -0,0 +1,173 @@
+use std::path::Path;
+use std::sync::{Arc, OnceLock};
+use fixture::Fixture;
+use iobase::blockbuf::{BlockBuf, BlockRead, Limits};
+use iobase::source::Source;
+use iobase::{Endian, Error};
+use proptest::prelude::*;
+use zisraw::batch::{read_subblocks, BatchOptions};
+use zisraw::segment::{Reader, Segment, SegmentBlock};
+use zisraw::structs::*;
+use zisraw::ZisrawInterface;
+
+fn memory(bytes:Vec<u8>) -> Arc<dyn Source> {
+	Arc::new(bytes)
+}
+
+fn fixture_bytes() -> Vec<u8> {
+	static BYTES:OnceLock<Vec<u8>> = OnceLock::new();
+	BYTES.get_or_init(||{
+		let path = Path::new(env!("CARGO_TARGET_TMPDIR")).join("hostile.czi");
+		Fixture::new(32, 32).tiles(16, 16, 0).channels(2).thumbnail(b"thumb").write(&path).unwrap();
+		std::fs::read(path).unwrap()
+	}).clone()
+}
+
+/// a segment header with the given id and sizes followed by body
+fn segment(id:&str, allocated:u64, used:u64, body:&[u8]) -> Vec<u8> {
+	let mut ret = id.as_bytes().to_vec();
+	ret.resize(16, 0);
+	ret.extend(allocated.to_le_bytes());
+	ret.extend(used.to_le_bytes());
+	ret.extend(body);
+	ret
+}
+
+fn read_as<T:BlockRead>(bytes:&[u8]) -> iobase::Result<T> {
+	BlockBuf::new(memory(bytes.to_vec()), 0, Endian::Little)?.read()
+}
+
+/// read everything there is to read, ignoring all errors
+fn read_everything(file:&Arc<dyn Source>) {
+	let Ok(hd) = zisraw::get_file_header(file) else {return};
+	if let Ok(metadata) = hd.get_metadata(file) {
+		metadata.as_tree().ok();
+	}
+	hd.get_image_info(file).ok();
+	if let Ok(Some(thumbnail)) = hd.get_thumbnail(file) {
+		thumbnail.Data.get().ok();
+	}
+	for entry in hd.get_attachments(file).unwrap_or_default() {
+		Segment::new(file, entry.FilePosition).ok();
+	}
+	for entry in hd.get_directory(file).map(|d| d.Entries).unwrap_or_default() {
+		if let Ok(Segment{block:SegmentBlock::ImageSubBlock(s), ..}) = Segment::new(file, entry.FilePosition) {
+			s.Metadata.get().ok();
+			if let Ok(data) = s.Data.get() {
+				zisraw::compression::decode(entry.Compression.into(), &data).ok();
+			}
+		}
+	}
+}
+
+#[test]
+fn oversized_segments() {
+	let huge = segment("ZISRAWDIRECTORY", 1 << 40, 0, &[]);
+	let e = Segment::new(&memory(huge), 0).unwrap_err();
+	assert!(matches!(e.cause(), Error::LimitExceeded{..}), "{e}");
+
+	let overflow = segment("ZISRAWSUBBLOCK", u64::MAX - 8, 0, &[]);
+	assert!(Segment::new(&memory(overflow), 0).is_err());
+
+	let used = segment("ZISRAWMETADATA", 32, 64, &[0; 32]);
+	assert!(Segment::new(&memory(used), 0).is_err());
+}
+
+#[test]
+fn counts_beyond_the_segment() {
+	// a directory claiming i32::MAX entries in 128 bytes
+	let mut body = i32::MAX.to_le_bytes().to_vec();
+	body.resize(128, 0);
+	let e = Segment::new(&memory(segment("ZISRAWDIRECTORY", 128, 0, &body)), 0).unwrap_err();
+	assert_eq!(e.segment(), Some(("ZISRAWDIRECTORY", 0)));
+	assert!(matches!(e.cause(), Error::LimitExceeded{..}), "{e}");
+
+	// a subblock whose data goes beyond the segment
+	let mut body = vec![0; 16];
+	body[8..16].copy_from_slice(&1000u64.to_le_bytes());
+	body.extend(b"DV");
+	body.resize(256, 0);
+	let e = Segment::new(&memory(segment("ZISRAWSUBBLOCK", 256, 0, &body)), 0).unwrap_err();
+	assert!(matches!(e.cause(), Error::Truncated{..}), "{e}");
+
+	// an entry with an unknown schema
+	body[16..18].copy_from_slice(b"XX");
+	let e = Segment::new(&memory(segment("ZISRAWSUBBLOCK", 256, 0, &body)), 0).unwrap_err();
+	assert!(matches!(e.cause(), Error::BadMagic{found, ..} if found == "XX"), "{e}");
+}
+
+#[test]
+fn configurable_limits() {
+	let file = memory(fixture_bytes());
+	let hd = zisraw::get_file_header(&file).unwrap();
+	let limits = Limits{max_block_size:64, ..Default::default()};
+	let e = Segment::with_limits(&file, hd.DirectoryPosition, &limits).unwrap_err();
+	assert!(matches!(e.cause(), Error::LimitExceeded{value, ..} if *value > 64), "{e}");
+
+	let limits = Limits{max_elements:3, ..Default::default()};
+	let e = Segment::with_limits(&file, hd.DirectoryPosition, &limits).unwrap_err();
+	assert!(matches!(e.cause(), Error::LimitExceeded{value:8, ..}), "{e}");
+
+	// the limits of a reader reach every entry point
+	let reader = Reader::new(&file).unwrap().with_limits(limits.clone());
+	let hd = zisraw::read_file_header(&reader).unwrap();
+	let e = hd.read_directory(&reader).unwrap_err();
+	assert!(matches!(e.cause(), Error::LimitExceeded{value:8, ..}), "{e}");
+	hd.read_metadata(&reader).unwrap();
+
+	let tiny = Reader::new(&file).unwrap().with_limits(Limits{max_block_size:16, ..Default::default()});
+	assert!(matches!(zisraw::read_file_header(&tiny).unwrap_err().cause(), Error::LimitExceeded{..}));
+	assert!(matches!(hd.read_metadata(&tiny).unwrap_err().cause(), Error::LimitExceeded{..}));
+
+	let entries = hd.read_directory(&Reader::new(&file).unwrap()).unwrap().Entries;
+	let options = BatchOptions{limits:Limits{max_block_size:16, ..Default::default()}, ..Default::default()};
+	let e = read_subblocks(&file, &entries, &options).unwrap_err();
+	assert!(matches!(e.cause(), Error::LimitExceeded{..}), "{e}");
+}
+
+proptest! {
+	#[test]
+	fn segment_from_random_bytes(bytes in proptest::collection::vec(any::<u8>(), 0..2048)) {
+		Segment::new(&memory(bytes), 0).ok();
+	}
+
+	#[test]
+	fn segment_with_random_content(
+		id in proptest::sample::select(vec!["ZISRAWFILE","ZISRAWDIRECTORY","ZISRAWSUBBLOCK","ZISRAWMETADATA","ZISRAWATTACH","ZISRAWATTDIR","DELETED"]),
+		allocated in prop_oneof![0..4096u64, any::<u64>()],
+		used in prop_oneof![Just(0u64), any::<u64>()],
+		body in proptest::collection::vec(any::<u8>(), 0..4096)
+	) {
+		Segment::new(&memory(segment(id, allocated, used, &body)), 0).ok();
+	}
+
+	#[test]
+	fn block_reads_from_random_bytes(bytes in proptest::collection::vec(any::<u8>(), 0..1024)) {
+		read_as::<FileHeader>(&bytes).ok();
+		read_as::<Metadata>(&bytes).ok();
+		read_as::<SubBlock>(&bytes).ok();
+		read_as::<DimensionEntryDV1>(&bytes).ok();
+		read_as::<DirectoryEntryDV>(&bytes).ok();
+		read_as::<Directory>(&bytes).ok();
+		read_as::<Attachment>(&bytes).ok();
+		read_as::<AttachmentEntryA1>(&bytes).ok();
+		read_as::<AttachmentDirectory>(&bytes).ok();
+	}
+
+	#[test]
+	fn corrupted_files(changes in proptest::collection::vec((any::<prop::sample::Index>(), any::<u8>()), 1..16)) {
+		let mut bytes = fixture_bytes();
+		for (index, value) in changes {
+			let i = index.index(bytes.len());
+			bytes[i] = value;
+		}
+		read_everything(&memory(bytes));
+	}
+
+	#[test]
+	fn truncated_files(cut in any::<prop::sample::Index>()) {
+		let mut bytes = fixture_bytes();
+		bytes.truncate(cut.index(bytes.len()));
+		read_everything(&memory(bytes));
+	}
+}
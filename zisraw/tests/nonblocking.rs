@@ -0,0 +1,29 @@
+#![cfg(feature = "tokio")]
+use std::path::Path;
+use fixture::Fixture;
+use zisraw::batch::BatchOptions;
+use zisraw::compression::decode;
+use zisraw::nonblocking;
+
+#[tokio::test]
+async fn read_without_blocking() {
+	let path = Path::new(env!("CARGO_TARGET_TMPDIR")).join("nonblocking.czi");
+	let fixture = Fixture::new(32, 16).tiles(16, 16, 0).channels(2);
+	fixture.write(&path).unwrap();
+
+	let file = nonblocking::open(&path).await.unwrap();
+	let hd = nonblocking::get_file_header(&file).await.unwrap();
+	assert_eq!(hd.FileGuid, fixture.guid);
+	assert!(nonblocking::get_metadata(&file, &hd).await.unwrap().as_tree().is_ok());
+	let entries = nonblocking::get_directory(&file, &hd).await.unwrap().Entries;
+	assert_eq!(entries.len(), 4);
+
+	let subblock = nonblocking::read_subblock(&file, &entries[1]).await.unwrap();
+	let all = nonblocking::read_subblocks(&file, &entries, &BatchOptions::default()).await.unwrap();
+	assert_eq!(subblock.Data.get().unwrap(), all[1].Data.get().unwrap());
+	let pixels = decode(entries[1].Compression.into(), &subblock.Data.get().unwrap()).unwrap();
+	assert_eq!(pixels.len(), 16*16);
+
+	assert!(nonblocking::segment(&file, 1).await.is_err());
+	assert!(nonblocking::open(path.with_extension("missing")).await.unwrap_err().is_not_found());
+}
@@ -0,0 +1,127 @@
+use fixture::{open_parts, temp_path, Fixture};
+use pyramid::{Axis, Blend, Pixel, Plane, Projection};
+use zisraw::structs::PixelType;
+use zisraw::tiles::{persist_generated, scene_pyramids};
+
+#[test]
+fn pyramid_per_scene() {
+	let path = temp_path!("pyramid_per_scene.czi");
+	let fixture = Fixture::new(64, 64).tiles(32, 32, 0).channels(2).scenes(2).pyramid(2, 2);
+	fixture.write(&path).unwrap();
+
+	let pyramids = scene_pyramids(&open_parts(&[&path])).unwrap();
+	assert_eq!(pyramids.keys().copied().collect::<Vec<_>>(), [0, 1]);
+	let scene = &pyramids[&1];
+	assert_eq!(scene.levels(), 3);
+	assert_eq!(scene.planes().len(), 2);
+	assert_eq!(scene.frame(), Some(euclid::rect(128, 0, 64, 64)));
+	let plane = Plane{c:1, z:0, t:0};
+	for level in 0..3 {
+		assert_eq!(scene.level(level).len(), 8);
+		let ids:Vec<i32> = scene.tiles(level, plane).map(|t|t.ordering_id()).collect();
+		assert_eq!(ids, [0, 1, 2, 3]);
+	}
+
+	let found:Vec<_> = scene.intersecting(1, plane, euclid::rect(150, 40, 4, 4)).collect();
+	assert_eq!(found.len(), 1);
+	let tile = found[0];
+	assert_eq!(tile.frame(), euclid::rect(128, 32, 32, 32));
+	let Pixel::Gray8(pixels) = tile.pixel().unwrap() else {panic!("expected Gray8")};
+	assert_eq!(pixels.dim(), (16, 16));
+	assert_eq!(pixels[(3, 5)], Fixture::value(128 + 5 * 2, 32 + 3 * 2, 1, 0, 0) as u8);
+}
+
+#[test]
+fn minification_of_the_scene() {
+	let path = temp_path!("minification_of_the_scene.czi");
+	Fixture::new(100, 100).pyramid(2, 3).write(&path).unwrap();
+
+	let scene = &scene_pyramids(&open_parts(&[&path])).unwrap()[&0];
+	assert_eq!(scene.scaling_factor(), 3);
+	// stored sizes are rounded up, 34 and 12 pixels
+	assert_eq!(scene.levels(), 3);
+	assert_eq!(scene.level(2)[0].stored_size(), euclid::size2(12, 12));
+}
+
+#[test]
+fn generated_and_persisted() {
+	let path = temp_path!("generated_and_persisted.czi");
+	let fixture = Fixture::new(64, 64).tiles(32, 32, 0).extra_dimension("H", 3);
+	fixture.write(&path).unwrap();
+
+	let mut pyramids = scene_pyramids(&open_parts(&[&path])).unwrap();
+	let scene = pyramids.get_mut(&0).unwrap();
+	assert_eq!(scene.levels(), 1);
+	scene.generate(1);
+	let tile = &scene.level(1)[3];
+	assert_eq!(tile.frame(), euclid::rect(32, 32, 32, 32));
+	let generated = tile.pixel().unwrap();
+	let Pixel::Gray8(pixels) = &generated else {panic!("expected Gray8")};
+	let block:u32 = [(0, 0), (1, 0), (0, 1), (1, 1)].iter()
+		.map(|(dx, dy)|fixture.pixel_bytes(32 + 2*5 + dx, 32 + 2*7 + dy, 0, 0, 0)[0] as u32)
+		.sum();
+	assert_eq!(pixels[(7, 5)] as u32, (block + 2) / 4);
+
+	let written = persist_generated(&path, &pyramids).unwrap();
+	assert_eq!(written.len(), 4);
+	assert!(written.iter().all(|e|e.PyramidType == 2 && e.dimension_map["X"].StoredSize == 16));
+	assert!(written.iter().all(|e|e.dimension_map["H"].Start == 3 && e.dimension_map["S"].Start == 0));
+
+	let reopened = scene_pyramids(&open_parts(&[&path])).unwrap();
+	let scene = &reopened[&0];
+	assert_eq!(scene.levels(), 2);
+	assert!(scene.generated_levels().is_empty());
+	assert_eq!(scene.level(1)[3].pixel().unwrap(), generated);
+}
+
+#[test]
+fn placed_on_the_stage() {
+	let (a, b) = (temp_path!("placed_on_the_stage_a.czi"), temp_path!("placed_on_the_stage_b.czi"));
+	let fixture = Fixture::new(64, 64).tiles(32, 32, 0).scaling(0.5e-6);
+	fixture.clone().stage(1000.0, 2000.0).write(&a).unwrap();
+	fixture.stage(1016.0, 2000.0).write(&b).unwrap();
+
+	let a = &scene_pyramids(&open_parts(&[&a])).unwrap()[&0];
+	let b = &scene_pyramids(&open_parts(&[&b])).unwrap()[&0];
+	let frame = a.level(0)[1].frame();
+	assert_eq!(frame, euclid::rect(32, 0, 32, 32));
+	let real = a.to_real(frame).unwrap();
+	assert_eq!(real, euclid::rect(1016.0, 2000.0, 16.0, 16.0));
+	// the second image is shifted by 16µm (32 pixels), so its first tile is at the same place
+	assert_eq!(b.to_real(b.level(0)[0].frame()), Some(real));
+	assert_eq!(b.to_pixels(real), Some(euclid::rect(0, 0, 32, 32)));
+
+	// without stage positions pixel 0/0 is at 0/0
+	let c = temp_path!("placed_on_the_stage_c.czi");
+	Fixture::new(64, 64).write(&c).unwrap();
+	let c = &scene_pyramids(&open_parts(&[&c])).unwrap()[&0];
+	assert_eq!(c.to_real(euclid::rect(0, 0, 64, 64)), Some(euclid::rect(0.0, 0.0, 64.0, 64.0)));
+
+	// neither do subblocks with empty or invalid metadata
+	for (name, xml) in [("placed_on_the_stage_d.czi", ""), ("placed_on_the_stage_e.czi", "<METADATA><Tags>")] {
+		let d = temp_path!(name);
+		Fixture::new(64, 64).stage(1000.0, 2000.0).subblock_metadata(xml).write(&d).unwrap();
+		let d = &scene_pyramids(&open_parts(&[&d])).unwrap()[&0];
+		assert_eq!(d.to_real(euclid::rect(0, 0, 64, 64)), Some(euclid::rect(0.0, 0.0, 64.0, 64.0)));
+	}
+}
+
+#[test]
+fn projected_stack() {
+	let path = temp_path!("projected_stack.czi");
+	let fixture = Fixture::new(64, 64).tiles(32, 32, 0).channels(2).z(3).t(2).pixel_type(PixelType::Gray16);
+	fixture.write(&path).unwrap();
+
+	let scene = &scene_pyramids(&open_parts(&[&path])).unwrap()[&0];
+	let area = euclid::rect(20, 10, 30, 40);
+	let plane = Plane{c:1, z:0, t:1};
+	let value = |x:i32, y:i32, z:u32, t:u32|Fixture::value(x, y, 1, z, t) as u16;
+	let Some(Pixel::Gray16(max)) = scene.project(0, plane, Axis::Z, area, Projection::Max, Blend::LastWins).unwrap()
+		else {panic!("expected Gray16")};
+	assert_eq!(max.dim(), (40, 30));
+	assert_eq!(max[(5, 7)], value(27, 15, 2, 1));
+	let Some(Pixel::Gray32Float(mean)) = scene.project(0, plane, Axis::T, area, Projection::Mean, Blend::LastWins).unwrap()
+		else {panic!("expected Gray32Float")};
+	assert_eq!(mean[(39, 29)], (value(49, 49, 0, 0) as f32 + value(49, 49, 0, 1) as f32)/2.0);
+	assert_eq!(scene.project(0, Plane{c:5, z:0, t:0}, Axis::Z, area, Projection::Max, Blend::LastWins).unwrap(), None);
+}
@@ -0,0 +1,275 @@
+use std::fs::File;
+use std::io::{Read, Seek, SeekFrom};
+use std::sync::Arc;
+use std::sync::atomic::{AtomicUsize, Ordering};
+use fixture::{open, temp_path, Fixture};
+use iobase::Error;
+use iobase::source::{Mapped, ReadSeek, Source, SubRange};
+use uom::si::length::micrometer;
+use zisraw::batch::{read_subblocks, BatchOptions};
+use zisraw::compression::{decode, Compression};
+use zisraw::segment::{Segment, SegmentBlock};
+use zisraw::structs::{DirectoryEntryDV, PixelType};
+use zisraw::ZisrawInterface;
+
+fn pixels(file:&Arc<dyn Source>, entry:&DirectoryEntryDV) -> Vec<u8> {
+	match Segment::new(file, entry.FilePosition).unwrap().block {
+		SegmentBlock::ImageSubBlock(s) => decode(entry.Compression.into(), &s.Data.get().unwrap()).unwrap(),
+		b => panic!("unexpected block {b:?}")
+	}
+}
+
+#[test]
+fn header_and_metadata() {
+	let path = temp_path!("header_and_metadata.czi");
+	let fixture = Fixture::new(64, 32).channels(2).z(3).scenes(2).pyramid(1, 2);
+	fixture.write(&path).unwrap();
+
+	let file = open(&path);
+	let hd = zisraw::get_file_header(&file).unwrap();
+	assert_eq!(hd.FileGuid, fixture.guid);
+	assert_eq!(hd.PrimaryFileGuid, fixture.guid);
+	assert!(!hd.UpdatePending);
+
+	let info = hd.get_image_info(&file).unwrap();
+	assert_eq!(info.pixels, (64, 32, 3));
+	assert_eq!(info.pixel_type, "Gray8");
+	assert_eq!(info.scenes.len(), 2);
+	assert_eq!(info.scenes[1].RegionId, "1001");
+	assert_eq!(info.scenes[0].MinificationFactor, 2);
+	assert!((info.pixel_size["x"].get::<micrometer>() - 1.0).abs() < 1e-9);
+	assert_eq!(hd.get_timestamp(&file).unwrap().to_string().get(..19), Some("2022-09-01 12:00:00"));
+	assert_eq!(hd.AttachmentDirectoryPosition, 0);
+	assert!(hd.get_thumbnail(&file).unwrap().is_none());
+}
+
+#[test]
+fn directory_layout() {
+	let path = temp_path!("directory_layout.czi");
+	let fixture = Fixture::new(100, 50).tiles(40, 40, 10).channels(2).pyramid(1, 3);
+	fixture.write(&path).unwrap();
+
+	let file = open(&path);
+	let directory = zisraw::get_file_header(&file).unwrap().get_directory(&file).unwrap();
+	// 3x2 tiles on two levels for two channels
+	assert_eq!(directory.Entries.len(), 3 * 2 * 2 * 2);
+	let full: Vec<_> = directory.Entries.iter().filter(|e| e.PyramidType == 0).collect();
+	assert_eq!(full.len(), 12);
+	let last = &full[5].dimension_map;
+	assert_eq!((last["X"].Start, last["Y"].Start), (60, 30));
+	assert_eq!((last["X"].Size, last["Y"].Size), (40, 20));
+	assert_eq!(last["M"].Start, 5);
+	let reduced = directory.Entries.iter().find(|e| e.PyramidType != 0).unwrap();
+	assert_eq!((reduced.dimension_map["X"].Size, reduced.dimension_map["X"].StoredSize), (40, 14));
+}
+
+#[test]
+fn pixel_data() {
+	for (pixel_type, compression) in [
+		(PixelType::Gray8, Compression::Uncompressed),
+		(PixelType::Gray16, Compression::Zstd1),
+		(PixelType::Bgr48, Compression::Zstd0),
+		(PixelType::Gray32Float, Compression::Uncompressed),
+		(PixelType::Bgr192ComplexFloat, Compression::Zstd0),
+	] {
+		let path = temp_path!(&format!("pixel_data_{pixel_type:?}.czi"));
+		let fixture = Fixture::new(20, 10).t(2).pixel_type(pixel_type).compression(compression).pyramid(1, 2);
+		fixture.write(&path).unwrap();
+
+		let file = open(&path);
+		let directory = zisraw::get_file_header(&file).unwrap().get_directory(&file).unwrap();
+		for entry in &directory.Entries {
+			assert_eq!(entry.Compression, i32::from(compression));
+			let minification = if entry.PyramidType == 0 { 1 } else { 2 };
+			let t = entry.dimension_map["T"].Start as u32;
+			let (stored_x, stored_y) = (entry.dimension_map["X"].StoredSize, entry.dimension_map["Y"].StoredSize);
+			let expected: Vec<u8> = (0..stored_y).flat_map(|j| (0..stored_x).map(move |i| (i, j)))
+				.flat_map(|(i, j)| fixture.pixel_bytes((i * minification) as i32, (j * minification) as i32, 0, 0, t))
+				.collect();
+			assert_eq!(expected.len(), (stored_x * stored_y) as usize * pixel_type.bytes_per_pixel());
+			assert_eq!(pixels(&file, entry), expected, "{pixel_type:?} at level {minification}");
+		}
+	}
+}
+
+#[test]
+fn attachments() {
+	let path = temp_path!("attachments.czi");
+	Fixture::new(8, 8).thumbnail(b"not really a jpeg").attachment("Label", "PNG", b"label").write(&path).unwrap();
+
+	let file = open(&path);
+	let hd = zisraw::get_file_header(&file).unwrap();
+	let names: Vec<_> = hd.get_attachments(&file).unwrap().into_iter().map(|a| a.Name).collect();
+	assert_eq!(names, ["Thumbnail", "Label"]);
+	let thumbnail = hd.get_thumbnail(&file).unwrap().unwrap();
+	assert_eq!(thumbnail.Entry.ContentFileType, "JPG");
+	assert_eq!(thumbnail.Data.get().unwrap(), b"not really a jpeg");
+}
+
+#[test]
+fn errors_know_their_segment() {
+	let path = temp_path!("truncated.czi");
+	Fixture::new(16, 16).write(&path).unwrap();
+	let hd = zisraw::get_file_header(&open(&path)).unwrap();
+	// the directory is the last segment in the file
+	let len = std::fs::metadata(&path).unwrap().len();
+	File::options().write(true).open(&path).unwrap().set_len(len - 64).unwrap();
+
+	let e = hd.get_directory(&open(&path)).unwrap_err();
+	assert_eq!(e.segment(), Some(("ZISRAWDIRECTORY", hd.DirectoryPosition)));
+	assert!(matches!(e.cause(), Error::Truncated{..}), "{e}");
+
+	// a segment of the wrong type
+	let e = Segment::new(&open(&path), hd.MetadataPosition).unwrap().unexpected("ZISRAWDIRECTORY");
+	assert_eq!(e.segment(), Some(("ZISRAWMETADATA", hd.MetadataPosition)));
+	assert!(matches!(e.cause(), Error::BadMagic{expected, ..} if expected == "ZISRAWDIRECTORY"));
+
+	let garbage = temp_path!("garbage.czi");
+	std::fs::write(&garbage, [b'x'; 100]).unwrap();
+	let e = zisraw::get_file_header(&open(&garbage)).unwrap_err();
+	assert!(matches!(e.cause(), Error::BadMagic{found, ..} if found == "xxxxxxxxxxxxxxxx"), "{e}");
+}
+
+#[test]
+fn other_sources() {
+	let path = temp_path!("other_sources.czi");
+	let fixture = Fixture::new(16, 16).thumbnail(b"thumb");
+	fixture.write(&path).unwrap();
+	let bytes = std::fs::read(&path).unwrap();
+
+	// a file embedded in some container
+	let mut container = vec![0xAA; 1000];
+	container.extend(&bytes);
+	container.extend([0xBB; 1000]);
+	let sources:[Arc<dyn Source>;4] = [
+		Arc::new(bytes.clone()),
+		Arc::new(Mapped::open(&path).unwrap()),
+		Arc::new(ReadSeek::new(std::io::Cursor::new(bytes.clone()))),
+		Arc::new(SubRange::new(Arc::new(container), 1000, bytes.len() as u64)),
+	];
+	for file in &sources {
+		let hd = zisraw::get_file_header(file).unwrap();
+		assert_eq!(hd.FileGuid, fixture.guid);
+		let entry = &hd.get_directory(file).unwrap().Entries[0];
+		assert_eq!(pixels(file, entry)[..3], [0, 3, 6]);
+		assert_eq!(hd.get_thumbnail(file).unwrap().unwrap().Data.get().unwrap(), b"thumb");
+	}
+}
+
+#[test]
+fn shared_between_threads() {
+	let path = temp_path!("shared_between_threads.czi");
+	Fixture::new(32, 32).tiles(16, 16, 0).channels(2).write(&path).unwrap();
+	let file = open(&path);
+	let hd = zisraw::get_file_header(&file).unwrap();
+	let metadata = hd.get_metadata(&file).unwrap();
+	let subblocks:Vec<_> = hd.get_directory(&file).unwrap().Entries.iter()
+		.map(|e|match Segment::new(&file, e.FilePosition).unwrap().block {
+			SegmentBlock::ImageSubBlock(s) => s,
+			b => panic!("unexpected block {b:?}")
+		}).collect();
+	let expected:Vec<Vec<u8>> = subblocks.iter().map(|s|s.Data.get().unwrap().to_vec()).collect();
+
+	std::thread::scope(|scope| {
+		for _ in 0..4 {
+			scope.spawn(|| {
+				assert_eq!(hd.get_directory(&file).unwrap().Entries.len(), subblocks.len());
+				assert!(metadata.as_tree().unwrap().get_child("Information").is_some());
+				for (s, expected) in subblocks.iter().zip(&expected) {
+					assert_eq!(*s.Data.get().unwrap(), expected[..]);
+				}
+			});
+		}
+	});
+}
+
+#[test]
+fn ranged_reads() {
+	let path = temp_path!("ranged_reads.czi");
+	// a whole czi file embedded as attachment
+	let embedded = temp_path!("ranged_reads_embedded.czi");
+	let inner = Fixture::new(4, 4).thumbnail(b"inner");
+	inner.write(&embedded).unwrap();
+	Fixture::new(16, 8).pixel_type(PixelType::Gray16)
+		.attachment("Embedded", "CZI", &std::fs::read(&embedded).unwrap())
+		.write(&path).unwrap();
+
+	let file = open(&path);
+	let hd = zisraw::get_file_header(&file).unwrap();
+	let entry = &hd.get_directory(&file).unwrap().Entries[0];
+	let SegmentBlock::ImageSubBlock(mut subblock) = Segment::new(&file, entry.FilePosition).unwrap().block else {panic!()};
+	let whole = pixels(&file, entry);
+	let stride = 16*2;
+	assert_eq!(*subblock.read_rows(2..5).unwrap(), whole[2*stride..5*stride]);
+	assert_eq!(*subblock.Data.read_range(3..7).unwrap(), whole[3..7]);
+	assert!(subblock.read_rows(7..9).is_err());
+	assert!(!subblock.Data.is_cached());
+
+	// Read and Seek
+	let mut row = vec![0; stride];
+	subblock.Data.seek(SeekFrom::Start(stride as u64)).unwrap();
+	subblock.Data.read_exact(&mut row).unwrap();
+	assert_eq!(row, whole[stride..2*stride]);
+	subblock.Data.seek(SeekFrom::End(-4)).unwrap();
+	let mut rest = vec![];
+	subblock.Data.read_to_end(&mut rest).unwrap();
+	assert_eq!(rest, whole[whole.len()-4..]);
+	assert!(subblock.Data.seek(SeekFrom::Current(-1000)).is_err());
+	assert!(!subblock.Data.is_cached());
+
+	// the embedded file can be read without copying it out
+	let attachment = hd.get_attachments(&file).unwrap().into_iter().find(|a|a.Name == "Embedded").unwrap();
+	let SegmentBlock::Attachment(attachment) = Segment::new(&file, attachment.FilePosition).unwrap().block else {panic!()};
+	let embedded:Arc<dyn Source> = Arc::new(attachment.Data);
+	let inner_hd = zisraw::get_file_header(&embedded).unwrap();
+	assert_eq!(inner_hd.FileGuid, inner.guid);
+	assert_eq!(inner_hd.get_thumbnail(&embedded).unwrap().unwrap().Data.get().unwrap(), b"inner");
+}
+
+/// a source in memory that counts how often it is read from
+struct CountingSource(Vec<u8>, AtomicUsize);
+
+impl Source for CountingSource {
+	fn read_at(&self, buf:&mut [u8], pos:u64) -> std::io::Result<usize> {
+		self.1.fetch_add(1, Ordering::Relaxed);
+		self.0.read_at(buf, pos)
+	}
+	fn len(&self) -> std::io::Result<u64> {Source::len(&self.0)}
+}
+
+#[test]
+fn batch_reads() {
+	let path = temp_path!("batch_reads.czi");
+	Fixture::new(64, 64).tiles(16, 16, 0).channels(2).write(&path).unwrap();
+	let counting = Arc::new(CountingSource(std::fs::read(&path).unwrap(), AtomicUsize::new(0)));
+	let file:Arc<dyn Source> = counting.clone();
+	let hd = zisraw::get_file_header(&file).unwrap();
+	// reverse order and a duplicate, the result has to follow the entries
+	let mut entries = hd.get_directory(&file).unwrap().Entries;
+	entries.reverse();
+	entries.push(entries[3].clone());
+	assert_eq!(entries.len(), 33);
+
+	counting.1.store(0, Ordering::Relaxed);
+	let subblocks = read_subblocks(&file, &entries, &BatchOptions::default()).unwrap();
+	assert_eq!(counting.1.load(Ordering::Relaxed), 1);
+	for (entry, subblock) in entries.iter().zip(&subblocks) {
+		assert_eq!(subblock.Entry.FilePosition, entry.FilePosition);
+		assert_eq!(decode(entry.Compression.into(), &subblock.Data.get().unwrap()).unwrap(), pixels(&file, entry));
+	}
+
+	// a small tail needs a second read to complete the last segment, no merging reads each on its own
+	let tiny = BatchOptions{tail:8, ..Default::default()};
+	counting.1.store(0, Ordering::Relaxed);
+	read_subblocks(&file, &entries, &tiny).unwrap();
+	assert_eq!(counting.1.load(Ordering::Relaxed), 2);
+	let single = BatchOptions{max_distance:0, ..Default::default()};
+	counting.1.store(0, Ordering::Relaxed);
+	read_subblocks(&file, &entries, &single).unwrap();
+	assert_eq!(counting.1.load(Ordering::Relaxed), 32);
+
+	assert!(read_subblocks(&file, &[], &BatchOptions::default()).unwrap().is_empty());
+	let mut wrong = entries[0].clone();
+	wrong.FilePosition = hd.DirectoryPosition;
+	assert!(read_subblocks(&file, &[wrong], &BatchOptions::default()).is_err());
+}
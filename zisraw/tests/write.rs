@@ -0,0 +1,95 @@
+use fixture::{open, temp_path, Fixture};
+use zisraw::compression::Compression;
+use zisraw::segment::{Segment, SegmentBlock};
+use zisraw::structs::PixelType;
+use zisraw::transcode::RecompressOptions;
+use zisraw::ZisrawInterface;
+
+#[test]
+fn recompress() {
+	let source = temp_path!("recompress_source.czi");
+	Fixture::new(64, 64).tiles(32, 32, 0).pixel_type(PixelType::Gray16).thumbnail(b"thumb").write(&source).unwrap();
+
+	for hilo_packing in [false, true] {
+		let target = temp_path!(&format!("recompress_target_{hilo_packing}.czi"));
+		let options = RecompressOptions { hilo_packing, ..Default::default() };
+		let report = zisraw::transcode::recompress(&source, &target, &options).unwrap();
+		assert_eq!((report.recompressed, report.copied), (4, 0));
+		assert!(report.bytes_after < report.bytes_before);
+
+		let file = open(&target);
+		let hd = zisraw::get_file_header(&file).unwrap();
+		let expected = if hilo_packing { Compression::Zstd1 } else { Compression::Zstd0 };
+		assert!(hd.get_directory(&file).unwrap().Entries.iter().all(|e| e.Compression == expected.into()));
+		assert!(hd.get_thumbnail(&file).unwrap().is_some());
+		let source_file = open(&source);
+		let source_hd = zisraw::get_file_header(&source_file).unwrap();
+		assert_eq!(hd.get_metadata_xml(&file).unwrap(), source_hd.get_metadata_xml(&source_file).unwrap());
+	}
+}
+
+#[test]
+fn recompress_copies_compressed_subblocks() {
+	let source = temp_path!("recompress_copy_source.czi");
+	let target = temp_path!("recompress_copy_target.czi");
+	Fixture::new(16, 16).compression(Compression::Zstd0).write(&source).unwrap();
+
+	let report = zisraw::transcode::recompress(&source, &target, &RecompressOptions::default()).unwrap();
+	assert_eq!((report.recompressed, report.copied), (0, 1));
+}
+
+#[test]
+fn split_and_merge() {
+	let source = temp_path!("split_source.czi");
+	let merged = temp_path!("split_merged.czi");
+	Fixture::new(64, 64).tiles(16, 16, 0).thumbnail(b"thumb").write(&source).unwrap();
+
+	let parts = zisraw::parts::part_file_names(&temp_path!("split.czi"), 3);
+	assert_eq!(parts[2].file_name().unwrap(), "split(2).czi");
+	zisraw::parts::split(&source, &parts).unwrap();
+
+	let headers: Vec<_> = parts.iter().map(|p| zisraw::get_file_header(&open(p)).unwrap()).collect();
+	assert!(headers.iter().all(|h| h.PrimaryFileGuid == headers[0].FileGuid));
+	assert_eq!(headers.iter().map(|h| h.FilePart).collect::<Vec<_>>(), [0, 1, 2]);
+	let primary = open(&parts[0]);
+	let entries = headers[0].get_directory(&primary).unwrap().Entries;
+	assert_eq!(entries.iter().map(|e| e.FilePart).max(), Some(2));
+
+	// parts in any order
+	zisraw::parts::merge(&[parts[2].clone(), parts[0].clone(), parts[1].clone()], &merged).unwrap();
+	assert_eq!(std::fs::read(&source).unwrap(), std::fs::read(&merged).unwrap());
+}
+
+#[test]
+fn merge_rejects_incomplete_parts() {
+	let source = temp_path!("incomplete_source.czi");
+	Fixture::new(32, 32).tiles(16, 16, 0).write(&source).unwrap();
+	let parts = zisraw::parts::part_file_names(&temp_path!("incomplete.czi"), 2);
+	zisraw::parts::split(&source, &parts).unwrap();
+
+	assert!(zisraw::parts::merge(&parts[1..], &temp_path!("incomplete_merged.czi")).is_err());
+}
+
+#[test]
+fn put_attachment() {
+	let path = temp_path!("put_attachment.czi");
+	Fixture::new(8, 8).attachment("Label", "PNG", b"label").write(&path).unwrap();
+
+	let first = zisraw::writer::put_attachment(&path, "Thumbnail", "JPG", b"first").unwrap();
+	let old_directory = zisraw::get_file_header(&open(&path)).unwrap().AttachmentDirectoryPosition;
+	zisraw::writer::put_attachment(&path, "Thumbnail", "JPG", b"second").unwrap();
+
+	let file = open(&path);
+	let hd = zisraw::get_file_header(&file).unwrap();
+	assert!(!hd.UpdatePending);
+	// the replaced attachment and the old directory are marked as deleted
+	assert_ne!(hd.AttachmentDirectoryPosition, old_directory);
+	for position in [old_directory, first.FilePosition] {
+		let segment = Segment::new(&file, position).unwrap();
+		assert!(matches!(segment.block, SegmentBlock::Deleted));
+	}
+	let names: Vec<_> = hd.get_attachments(&file).unwrap().into_iter().map(|a| a.Name).collect();
+	assert_eq!(names, ["Label", "Thumbnail"]);
+	let thumbnail = hd.get_thumbnail(&file).unwrap().unwrap();
+	assert_eq!(thumbnail.Data.get().unwrap(), b"second");
+}